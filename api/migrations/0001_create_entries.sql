@@ -0,0 +1,7 @@
+-- Initial schema. Databases created from the old 'create-tables.sql' already have this
+-- table, which is why it's created conditionally.
+CREATE TABLE IF NOT EXISTS entries (
+  id BIGSERIAL PRIMARY KEY,
+  namespace VARCHAR(64) NOT NULL,
+  content JSONB NOT NULL
+);
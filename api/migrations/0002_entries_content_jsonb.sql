@@ -0,0 +1,45 @@
+-- Converts 'entries.content' from TEXT to JSONB in place, for databases which were
+-- created before content was stored as JSONB.
+--
+-- Rows which can't be parsed as JSON don't abort the migration. Instead, each of them
+-- is reported with a warning and moved into 'entries_invalid_content' together with the
+-- parsing error, so it can be inspected (and fixed/re-inserted) later. On databases
+-- which already store JSONB (e.g. freshly created ones) this migration does nothing.
+DO $$
+DECLARE
+  rec     RECORD;
+  invalid BIGINT := 0;
+BEGIN
+  IF (SELECT data_type FROM information_schema.columns
+      WHERE table_name = 'entries' AND column_name = 'content') <> 'text' THEN
+    RAISE NOTICE 'Column entries.content is not TEXT, nothing to migrate.';
+    RETURN;
+  END IF;
+
+  CREATE TABLE IF NOT EXISTS entries_invalid_content (
+    id BIGINT PRIMARY KEY,
+    namespace VARCHAR(64) NOT NULL,
+    content TEXT NOT NULL,
+    error TEXT NOT NULL,
+    moved_at TIMESTAMPTZ NOT NULL DEFAULT now()
+  );
+
+  FOR rec IN SELECT id, namespace, content FROM entries ORDER BY id LOOP
+    BEGIN
+      PERFORM rec.content::jsonb;
+    EXCEPTION WHEN others THEN
+      RAISE WARNING 'Entry % (namespace ''%'') is not valid JSON: %', rec.id, rec.namespace, SQLERRM;
+      INSERT INTO entries_invalid_content (id, namespace, content, error)
+        VALUES (rec.id, rec.namespace, rec.content, SQLERRM);
+      DELETE FROM entries WHERE id = rec.id;
+      invalid := invalid + 1;
+    END;
+  END LOOP;
+
+  ALTER TABLE entries ALTER COLUMN content TYPE JSONB USING content::jsonb;
+
+  IF invalid > 0 THEN
+    RAISE WARNING '% entries failed to parse and were moved to entries_invalid_content.', invalid;
+  END IF;
+END
+$$;
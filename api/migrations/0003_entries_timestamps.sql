@@ -0,0 +1,8 @@
+-- Server-assigned creation and last update time of every entry. Entries which existed
+-- before this migration get the time of the migration for both values.
+ALTER TABLE entries
+  ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE INDEX entries_namespace_created_at_idx ON entries (namespace, created_at);
+CREATE INDEX entries_namespace_updated_at_idx ON entries (namespace, updated_at);
@@ -0,0 +1,11 @@
+-- API keys are never stored in plain text, only SHA-256 of the key is kept. Grants are a JSON
+-- array of {"namespace": "<name or prefix*>", "scopes": ["read", "write", "delete", "admin"]}.
+CREATE TABLE IF NOT EXISTS api_keys (
+  id BIGSERIAL PRIMARY KEY,
+  name VARCHAR(64) NOT NULL,
+  prefix VARCHAR(16) NOT NULL,
+  key_hash CHAR(64) NOT NULL UNIQUE,
+  grants JSONB NOT NULL DEFAULT '[]',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  revoked_at TIMESTAMPTZ
+);
@@ -0,0 +1,6 @@
+-- Optional JSON Schema of the namespace, which every written entry must conform to.
+CREATE TABLE IF NOT EXISTS namespace_schemas (
+  namespace VARCHAR(64) PRIMARY KEY,
+  schema JSONB NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
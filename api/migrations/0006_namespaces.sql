@@ -0,0 +1,13 @@
+-- Optional metadata of namespaces. Namespaces still come into existence with their first
+-- entry, so this table only has rows for namespaces which were described by someone.
+CREATE TABLE IF NOT EXISTS namespaces (
+  name VARCHAR(64) PRIMARY KEY,
+  description TEXT,
+  owner VARCHAR(64),
+  tags JSONB NOT NULL DEFAULT '[]',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Listing groups entries by namespace, which is much cheaper with this index.
+CREATE INDEX IF NOT EXISTS entries_namespace_id_idx ON entries (namespace, id);
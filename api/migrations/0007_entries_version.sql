@@ -0,0 +1,7 @@
+-- Version of the entry, which changes on every write and is exposed as ETag. Versions come
+-- from a single sequence, so an entry which was deleted and then created again with the same
+-- ID never reuses a version of the old one.
+CREATE SEQUENCE IF NOT EXISTS entries_version_seq;
+
+ALTER TABLE entries
+  ADD COLUMN version BIGINT NOT NULL DEFAULT nextval('entries_version_seq');
@@ -0,0 +1,13 @@
+-- Audit log of entries which were explicitly moved to another namespace. Entries are never
+-- moved by PUT, only by the move endpoint. Name of the API key is kept (not its ID), so the
+-- log stays readable after keys are revoked; it's NULL when keys are not required.
+CREATE TABLE IF NOT EXISTS entry_moves (
+  id BIGSERIAL PRIMARY KEY,
+  entry_id BIGINT NOT NULL,
+  from_namespace VARCHAR(64) NOT NULL,
+  to_namespace VARCHAR(64) NOT NULL,
+  moved_by VARCHAR(64),
+  moved_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS entry_moves_entry_id_idx ON entry_moves (entry_id);
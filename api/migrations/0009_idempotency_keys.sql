@@ -0,0 +1,13 @@
+-- Responses of successful create requests, which were sent with "Idempotency-Key" header, so
+-- retries of the same request replay them instead of creating duplicates. Keys are scoped by
+-- namespace and expire after the configured window.
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+  namespace VARCHAR(64) NOT NULL,
+  key VARCHAR(255) NOT NULL,
+  request_hash CHAR(64) NOT NULL,
+  response JSONB,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (namespace, key)
+);
+
+CREATE INDEX IF NOT EXISTS idempotency_keys_created_at_idx ON idempotency_keys (created_at);
@@ -0,0 +1,39 @@
+-- Every change of an entry is announced on 'entry_changes' channel, so all API replicas (and
+-- anything else listening) learn about writes, including ones made directly in SQL. Payload is
+-- limited to 8000 bytes, so content is only included when it fits, otherwise listeners read it.
+-- Notifications are delivered on commit, in commit order, and never for rolled back writes.
+CREATE OR REPLACE FUNCTION entry_change_payload(kind TEXT, entry entries) RETURNS TEXT AS $$
+DECLARE
+  payload TEXT;
+BEGIN
+  IF kind <> 'deleted' THEN
+    payload := json_build_object('kind', kind, 'namespace', entry.namespace, 'id', entry.id, 'content', entry.content)::text;
+    IF octet_length(payload) < 7900 THEN
+      RETURN payload;
+    END IF;
+  END IF;
+  RETURN json_build_object('kind', kind, 'namespace', entry.namespace, 'id', entry.id)::text;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_entry_change() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    PERFORM pg_notify('entry_changes', entry_change_payload('created', NEW));
+  ELSIF TG_OP = 'DELETE' THEN
+    PERFORM pg_notify('entry_changes', entry_change_payload('deleted', OLD));
+  ELSIF OLD.namespace <> NEW.namespace THEN
+    -- Entry which was moved leaves one namespace and appears in another one.
+    PERFORM pg_notify('entry_changes', entry_change_payload('deleted', OLD));
+    PERFORM pg_notify('entry_changes', entry_change_payload('created', NEW));
+  ELSE
+    PERFORM pg_notify('entry_changes', entry_change_payload('updated', NEW));
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS entries_notify_change ON entries;
+CREATE TRIGGER entries_notify_change
+  AFTER INSERT OR UPDATE OR DELETE ON entries
+  FOR EACH ROW EXECUTE FUNCTION notify_entry_change();
@@ -0,0 +1,65 @@
+-- Webhooks of a namespace. Secret is kept as is, since it's needed to sign payloads. Events is
+-- a JSON array of change kinds to deliver, and filter is the same structured filter as listing
+-- uses (it's checked on content by the dispatcher).
+CREATE TABLE IF NOT EXISTS webhooks (
+  id BIGSERIAL PRIMARY KEY,
+  namespace VARCHAR(64) NOT NULL,
+  url TEXT NOT NULL,
+  secret TEXT NOT NULL,
+  events JSONB NOT NULL DEFAULT '["created", "updated", "deleted"]',
+  filter JSONB NOT NULL DEFAULT '{}',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS webhooks_namespace_idx ON webhooks (namespace);
+
+-- Queue and log of deliveries. Status is 'pending' until delivery succeeds ('delivered') or
+-- runs out of attempts ('dead'). Pending deliveries are claimed by pushing next_attempt_at
+-- forward, so multiple replicas never send the same attempt.
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+  id BIGSERIAL PRIMARY KEY,
+  webhook_id BIGINT NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+  event JSONB NOT NULL,
+  status VARCHAR(16) NOT NULL DEFAULT 'pending',
+  attempts INTEGER NOT NULL DEFAULT 0,
+  next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  last_status INTEGER,
+  last_error TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, id);
+CREATE INDEX IF NOT EXISTS webhook_deliveries_pending_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+
+-- Deliveries are queued in the same transaction as the change, so none is lost or sent for a
+-- rolled back write, whichever replica (or SQL client) made it.
+CREATE OR REPLACE FUNCTION enqueue_webhook_deliveries(kind TEXT, entry entries) RETURNS void AS $$
+  INSERT INTO webhook_deliveries (webhook_id, event)
+  SELECT id, jsonb_build_object('kind', kind, 'namespace', entry.namespace, 'id', entry.id)
+    || CASE WHEN kind = 'deleted' THEN '{}'::jsonb ELSE jsonb_build_object('content', entry.content) END
+  FROM webhooks
+  WHERE namespace = entry.namespace AND events ? kind
+  ORDER BY id;
+$$ LANGUAGE sql;
+
+CREATE OR REPLACE FUNCTION enqueue_entry_change() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    PERFORM enqueue_webhook_deliveries('created', NEW);
+  ELSIF TG_OP = 'DELETE' THEN
+    PERFORM enqueue_webhook_deliveries('deleted', OLD);
+  ELSIF OLD.namespace <> NEW.namespace THEN
+    PERFORM enqueue_webhook_deliveries('deleted', OLD);
+    PERFORM enqueue_webhook_deliveries('created', NEW);
+  ELSE
+    PERFORM enqueue_webhook_deliveries('updated', NEW);
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS entries_enqueue_webhooks ON entries;
+CREATE TRIGGER entries_enqueue_webhooks
+  AFTER INSERT OR UPDATE OR DELETE ON entries
+  FOR EACH ROW EXECUTE FUNCTION enqueue_entry_change();
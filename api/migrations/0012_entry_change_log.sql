@@ -0,0 +1,61 @@
+-- Append-only log of every change of entries, so clients can replay history of a namespace from
+-- any point ("every change since N"). Like notifications, it's written by a trigger, so writes made
+-- directly in SQL are logged too. Created and updated entries keep their content after the change.
+CREATE TABLE IF NOT EXISTS entry_change_log (
+  seq BIGSERIAL PRIMARY KEY,
+  namespace VARCHAR(64) NOT NULL,
+  entry_id BIGINT NOT NULL,
+  kind VARCHAR(16) NOT NULL,
+  content JSONB,
+  changed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS entry_change_log_namespace_seq_idx ON entry_change_log (namespace, seq);
+CREATE INDEX IF NOT EXISTS entry_change_log_changed_at_idx ON entry_change_log (changed_at);
+
+-- The highest sequence number which was removed from the log of the namespace by retention.
+-- Clients which read from an older position have missed changes and must reload the data.
+CREATE TABLE IF NOT EXISTS entry_change_log_pruned (
+  namespace VARCHAR(64) PRIMARY KEY,
+  seq BIGINT NOT NULL
+);
+
+-- Sequence numbers are allocated when the change is made, but transactions commit in any order,
+-- so a reader could see number 6 before 5 is committed and skip it. To prevent that, the writer
+-- holds a lock of the namespace (in a separate key space from other advisory locks) until commit,
+-- and within a namespace numbers are visible in order. Readers don't take it.
+-- The cost is that writes to the same namespace are serialized: a transaction which wrote an entry
+-- blocks every other writer of the namespace until it commits, so long transactions (or many
+-- concurrent writers of one namespace) lose throughput. Writes to different namespaces don't wait.
+CREATE OR REPLACE FUNCTION lock_entry_change_log(namespace TEXT) RETURNS void AS $$
+BEGIN
+  PERFORM pg_advisory_xact_lock(1, hashtext(namespace));
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION log_entry_change() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    PERFORM lock_entry_change_log(NEW.namespace);
+    INSERT INTO entry_change_log (namespace, entry_id, kind, content) VALUES (NEW.namespace, NEW.id, 'created', NEW.content);
+  ELSIF TG_OP = 'DELETE' THEN
+    PERFORM lock_entry_change_log(OLD.namespace);
+    INSERT INTO entry_change_log (namespace, entry_id, kind) VALUES (OLD.namespace, OLD.id, 'deleted');
+  ELSIF OLD.namespace <> NEW.namespace THEN
+    -- Both namespaces are locked in the same order by everyone, so moves can't deadlock each other.
+    PERFORM lock_entry_change_log(LEAST(OLD.namespace, NEW.namespace));
+    PERFORM lock_entry_change_log(GREATEST(OLD.namespace, NEW.namespace));
+    INSERT INTO entry_change_log (namespace, entry_id, kind) VALUES (OLD.namespace, OLD.id, 'deleted');
+    INSERT INTO entry_change_log (namespace, entry_id, kind, content) VALUES (NEW.namespace, NEW.id, 'created', NEW.content);
+  ELSE
+    PERFORM lock_entry_change_log(NEW.namespace);
+    INSERT INTO entry_change_log (namespace, entry_id, kind, content) VALUES (NEW.namespace, NEW.id, 'updated', NEW.content);
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS entries_log_change ON entries;
+CREATE TRIGGER entries_log_change
+  AFTER INSERT OR UPDATE OR DELETE ON entries
+  FOR EACH ROW EXECUTE FUNCTION log_entry_change();
@@ -0,0 +1,45 @@
+-- Every state of every entry, including the current one, with the time range during which it was
+-- current (valid_to is NULL for the current state, and for deleted entries it's the deletion time).
+-- Revisions are numbered per entry from 1. Like other logs, it's written by a trigger, so writes
+-- made directly in SQL keep history too, and it's never cleaned up by the API.
+CREATE TABLE IF NOT EXISTS entry_revisions (
+  id BIGSERIAL PRIMARY KEY,
+  entry_id BIGINT NOT NULL,
+  revision INT NOT NULL,
+  namespace VARCHAR(64) NOT NULL,
+  content JSONB NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL,
+  version BIGINT NOT NULL,
+  valid_from TIMESTAMPTZ NOT NULL DEFAULT now(),
+  valid_to TIMESTAMPTZ,
+  UNIQUE (entry_id, revision)
+);
+
+CREATE INDEX IF NOT EXISTS entry_revisions_namespace_valid_idx ON entry_revisions (namespace, valid_from, valid_to);
+
+-- History before this migration is unknown, so existing entries start with their current state,
+-- which is considered valid since their last update.
+INSERT INTO entry_revisions (entry_id, revision, namespace, content, created_at, updated_at, version, valid_from)
+  SELECT id, 1, namespace, content, created_at, updated_at, version, updated_at FROM entries
+  WHERE NOT EXISTS (SELECT 1 FROM entry_revisions WHERE entry_id = entries.id);
+
+CREATE OR REPLACE FUNCTION record_entry_revision() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP IN ('UPDATE', 'DELETE') THEN
+    UPDATE entry_revisions SET valid_to = now() WHERE entry_id = OLD.id AND valid_to IS NULL;
+  END IF;
+  IF TG_OP IN ('INSERT', 'UPDATE') THEN
+    -- Entry which was deleted and then put again with the same ID continues its numbering.
+    INSERT INTO entry_revisions (entry_id, revision, namespace, content, created_at, updated_at, version)
+      SELECT NEW.id, COALESCE(max(revision), 0) + 1, NEW.namespace, NEW.content, NEW.created_at, NEW.updated_at, NEW.version
+      FROM entry_revisions WHERE entry_id = NEW.id;
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS entries_record_revision ON entries;
+CREATE TRIGGER entries_record_revision
+  AFTER INSERT OR UPDATE OR DELETE ON entries
+  FOR EACH ROW EXECUTE FUNCTION record_entry_revision();
@@ -0,0 +1,46 @@
+-- Revisions are numbered from 1 within a generation of the entry: entry which is deleted and then
+-- created again with the same ID (maybe in another namespace, by someone who couldn't read the old
+-- one) starts a new generation, and only the current one is listed by the API.
+ALTER TABLE entry_revisions ADD COLUMN IF NOT EXISTS generation INT NOT NULL DEFAULT 1;
+ALTER TABLE entry_revisions DROP CONSTRAINT IF EXISTS entry_revisions_entry_id_revision_key;
+
+-- Existing history is split where the entry didn't exist for a while: revision which replaced
+-- another one became valid exactly when that one stopped being valid (in the same transaction),
+-- while entry which was created again became valid after the deletion.
+WITH breaks AS (
+  SELECT id, entry_id, revision,
+         CASE WHEN lag(valid_to) OVER (PARTITION BY entry_id ORDER BY revision) < valid_from THEN 1 ELSE 0 END AS created_again
+  FROM entry_revisions
+), generations AS (
+  SELECT id, entry_id, revision,
+         1 + sum(created_again) OVER (PARTITION BY entry_id ORDER BY revision) AS generation
+  FROM breaks
+), numbered AS (
+  SELECT id, generation, row_number() OVER (PARTITION BY entry_id, generation ORDER BY revision) AS revision
+  FROM generations
+)
+UPDATE entry_revisions r SET generation = n.generation, revision = n.revision
+  FROM numbered n WHERE n.id = r.id;
+
+ALTER TABLE entry_revisions DROP CONSTRAINT IF EXISTS entry_revisions_entry_id_generation_revision_key;
+ALTER TABLE entry_revisions ADD CONSTRAINT entry_revisions_entry_id_generation_revision_key
+  UNIQUE (entry_id, generation, revision);
+
+CREATE OR REPLACE FUNCTION record_entry_revision() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP IN ('UPDATE', 'DELETE') THEN
+    UPDATE entry_revisions SET valid_to = now() WHERE entry_id = OLD.id AND valid_to IS NULL;
+  END IF;
+  IF TG_OP = 'INSERT' THEN
+    -- New entry, or one which was deleted before, starts a new generation.
+    INSERT INTO entry_revisions (entry_id, generation, revision, namespace, content, created_at, updated_at, version)
+      SELECT NEW.id, COALESCE(max(generation), 0) + 1, 1, NEW.namespace, NEW.content, NEW.created_at, NEW.updated_at, NEW.version
+      FROM entry_revisions WHERE entry_id = NEW.id;
+  ELSIF TG_OP = 'UPDATE' THEN
+    INSERT INTO entry_revisions (entry_id, generation, revision, namespace, content, created_at, updated_at, version)
+      SELECT NEW.id, generation, revision + 1, NEW.namespace, NEW.content, NEW.created_at, NEW.updated_at, NEW.version
+      FROM entry_revisions WHERE entry_id = NEW.id ORDER BY generation DESC, revision DESC LIMIT 1;
+  END IF;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,91 @@
+-- Replaces per-row notifications (see 0010) with per-statement ones, so a statement which changes
+-- the whole namespace doesn't flood the notification queue. Payload is the same as before.
+CREATE OR REPLACE FUNCTION entry_change_payload(kind TEXT, namespace TEXT, id BIGINT, content JSONB) RETURNS TEXT AS $$
+DECLARE
+  payload TEXT;
+BEGIN
+  IF kind <> 'deleted' THEN
+    payload := json_build_object('kind', kind, 'namespace', namespace, 'id', id, 'content', content)::text;
+    IF octet_length(payload) < 7900 THEN
+      RETURN payload;
+    END IF;
+  END IF;
+  RETURN json_build_object('kind', kind, 'namespace', namespace, 'id', id)::text;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Changes are announced once per statement. Namespace-wide operations of the API (deletion of all
+-- entries, copy, rename and merge, which set 'voyeur.bulk_write' for their transaction) changing
+-- more than 100 entries, and any statement changing more than 10000 entries, would flood the queue,
+-- so instead every affected namespace gets a single {"reset": <namespace>} and listeners reload it.
+-- Other writes, such as inserts of arrays and NDJSON batches (1000 entries), are announced entry by
+-- entry unless they are that large.
+CREATE OR REPLACE FUNCTION notify_entry_changes() RETURNS trigger AS $$
+DECLARE
+  amount BIGINT;
+  changes REFCURSOR;
+  change RECORD;
+BEGIN
+  IF TG_OP = 'INSERT' THEN
+    SELECT count(*) INTO amount FROM new_rows;
+  ELSE
+    SELECT count(*) INTO amount FROM old_rows;
+  END IF;
+
+  IF amount > 10000 OR (amount > 100 AND current_setting('voyeur.bulk_write', true) = 'on') THEN
+    IF TG_OP = 'INSERT' THEN
+      OPEN changes FOR SELECT DISTINCT namespace FROM new_rows;
+    ELSIF TG_OP = 'DELETE' THEN
+      OPEN changes FOR SELECT DISTINCT namespace FROM old_rows;
+    ELSE
+      OPEN changes FOR SELECT namespace FROM old_rows UNION SELECT namespace FROM new_rows;
+    END IF;
+    LOOP
+      FETCH changes INTO change;
+      EXIT WHEN NOT FOUND;
+      PERFORM pg_notify('entry_changes', json_build_object('reset', change.namespace)::text);
+    END LOOP;
+    CLOSE changes;
+    RETURN NULL;
+  END IF;
+
+  IF TG_OP = 'INSERT' THEN
+    OPEN changes FOR SELECT 'created' AS kind, namespace, id, content FROM new_rows ORDER BY id;
+  ELSIF TG_OP = 'DELETE' THEN
+    OPEN changes FOR SELECT 'deleted' AS kind, namespace, id, NULL::jsonb AS content FROM old_rows ORDER BY id;
+  ELSE
+    -- Entry which was moved leaves one namespace and appears in another one.
+    OPEN changes FOR SELECT kind, namespace, id, content FROM (
+      SELECT 'deleted' AS kind, o.namespace, o.id, NULL::jsonb AS content, 0 AS step
+        FROM old_rows o JOIN new_rows n ON n.id = o.id WHERE o.namespace <> n.namespace
+      UNION ALL
+      SELECT CASE WHEN o.namespace <> n.namespace THEN 'created' ELSE 'updated' END, n.namespace, n.id, n.content, 1
+        FROM old_rows o JOIN new_rows n ON n.id = o.id
+    ) moved ORDER BY id, step;
+  END IF;
+  LOOP
+    FETCH changes INTO change;
+    EXIT WHEN NOT FOUND;
+    PERFORM pg_notify('entry_changes', entry_change_payload(change.kind, change.namespace, change.id, change.content));
+  END LOOP;
+  CLOSE changes;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Transition tables can't be shared by a trigger of several events, so there is one per event.
+DROP TRIGGER IF EXISTS entries_notify_change ON entries;
+DROP FUNCTION IF EXISTS notify_entry_change();
+DROP FUNCTION IF EXISTS entry_change_payload(TEXT, entries);
+DROP TRIGGER IF EXISTS entries_notify_insert ON entries;
+CREATE TRIGGER entries_notify_insert
+  AFTER INSERT ON entries REFERENCING NEW TABLE AS new_rows
+  FOR EACH STATEMENT EXECUTE FUNCTION notify_entry_changes();
+DROP TRIGGER IF EXISTS entries_notify_update ON entries;
+CREATE TRIGGER entries_notify_update
+  AFTER UPDATE ON entries REFERENCING OLD TABLE AS old_rows NEW TABLE AS new_rows
+  FOR EACH STATEMENT EXECUTE FUNCTION notify_entry_changes();
+DROP TRIGGER IF EXISTS entries_notify_delete ON entries;
+CREATE TRIGGER entries_notify_delete
+  AFTER DELETE ON entries REFERENCING OLD TABLE AS old_rows
+  FOR EACH STATEMENT EXECUTE FUNCTION notify_entry_changes();
@@ -0,0 +1,340 @@
+use rocket::request::{Outcome, Request, FromRequest};
+use rocket_contrib::databases::postgres;
+use rocket_contrib::json::JsonValue;
+use serde::{Serialize, Deserialize};
+use crate::errors::{ErrorMessage, ApiError};
+use crate::timestamps::format_timestamp;
+use crate::namespace::{Namespace, MAX_NAMESPACE_LENGTH};
+use crate::model::ApiDatabase;
+use rocket::fairing::AdHoc;
+use std::marker::PhantomData;
+use time::OffsetDateTime;
+use rocket::http::Status;
+use sha2::{Sha256, Digest};
+use serde_json::Value;
+
+
+// Every generated key starts with this, so leaked keys are easy to find (e.g. in logs).
+const KEY_PREFIX: &str = "vy_";
+// Amount of leading characters of the key which are stored as is, to tell keys apart.
+const KEY_VISIBLE_CHARS: usize = 8;
+
+
+/// Authentication settings, read from Rocket config. When `require_api_keys` is disabled (it's
+/// the default for debug builds), requests without a key are allowed to do anything, but provided
+/// keys are still checked. `admin_key` is a bootstrap key with admin access to all namespaces,
+/// which is used to create the first real keys; empty value means there is none.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub require_api_keys: bool,
+    #[serde(default)]
+    pub admin_key: Option<String>,
+}
+
+
+/// Fairing which reads authentication settings into managed state.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("API Keys", |rocket| async {
+        match rocket.figment().extract::<AuthConfig>() {
+            // Empty key (e.g. unset variable in docker-compose) means there is no bootstrap key,
+            // otherwise an empty header would be accepted as one.
+            Ok(mut config) => {
+                config.admin_key = config.admin_key.filter(|key| !key.trim().is_empty());
+                Ok(rocket.manage(config))
+            },
+            Err(e) => {
+                eprintln!("Failed to read API keys config with error: '{}'!", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+
+/// Access level which can be granted to a key. Admin implies all the other scopes.
+pub trait Scope: Send + Sync + 'static {
+    const NAME: &'static str;
+    /// Global scopes are checked against all namespaces ("*" grants), instead of request's one.
+    const GLOBAL: bool = false;
+    /// Listing scopes only require a valid key, handler must check every namespace it returns
+    /// with `Scoped::allows`.
+    const LISTING: bool = false;
+}
+
+pub struct Read;
+pub struct Write;
+pub struct Delete;
+pub struct Admin;
+/// Managing API keys themselves, requires admin grant for all namespaces.
+pub struct ManageKeys;
+/// Listing namespaces, each of them is returned only if key can read it.
+pub struct ListNamespaces;
+
+impl Scope for Read { const NAME: &'static str = "read"; }
+impl Scope for Write { const NAME: &'static str = "write"; }
+impl Scope for Delete { const NAME: &'static str = "delete"; }
+impl Scope for Admin { const NAME: &'static str = "admin"; }
+impl Scope for ManageKeys { const NAME: &'static str = "admin"; const GLOBAL: bool = true; }
+impl Scope for ListNamespaces { const NAME: &'static str = "read"; const LISTING: bool = true; }
+
+static SCOPES: &[&str] = &["read", "write", "delete", "admin"];
+
+
+/// Scopes granted for a namespace, or for all namespaces with a prefix, if it ends with '*'
+/// (e.g. "team_a_*"). Single "*" matches every namespace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Grant {
+    pub namespace: String,
+    pub scopes:    Vec<String>,
+}
+
+
+impl Grant {
+    fn matches(&self, namespace: &str) -> bool {
+        match self.namespace.strip_suffix('*') {
+            Some(prefix) => namespace.starts_with(prefix),
+            None => self.namespace == namespace,
+        }
+    }
+
+    fn allows(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope || s == "admin")
+    }
+
+    /// Parses list of grants from the request body. On failure returns an error message which
+    /// is ready to be sent to the client.
+    pub fn parse_all(value: &Value) -> Result<Vec<Grant>, JsonValue> {
+        let grants = serde_json::from_value::<Vec<Grant>>(value.clone()).map_err(|e| json!({
+            "code":    "err_api_key_grants",
+            "message": format!("Grants must be a list of {{\"namespace\": <string>, \"scopes\": [<string>]}} objects, error: '{}'!", e),
+        }))?;
+
+        for grant in &grants {
+            let name = grant.namespace.strip_suffix('*').unwrap_or(&grant.namespace);
+            if (name.is_empty() && grant.namespace != "*") || grant.namespace.len() > MAX_NAMESPACE_LENGTH || name.contains('*') {
+                return Err(json!({
+                    "code":      "err_api_key_grants",
+                    "message":   format!("Grant namespace '{}' is malformed, it must be a namespace, a prefix ending with '*' or '*'!", grant.namespace),
+                    "namespace": grant.namespace,
+                }));
+            }
+            if let Some(scope) = grant.scopes.iter().find(|s| !SCOPES.contains(&s.as_str())) {
+                return Err(json!({
+                    "code":    "err_api_key_grants",
+                    "message": format!("Unknown scope '{}', expected one of: read, write, delete, admin!", scope),
+                    "scope":   scope,
+                }));
+            }
+        }
+
+        Ok(grants)
+    }
+}
+
+
+/// Stored API key (without the key itself, which is only known to its owner).
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub id:         Option<u64>,
+    pub name:       String,
+    pub prefix:     String,
+    pub grants:     Vec<Grant>,
+    pub created_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+
+impl ApiKey {
+    fn hash(key: &str) -> String {
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+
+    fn from_row(row: &postgres::Row) -> Result<ApiKey, ApiError> {
+        let malformed = |e: postgres::Error| ApiError::Malformed(e.to_string());
+        Ok(ApiKey {
+            id: Some(row.try_get::<_, i64>("id").map_err(malformed)? as u64),
+            name: row.try_get("name").map_err(malformed)?,
+            prefix: row.try_get("prefix").map_err(malformed)?,
+            grants: serde_json::from_value(row.try_get::<_, Value>("grants").map_err(malformed)?)
+                .map_err(|e| ApiError::Malformed(e.to_string()))?,
+            created_at: Some(format_timestamp(row.try_get::<_, OffsetDateTime>("created_at").map_err(malformed)?)),
+            revoked_at: row.try_get::<_, Option<OffsetDateTime>>("revoked_at").map_err(malformed)?.map(format_timestamp),
+        })
+    }
+
+    /// Returns true if key allows to use the scope with the namespace, or with every namespace
+    /// if it's None.
+    pub fn allows(&self, scope: &str, namespace: Option<&str>) -> bool {
+        self.grants.iter().any(|grant| grant.allows(scope) && match namespace {
+            Some(namespace) => grant.matches(namespace),
+            None => grant.namespace == "*",
+        })
+    }
+
+    /// Creates new key with the grants. Returns stored key together with the key itself, which
+    /// can't be recovered later.
+    pub fn create(c: &mut postgres::Client, name: String, grants: Vec<Grant>) -> Result<(ApiKey, String), ApiError> {
+        let key = format!("{}{}", KEY_PREFIX, base64::encode_config(rand::random::<[u8; 32]>(), base64::URL_SAFE_NO_PAD));
+        let prefix = key[..KEY_VISIBLE_CHARS].to_string();
+        let grants = serde_json::to_value(&grants).map_err(|e| ApiError::Malformed(e.to_string()))?;
+
+        let row = c.query_one(
+            "INSERT INTO api_keys (name, prefix, key_hash, grants) VALUES ($1, $2, $3, $4) RETURNING *",
+            &[&name, &prefix, &Self::hash(&key), &grants]
+        )?;
+        Ok((Self::from_row(&row)?, key))
+    }
+
+    pub fn list(c: &mut postgres::Client) -> Result<Vec<ApiKey>, ApiError> {
+        c.query("SELECT * FROM api_keys ORDER BY id ASC", &[])?
+            .iter()
+            .map(Self::from_row)
+            .collect()
+    }
+
+    /// Revokes the key, so it can't be used anymore. Key is kept for the audit.
+    pub fn revoke(c: &mut postgres::Client, id: u64) -> Result<ApiKey, ApiError> {
+        match c.query_opt(
+            "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, now()) WHERE id = $1 RETURNING *",
+            &[&(id as i64)]
+        )? {
+            Some(row) => Self::from_row(&row),
+            None => Err(ApiError::KeyNotFound(id)),
+        }
+    }
+
+    /// Finds active key by its value.
+    pub fn find(c: &mut postgres::Client, key: &str) -> Result<Option<ApiKey>, ApiError> {
+        match c.query_opt(
+            "SELECT * FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL",
+            &[&Self::hash(key)]
+        )? {
+            Some(row) => Ok(Some(Self::from_row(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Bootstrap key from the config, which is never stored.
+    fn bootstrap() -> ApiKey {
+        ApiKey {
+            id:         None,
+            name:       "admin_key".to_string(),
+            prefix:     String::new(),
+            grants:     vec![Grant { namespace: "*".to_string(), scopes: vec!["admin".to_string()] }],
+            created_at: None,
+            revoked_at: None,
+        }
+    }
+}
+
+
+/// Request guard which makes sure that request is authenticated with an API key, which grants
+/// the scope for requested namespace (see `Namespace`). Key is read from the "Authorization"
+/// header ("Bearer <key>") or "X-Api-Key" header. If keys are not required and none was
+/// provided, `key` is None.
+pub struct Scoped<S: Scope> {
+    pub key: Option<ApiKey>,
+    scope:   PhantomData<S>,
+}
+
+
+impl<S: Scope> Scoped<S> {
+    /// Returns true if request is allowed to use the scope with the namespace.
+    pub fn allows(&self, namespace: &str) -> bool {
+        self.key.as_ref().map_or(true, |key| key.allows(S::NAME, Some(namespace)))
+    }
+
+    /// Same as `allows`, but for another scope (e.g. when request touches two namespaces).
+    pub fn allows_scope<T: Scope>(&self, namespace: &str) -> bool {
+        self.key.as_ref().map_or(true, |key| key.allows(T::NAME, Some(namespace)))
+    }
+
+    /// Turns errors which reveal that entry exists in another namespace into `NotFound`, unless
+    /// the request can read that namespace. Put isn't concealed (see `update_entry_by_id`).
+    pub fn conceal(&self, error: ApiError) -> ApiError {
+        match error {
+            ApiError::NamespaceMismatch(id, actual)
+                if !self.allows_scope::<Read>(&actual) => ApiError::NotFound(id),
+            error => error,
+        }
+    }
+
+    fn fail(req: &Request<'_>, status: Status, code: &str, message: String) -> Outcome<Self, ()> {
+        // Store error message.
+        req.local_cache(|| ErrorMessage(Some(json!({
+            "code":    code,
+            "message": message,
+        }))));
+        // Forward to error catcher.
+        Outcome::Failure((status, ()))
+    }
+}
+
+
+#[rocket::async_trait]
+impl<'r, S: Scope> FromRequest<'r> for Scoped<S> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        let config = req.rocket().state::<AuthConfig>().expect("API keys fairing is not attached!");
+
+        let raw = match req.headers().get_one("X-Api-Key") {
+            Some(value) => Some(value.to_string()),
+            None => req.headers().get_one("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(|value| value.trim().to_string()),
+        };
+
+        let key = match raw {
+            None if !config.require_api_keys => return Outcome::Success(Scoped { key: None, scope: PhantomData }),
+            None => return Self::fail(req, Status::Unauthorized, "err_api_key_missing",
+                "You must provide API key with 'Authorization: Bearer <key>' or 'X-Api-Key' header!".to_string()),
+            Some(raw) if raw.trim().is_empty() => return Self::fail(req, Status::Unauthorized, "err_api_key_invalid",
+                "Provided API key is invalid or was revoked!".to_string()),
+            // Digests are compared instead of keys, so time of the comparison doesn't depend on the key.
+            Some(raw) if config.admin_key.as_deref().map_or(false, |admin| ApiKey::hash(admin) == ApiKey::hash(&raw)) => ApiKey::bootstrap(),
+            Some(raw) => {
+                let conn = match req.guard::<ApiDatabase>().await {
+                    Outcome::Success(conn) => conn,
+                    Outcome::Failure((status, _)) => return Outcome::Failure((status, ())),
+                    Outcome::Forward(()) => return Outcome::Forward(()),
+                };
+
+                match conn.run(move |c| ApiKey::find(c, &raw)).await {
+                    Ok(Some(key)) => key,
+                    Ok(None) => return Self::fail(req, Status::Unauthorized, "err_api_key_invalid",
+                        "Provided API key is invalid or was revoked!".to_string()),
+                    Err(e) => {
+                        let status = match e {
+                            ApiError::Unavailable(_) => Status::ServiceUnavailable,
+                            _ => Status::InternalServerError,
+                        };
+                        return Self::fail(req, status, e.code(), e.to_string());
+                    }
+                }
+            }
+        };
+
+        if S::LISTING {
+            return Outcome::Success(Scoped { key: Some(key), scope: PhantomData });
+        }
+
+        let namespace = match S::GLOBAL {
+            true => None,
+            false => match req.guard::<Namespace>().await {
+                Outcome::Success(namespace) => Some(namespace.0),
+                Outcome::Failure((status, _)) => return Outcome::Failure((status, ())),
+                Outcome::Forward(()) => return Outcome::Forward(()),
+            },
+        };
+
+        match key.allows(S::NAME, namespace.as_deref()) {
+            true => Outcome::Success(Scoped { key: Some(key), scope: PhantomData }),
+            false => Self::fail(req, Status::Forbidden, "err_api_key_scope", match namespace {
+                Some(namespace) => format!("API key doesn't grant '{}' scope for namespace '{}'!", S::NAME, namespace),
+                None => format!("API key doesn't grant '{}' scope for all namespaces!", S::NAME),
+            }),
+        }
+    }
+}
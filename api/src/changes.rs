@@ -0,0 +1,166 @@
+use rocket_contrib::databases::postgres;
+use crate::timestamps::format_timestamp;
+use crate::events::ChangeKind;
+use crate::errors::ApiError;
+use rocket::fairing::AdHoc;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use std::thread;
+
+
+// How often changes older than retention are removed from the log.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// How often pruner checks whether Rocket instance is still alive.
+const ALIVE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+
+fn default_retention() -> u64 {
+    // 7 days, enough for a sync which runs daily to skip a few runs.
+    7 * 24 * 60 * 60
+}
+
+
+/// Change log settings, read from Rocket config. `change_log_retention` is the amount of seconds
+/// during which changes are kept in the log, 0 means that they are kept forever.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChangeLogConfig {
+    #[serde(default = "default_retention")]
+    pub change_log_retention: u64,
+}
+
+
+/// Logged change of an entry. Sequence numbers grow in the order in which changes of the namespace
+/// were committed, but they are shared by all namespaces, so there are gaps between them.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoggedChange {
+    pub seq:        u64,
+    pub kind:       ChangeKind,
+    pub id:         u64,
+    /// Content of the entry after the change, there is none for deleted entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content:    Option<Value>,
+    pub changed_at: String,
+}
+
+
+impl LoggedChange {
+    /// Returns up to `limit` changes of the namespace after the sequence number, from the oldest
+    /// one. Second value is true if there are more of them. If some changes after `since` were
+    /// already removed by retention, fails with 'err_changes_expired'.
+    pub fn read(c: &mut postgres::Client, namespace: &str, since: u64, limit: u16) -> Result<(Vec<LoggedChange>, bool), ApiError> {
+        // Both queries must see the same state, otherwise log could be pruned in between.
+        let mut tx = c.build_transaction().isolation_level(postgres::IsolationLevel::RepeatableRead).start()?;
+        let pruned = tx.query_opt("SELECT seq FROM entry_change_log_pruned WHERE namespace = $1", &[&namespace])?
+            .map_or(0, |row| row.get::<_, i64>("seq") as u64);
+        if since < pruned {
+            return Err(ApiError::ChangesExpired(pruned));
+        }
+
+        // One more row is read, to know whether there are more of them.
+        let mut rows = tx.query(
+            "SELECT * FROM entry_change_log WHERE namespace = $1 AND seq > $2 ORDER BY seq ASC LIMIT $3",
+            &[&namespace, &(since as i64), &(limit as i64 + 1)]
+        )?;
+        let has_more = rows.len() > limit as usize;
+        rows.truncate(limit as usize);
+        tx.commit()?;
+
+        let changes = rows.iter().map(|row| {
+            let malformed = |e: postgres::Error| ApiError::Malformed(e.to_string());
+            Ok(LoggedChange {
+                seq: row.try_get::<_, i64>("seq").map_err(malformed)? as u64,
+                kind: serde_json::from_value(Value::String(row.try_get("kind").map_err(malformed)?))
+                    .map_err(|e| ApiError::Malformed(e.to_string()))?,
+                id: row.try_get::<_, i64>("entry_id").map_err(malformed)? as u64,
+                content: row.try_get("content").map_err(malformed)?,
+                changed_at: format_timestamp(row.try_get::<_, OffsetDateTime>("changed_at").map_err(malformed)?),
+            })
+        }).collect::<Result<Vec<LoggedChange>, ApiError>>()?;
+
+        Ok((changes, has_more))
+    }
+
+    /// Returns the sequence number of the last change of the namespace (0 if there was none), which is
+    /// the position to replay changes from after the data was loaded. Since changes of a namespace
+    /// are committed in the order of their numbers, every change up to it is already visible.
+    pub fn head(c: &mut postgres::Client, namespace: &str) -> Result<u64, ApiError> {
+        let row = c.query_one(
+            "SELECT GREATEST( \
+                (SELECT max(seq) FROM entry_change_log WHERE namespace = $1), \
+                (SELECT seq FROM entry_change_log_pruned WHERE namespace = $1), \
+                0 \
+            ) AS head",
+            &[&namespace]
+        )?;
+        Ok(row.get::<_, i64>("head") as u64)
+    }
+
+    /// Removes changes older than retention, and remembers the last removed sequence number of
+    /// every namespace.
+    pub fn prune(c: &mut postgres::Client, retention: u64) -> Result<(), ApiError> {
+        c.execute(
+            "WITH pruned AS ( \
+                DELETE FROM entry_change_log WHERE changed_at < now() - make_interval(secs => $1) RETURNING namespace, seq \
+            ) \
+            INSERT INTO entry_change_log_pruned (namespace, seq) SELECT namespace, max(seq) FROM pruned GROUP BY namespace \
+            ON CONFLICT (namespace) DO UPDATE SET seq = GREATEST(entry_change_log_pruned.seq, EXCLUDED.seq)",
+            &[&(retention as f64)]
+        )?;
+        Ok(())
+    }
+}
+
+
+// Runs in its own thread with its own connection, until Rocket instance is gone.
+fn prune_periodically(url: String, retention: u64, alive: Weak<()>) {
+    let mut last_run: Option<Instant> = None;
+    while alive.upgrade().is_some() {
+        if last_run.map_or(true, |last| last.elapsed() >= PRUNE_INTERVAL) {
+            last_run = Some(Instant::now());
+            let result = postgres::Client::connect(&url, postgres::NoTls)
+                .map_err(ApiError::from)
+                .and_then(|mut c| LoggedChange::prune(&mut c, retention));
+            if let Err(e) = result {
+                eprintln!("Failed to prune change log with error: '{}'!", e);
+            }
+        }
+        thread::sleep(ALIVE_CHECK_INTERVAL);
+    }
+}
+
+
+// Managed state which keeps pruner running, while Rocket instance exists.
+struct Pruner(#[allow(dead_code)] Arc<()>);
+
+
+/// Fairing which reads change log settings and starts removing changes older than retention
+/// (unless it's 0). Every replica runs it, and it doesn't matter which one removes them.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Change Log", |rocket| async {
+        let config = match rocket.figment().extract::<ChangeLogConfig>() {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Failed to read change log config with error: '{}'!", e);
+                return Err(rocket);
+            }
+        };
+        if config.change_log_retention == 0 {
+            return Ok(rocket);
+        }
+        let url = match rocket.figment().extract_inner::<String>("databases.storage.url") {
+            Ok(url) => url,
+            Err(e) => {
+                eprintln!("Failed to read database url for change log with error: '{}'!", e);
+                return Err(rocket);
+            }
+        };
+
+        let alive = Arc::new(());
+        let weak = Arc::downgrade(&alive);
+        thread::spawn(move || prune_periodically(url, config.change_log_retention, weak));
+        Ok(rocket.manage(Pruner(alive)))
+    })
+}
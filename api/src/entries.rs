@@ -1,34 +1,108 @@
-use crate::responders::CustomResponder;
-use crate::model::{ApiDatabase, Entry};
+use crate::responders::{CustomResponder, Linked, Tagged, Idempotent};
+use crate::preconditions::{self, Preconditions};
+use crate::pagination::{PageSize, Cursor, Sort, SortKey};
+use crate::model::{ApiDatabase, Entry, Selection};
 use rocket_contrib::json::JsonValue;
-use crate::pagination::PageSize;
-use crate::namespace::Namespace;
-use crate::errors::ErrorMessage;
-use rocket::Request;
+use serde_json::Value;
+use crate::timestamps::TimeRange;
+use crate::export::{self, ExportFormat, Columns};
+use crate::ndjson::{NdjsonReader, BodyLimit};
+use crate::namespace::{Namespace, TargetNamespace};
+use crate::auth::{Scoped, Read, Write, Delete};
+use crate::idempotency::IdempotencyKey;
+use crate::events::{Broker, EventId, LastEventId};
+use crate::changes::LoggedChange;
+use crate::filter::Filter;
+use crate::patch::{self, Patch};
+use crate::errors::{ErrorMessage, ApiError};
+use rocket::response::stream::{TextStream, EventStream, Event};
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::select;
+use rocket::Shutdown;
+use rocket::{Request, Data};
+use rocket::http::ContentType;
 
 
 /// This endpoint is used to receive a single entry by ID (url path /<id> of type unsigned
 /// 64-bit integer). Entry is an object containing id and content, example: {"id": 4, "content":
 /// <your_json>}. For this endpoint you must provide namespace (url argument <namespace> or header
-/// "X-Namespace", of type <String>).
+/// "X-Namespace", of type <String>). If there is no such entry, response is 404 with code
+/// 'err_entry_not_found', or 'err_entry_namespace_mismatch' if entry belongs to another namespace.
+/// Response has "ETag" header with the version of the entry, and if it matches "If-None-Match"
+/// header of the request, response is 304 without body.
 #[get("/<id>")]
-pub async fn get_entry_by_id(namespace: Namespace, id: u64, conn: ApiDatabase) -> CustomResponder {
+pub async fn get_entry_by_id(namespace: Namespace, key: Scoped<Read>, id: u64, preconditions: Preconditions, conn: ApiDatabase) -> Tagged {
     // @Robustness: This copy is required due to the way we handle errors right now.
     let namespace_copy = namespace.0.clone();
 
     match conn.run(move |c| Entry::get_one(c, id, namespace.0)).await {
-        Ok(entry) => CustomResponder::Ok(json!({
-            "code": "no_message",
-            "namespace": &namespace_copy,
-            "data": entry
-        })),
-        Err(id) => CustomResponder::BadRequest(json!({
-            "code": "error_sql_get_one_by_id",
-            "message": format!("Entry with ID '{}' does not exist!", id),
-            "namespace": &namespace_copy,
-            "id": id,
-        }))
+        Ok(entry) => Tagged {
+            etag: Some(preconditions::etag(entry.version)),
+            inner: match preconditions.if_none_match {
+                Some(precondition) if precondition.matches(Some(entry.version)) => CustomResponder::NotModified(()),
+                _ => CustomResponder::Ok(json!({
+                    "code": "no_message",
+                    "namespace": &namespace_copy,
+                    "data": entry
+                })),
+            },
+        },
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy).into(),
+    }
+}
+
+
+/// Shared implementation of paginated endpoints below. Total amount of entries (and pages) is
+/// only counted when client asks for it, because it requires to scan all matching entries.
+async fn get_page(selection: Selection, sort: Sort, page: u32, page_size: PageSize, count: bool, conn: ApiDatabase) -> Linked {
+    // @Robustness: This copy is required due to the way we handle errors right now.
+    let namespace_copy = selection.namespace.clone();
+    let size = page_size.0;
+
+    let result = conn.run(move |c| -> Result<_, ApiError> {
+        let total = match count {
+            true => Some(Entry::count(c, &selection)?),
+            false => None,
+        };
+        let (data, has_next) = Entry::get_page(c, &selection, sort, page, size)?;
+        Ok((data, has_next, total))
+    }).await;
+
+    let (data, has_next, total) = match result {
+        Ok(result) => result,
+        Err(e) => return Linked { inner: e.into(), links: vec![] },
+    };
+
+    let mut body = json!({
+        "code": "no_message",
+        "namespace": &namespace_copy,
+        "page_number": page,
+        "page_size": size,
+        "data": data
+    });
+
+    let mut links = vec![];
+    if page > 0 {
+        links.push(("prev", "page", (page - 1).to_string()));
     }
+    if has_next {
+        links.push(("next", "page", (page + 1).to_string()));
+    }
+
+    if let Some(total) = total {
+        let total_pages = (total + size as u64 - 1) / size as u64;
+        body["total"] = total.into();
+        body["total_pages"] = total_pages.into();
+        body["has_next"] = has_next.into();
+        body["has_prev"] = (page > 0).into();
+
+        links.push(("first", "page", "0".to_string()));
+        if total_pages > 0 {
+            links.push(("last", "page", (total_pages - 1).to_string()));
+        }
+    }
+
+    Linked { inner: CustomResponder::Ok(body), links }
 }
 
 
@@ -38,18 +112,19 @@ pub async fn get_entry_by_id(namespace: Namespace, id: u64, conn: ApiDatabase) -
 /// <your_json>}. For this endpoint you must provide namespace (url argument <namespace> or header
 /// "X-Namespace", of type <String>), page (url argument <page>, of type unsigned 32-bit integer),
 /// and query (url argument <query>, of type <String>) values. Optionally, you can specify a page
-/// size (url argument <page_size> or header "X-PAGE-SIZE", of type unsigned 16-bit integer).
-#[get("/?<page>&<query>", rank = 1)]
-pub async fn get_query_content(namespace: Namespace, query: String, page: u32, page_size: PageSize, conn: ApiDatabase) -> JsonValue {
-    json!({
-        "code": "no_message",
-        "namespace": &namespace.0,
-        "page_number": page.clone(),
-        "page_size": page_size.0.clone(),
-        "data": conn.run(
-            move |c| Entry::get_query(c, namespace.0, page, page_size.0, query)
-        ).await
-    })
+/// size (url argument <page_size> or header "X-PAGE-SIZE", of type unsigned 16-bit integer),
+/// a structured filter (url argument <filter> or header "X-Filter", see below) and a count flag
+/// (url argument <count>, of type bool) which adds total, total_pages, has_next and has_prev values
+/// to the response. Links to neighbouring pages are returned in the "Link" header. Entries can be
+/// limited by creation time (url arguments <since> and <until>, or headers "X-Since" and "X-Until",
+/// RFC 3339 timestamps) and by last update time (<updated_since> and <updated_until>), and sorted
+/// (url argument <sort> or header "X-Sort", one of id, created_at, updated_at, prefixed with '-'
+/// for descending order). Namespace can be read as it was at some time (url argument <as_of> or
+/// header "X-As-Of", RFC 3339 timestamp): entries which existed then, with their content at that time.
+#[get("/?<page>&<query>&<count>", rank = 1)]
+pub async fn get_query_content(namespace: Namespace, _key: Scoped<Read>, query: String, page: u32, count: Option<bool>, page_size: PageSize, filter: Filter, range: TimeRange, sort: Sort, conn: ApiDatabase) -> Linked {
+    let selection = Selection { namespace: namespace.0, query: Some(query), filter, range };
+    get_page(selection, sort, page, page_size, count.unwrap_or(false), conn).await
 }
 
 
@@ -58,36 +133,306 @@ pub async fn get_query_content(namespace: Namespace, query: String, page: u32, p
 /// you must provide namespace (url argument <namespace> or header "X-Namespace", of type <String>)
 /// and page (url argument <page>, of type unsigned 32-bit integer) values. Optionally, you can
 /// specify a page size (url argument <page_size> or header "X-PAGE-SIZE", of type unsigned 16-bit
-/// integer).
-#[get("/?<page>", rank = 2)]
-pub async fn get_paginated_entries(namespace: Namespace, page: u32, page_size: PageSize, conn: ApiDatabase) -> JsonValue {
-    json!({
+/// integer), a count flag (url argument <count>, of type bool, see above) and a structured filter
+/// (url argument <filter> or header "X-Filter"). Filter is a JSON object of paths mapped to operators,
+/// example: {"status": {"eq": "failed"}, "metrics.latency_ms": {"gt": 500}}. Supported operators are
+/// eq, ne, lt, lte, gt, gte, in, exists and contains. Time range, "as_of" and sorting work the same
+/// way as for the endpoint above.
+#[get("/?<page>&<count>", rank = 2)]
+pub async fn get_paginated_entries(namespace: Namespace, _key: Scoped<Read>, page: u32, count: Option<bool>, page_size: PageSize, filter: Filter, range: TimeRange, sort: Sort, conn: ApiDatabase) -> Linked {
+    let selection = Selection { namespace: namespace.0, query: None, filter, range };
+    get_page(selection, sort, page, page_size, count.unwrap_or(false), conn).await
+}
+
+
+/// This endpoint is used to receive JSON array of entries using keyset (cursor) pagination, which
+/// unlike page numbers stays fast for big namespaces and doesn't skip or duplicate entries when
+/// new ones are inserted during the scan. Entry is an object containing id and content, example:
+/// {"id": 4, "content": <your_json>}. For this endpoint you must provide namespace (url argument
+/// <namespace> or header "X-Namespace", of type <String>) and cursor (url argument <cursor>, of
+/// type <String>). Use empty cursor to get the first page, and then 'next_cursor' value of the
+/// response to get the following ones, until it's null. Optionally, you can specify a page size,
+/// a query, a filter, a time range (with "as_of") and a count flag, same as for paginated endpoints
+/// above. Cursor always follows ID order, so sorting by other columns is not supported here.
+#[get("/?<cursor>&<query>&<count>", rank = 3)]
+pub async fn get_entries_by_cursor(namespace: Namespace, _key: Scoped<Read>, cursor: String, query: Option<String>, count: Option<bool>, page_size: PageSize, filter: Filter, range: TimeRange, sort: Sort, conn: ApiDatabase) -> Linked {
+    let after = match Cursor::decode(&cursor) {
+        Ok(after) => after,
+        Err(message) => return Linked { inner: CustomResponder::BadRequest(message), links: vec![] },
+    };
+    if sort.key != SortKey::Id || sort.descending {
+        return Linked { inner: CustomResponder::BadRequest(json!({
+            "code":    "err_sort_cursor_unsupported",
+            "message": "Cursor pagination always follows ascending ID order, use page numbers to sort by other columns!",
+        })), links: vec![] };
+    }
+    // @Robustness: This copy is required due to the way we handle errors right now.
+    let namespace_copy = namespace.0.clone();
+    let page_size_copy = page_size.0.clone();
+    let count = count.unwrap_or(false);
+    let selection = Selection { namespace: namespace.0, query, filter, range };
+
+    let result = conn.run(move |c| -> Result<_, ApiError> {
+        let total = match count {
+            true => Some(Entry::count(c, &selection)?),
+            false => None,
+        };
+        let (data, next) = Entry::get_after(c, &selection, after, page_size.0)?;
+        Ok((data, next, total))
+    }).await;
+
+    let (data, next, total) = match result {
+        Ok(result) => result,
+        Err(e) => return Linked { inner: e.into(), links: vec![] },
+    };
+
+    let next_cursor = next.map(|next| next.encode());
+    let mut body = json!({
         "code": "no_message",
-        "namespace": &namespace.0,
-        "page_number": page.clone(),
-        "page_size": page_size.0.clone(),
-        "data": conn.run(
-            move |c| Entry::get_page(c, namespace.0, page, page_size.0)
-        ).await
+        "namespace": &namespace_copy,
+        "cursor": &cursor,
+        "next_cursor": &next_cursor,
+        "page_size": page_size_copy,
+        "data": data
+    });
+
+    if let Some(total) = total {
+        body["total"] = total.into();
+        body["has_next"] = next_cursor.is_some().into();
+        body["has_prev"] = (!cursor.is_empty()).into();
+    }
+
+    let mut links = vec![("first", "cursor", String::new())];
+    if let Some(next_cursor) = next_cursor {
+        links.push(("next", "cursor", next_cursor));
+    }
+
+    Linked { inner: CustomResponder::Ok(body), links }
+}
+
+
+// Amount of entries which are read from the database at once during export.
+const EXPORT_BATCH_SIZE: u16 = 1000;
+
+
+/// This endpoint is used to export all entries of the namespace. Entries are read in batches and
+/// streamed to the client, so there is no limit on their amount. Output format is chosen with url
+/// argument <format> ("ndjson", "json" or "csv"), or "Accept" header ("application/x-ndjson",
+/// "application/json" or "text/csv"), by default it's NDJSON. For this endpoint you must provide
+/// namespace (url argument <namespace> or header "X-Namespace", of type <String>), and optionally
+/// a query, a filter and a time range, same as for listing endpoints. CSV output flattens content
+/// into dotted columns; they may be chosen with url argument <columns> or header "X-Columns"
+/// (comma-separated paths), otherwise columns of the first exported entries are used. If reading
+/// fails in the middle of the export, NDJSON ends with a line {"code": "err_export_interrupted",
+/// "message"}, CSV ends with a row "err_export_interrupted,<message>" (in place of the ID), and
+/// JSON array is not closed.
+#[get("/export?<query>")]
+pub async fn export_entries(namespace: Namespace, _key: Scoped<Read>, query: Option<String>, format: ExportFormat, columns: Columns, filter: Filter, range: TimeRange, conn: ApiDatabase) -> (ContentType, TextStream![String]) {
+    let selection = Selection { namespace: namespace.0, query, filter, range };
+
+    (format.content_type(), TextStream! {
+        let mut after = None;
+        let mut columns = columns.0;
+        let mut first = true;
+
+        loop {
+            let batch_selection = selection.clone();
+            let (data, next) = match conn.run(move |c| Entry::get_after(c, &batch_selection, Cursor(after), EXPORT_BATCH_SIZE)).await {
+                Ok(result) => result,
+                // Response is already being sent, so the status can't be changed. Instead, NDJSON
+                // and CSV end with an error line, and JSON array is left unterminated, so the client
+                // can't mistake partial export for the whole one.
+                Err(e) => {
+                    eprintln!("Export was interrupted with error: '{}'", e);
+                    let message = format!("Export was interrupted with error: '{}'!", e);
+                    match format {
+                        ExportFormat::Ndjson => yield format!("{}\n", json!({
+                            "code": "err_export_interrupted",
+                            "message": message,
+                        }).to_string()),
+                        ExportFormat::Csv => yield export::csv_error("err_export_interrupted", &message),
+                        ExportFormat::Json => (),
+                    }
+                    return;
+                }
+            };
+
+            let mut chunk = String::new();
+            match format {
+                ExportFormat::Ndjson => for entry in &data {
+                    chunk.push_str(&serde_json::to_string(entry).unwrap());
+                    chunk.push('\n');
+                },
+                ExportFormat::Json => for entry in &data {
+                    chunk.push_str(if first { "[" } else { "," });
+                    chunk.push_str(&serde_json::to_string(entry).unwrap());
+                    first = false;
+                },
+                ExportFormat::Csv => {
+                    if first {
+                        let columns = columns.get_or_insert_with(|| export::csv_columns(&data));
+                        chunk.push_str(&export::csv_header(columns));
+                        first = false;
+                    }
+                    for entry in &data {
+                        chunk.push_str(&export::csv_row(entry, columns.as_ref().unwrap()));
+                    }
+                },
+            }
+            if !chunk.is_empty() {
+                yield chunk;
+            }
+
+            match next {
+                Some(Cursor(Some(id))) => after = Some(id),
+                _ => break,
+            }
+        }
+
+        if format == ExportFormat::Json {
+            yield if first { "[]".to_string() } else { "]".to_string() };
+        }
     })
 }
 
 
+/// This endpoint is used to receive changes of the namespace in real time, as Server-Sent Events
+/// (content type "text/event-stream"), whichever replica (or SQL client) made them. Events are named
+/// "created", "updated" and "deleted", and their data is a JSON object with namespace, kind, ID and
+/// content of the entry (there is no content for deleted entries). For this endpoint you must provide
+/// namespace (url argument <namespace> or header "X-Namespace", of type <String>), and optionally
+/// a filter, same as for listing endpoints (deleted entries are sent regardless of it). Client which
+/// reconnects with "Last-Event-ID" header (or url argument <last_event_id>) gets events it has missed.
+/// If they are not available anymore (e.g. client reconnected to another replica), client is too slow
+/// to keep up, or a namespace-wide operation (deletion of all entries, copy, rename or merge) changed
+/// many entries at once, it gets "reset" event, meaning that the data should be reloaded.
+#[get("/stream")]
+pub async fn stream_entries(namespace: Namespace, _key: Scoped<Read>, filter: Filter, last_event_id: LastEventId, broker: Broker, mut shutdown: Shutdown) -> EventStream![] {
+    let subscription = broker.subscribe(last_event_id.0);
+    let reset = || Event::data(json!({
+        "code": "info_stream_reset",
+        "message": "Some changes were missed, data should be reloaded!",
+    }).to_string()).event("reset");
+
+    EventStream! {
+        let mut receiver = subscription.receiver;
+        let replica = subscription.replica;
+        if subscription.gap {
+            yield reset().id(EventId::format(&replica, subscription.last_seq));
+        }
+        for event in subscription.missed {
+            if event.matches(&namespace.0, &filter) {
+                yield event.to_sse(&replica);
+            }
+        }
+
+        loop {
+            let event = select! {
+                event = receiver.recv() => event,
+                _ = &mut shutdown => break,
+            };
+            match event {
+                Ok(event) if event.matches(&namespace.0, &filter) => yield event.to_sse(&replica),
+                Ok(_) => continue,
+                // Subscriber was too slow, and some events were dropped.
+                Err(RecvError::Lagged(_)) => yield reset(),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+
+/// This endpoint is used to replay history of the namespace: every change of its entries after
+/// certain position of the change log (url argument <since>, of type unsigned 64-bit integer, 0 by
+/// default), from the oldest one. Change is an object with sequence number, kind ("created", "updated"
+/// or "deleted"), ID of the entry, its content after the change (none for deleted entries) and time,
+/// example: {"seq": 42, "kind": "created", "id": 4, "content": <your_json>, "changed_at": "..."}.
+/// Response has "next_since" value (and "next" link in the "Link" header, if there are more changes),
+/// which is the position to continue from. Changes are kept for configured time only, and if some after
+/// <since> were removed, response is 410 with code 'err_changes_expired', meaning that the data should
+/// be reloaded (see `get_entry_changes_head`). For this endpoint you must provide namespace (url argument <namespace> or header
+/// "X-Namespace", of type <String>), and optionally a page size (url argument <page_size> or header
+/// "X-PAGE-SIZE", of type unsigned 16-bit integer).
+#[get("/changes?<since>")]
+pub async fn get_entry_changes(namespace: Namespace, _key: Scoped<Read>, since: Option<u64>, page_size: PageSize, conn: ApiDatabase) -> Linked {
+    let namespace_copy = namespace.0.clone();
+    let (since, size) = (since.unwrap_or(0), page_size.0);
+
+    let (data, has_more) = match conn.run(move |c| LoggedChange::read(c, &namespace.0, since, size)).await {
+        Ok(result) => result,
+        Err(e) => return Linked { inner: e.in_namespace(&namespace_copy), links: vec![] },
+    };
+
+    let next_since = data.last().map_or(since, |change| change.seq);
+    let mut links = vec![];
+    if has_more {
+        links.push(("next", "since", next_since.to_string()));
+    }
+
+    Linked {
+        inner: CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "since": since,
+            "next_since": next_since,
+            "has_more": has_more,
+            "data": data,
+        })),
+        links,
+    }
+}
+
+
+/// This endpoint is used to receive the current position of the change log of the namespace: the
+/// sequence number of its last change ("head", 0 if there was none). Client which (re)loads the data
+/// should read it first, and then replay changes after it (see `get_entry_changes`): changes which
+/// are already in the loaded data are replayed again, but none is missed. For this endpoint you must
+/// provide namespace (url argument <namespace> or header "X-Namespace", of type <String>).
+#[get("/changes/head")]
+pub async fn get_entry_changes_head(namespace: Namespace, _key: Scoped<Read>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| LoggedChange::head(c, &namespace.0)).await {
+        Ok(head) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "head": head,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
 /// This endpoint is used to create an entry from your data. Body of the request must be a valid
 /// JSON object*, so it can be recongnized by handler and interpreted for further dumping/loading.
 /// For this endpoint you must provide namespace (url argument <namespace> or header "X-Namespace",
 /// of type <String>). In addition to message code and message, correct response will contain ID of
 /// the created entry.
 ///
+/// Request may have "Idempotency-Key" header, then retries with the same key and body get the
+/// original response (with "Idempotency-Replayed: true" header) instead of creating another entry,
+/// and requests with the same key but another body get 409 with code 'err_idempotency_key_reused'.
+///
 /// * - Note, to allow storing multiple entries with single request, this handler ignores data that
 ///     looks like JSON array (see next handler).
 #[post("/", format = "application/json", data = "<entry>", rank = 1)]
-pub async fn create_one_entry(namespace: Namespace, entry: Entry, conn: ApiDatabase) -> JsonValue {
-    json!({
-        "code": "info_one_item_ok",
-        "message": "Successfully created new entry!",
-        "item_id": conn.run(move |c| entry.insert(c, namespace.0)).await
-    })
+pub async fn create_one_entry(namespace: Namespace, _key: Scoped<Write>, idempotency: IdempotencyKey, entry: Entry, conn: ApiDatabase) -> Idempotent {
+    let request = format!("create_one_entry\n{}", entry.0);
+
+    let result = conn.run(move |c| idempotency.run(c, &namespace.0, &request, |tx| {
+        let id = entry.insert(tx, namespace.0.clone())?;
+        Ok(json!({
+            "code": "info_one_item_ok",
+            "message": "Successfully created new entry!",
+            "item_id": id
+        }))
+    })).await;
+
+    match result {
+        Ok((body, replayed)) => Idempotent { inner: CustomResponder::Ok(body), replayed },
+        Err(e) => CustomResponder::from(e).into(),
+    }
 }
 
 
@@ -96,19 +441,167 @@ pub async fn create_one_entry(namespace: Namespace, entry: Entry, conn: ApiDatab
 /// of entries to create. For this endpoint you must provide namespace (url argument <namespace>
 /// or header "X-Namespace", of type <String>). In addition to message code and message, correct
 /// response will contain a list of IDs of created entries.
-#[post("/", format = "application/json", data = "<entries>", rank = 2)]
-pub async fn create_many_entries(namespace: Namespace, entries: Entry, conn: ApiDatabase) -> JsonValue {
-    json!({
-        "code": "info_many_items_ok",
-        "message": "Successfully created multiple entries!",
-        "item_ids": conn.run(
-            // We know for sure that this JSON value is an array of something.
-            move |c| entries.0.as_array().unwrap()
-                .iter()
-                .map(|entry| Entry::insert_raw(c, namespace.0.clone(), entry))
-                .collect::<Vec<u64>>()
-        ).await
-    })
+///
+/// Entries are inserted in a single transaction, so if any of them is rejected by the database,
+/// nothing is created. To insert as many entries as possible instead, provide url argument
+/// <partial> set to "true": then response will contain result for each entry, in the same order,
+/// either with ID of created entry or with an error message. "Idempotency-Key" header is handled
+/// the same way as for a single entry.
+#[post("/?<partial>", format = "application/json", data = "<entries>", rank = 2)]
+pub async fn create_many_entries(namespace: Namespace, _key: Scoped<Write>, partial: Option<bool>, idempotency: IdempotencyKey, entries: Entry, conn: ApiDatabase) -> Idempotent {
+    let partial = partial.unwrap_or(false);
+    let request = format!("create_many_entries\npartial={}\n{}", partial, entries.0);
+
+    // Arrays are routed here by the first byte of the body, but make sure it's still an array.
+    let entries = match entries.0 {
+        Value::Array(entries) => entries,
+        _ => return CustomResponder::BadRequest(json!({
+            "code": "err_entries_not_array",
+            "message": "Request body must be a JSON array of entries!",
+        })).into(),
+    };
+
+    let result = conn.run(move |c| idempotency.run(c, &namespace.0, &request, |tx| match partial {
+        true => {
+            let results = Entry::insert_each(tx, namespace.0.clone(), &entries)?;
+            let inserted = results.iter().filter(|result| result.is_ok()).count();
+            Ok(json!({
+                "code": "info_many_items_partial",
+                "message": format!("Created {} out of {} entries!", inserted, results.len()),
+                "inserted": inserted,
+                "failed": results.len() - inserted,
+                "results": results.into_iter().enumerate().map(|(index, result)| match result {
+                    Ok(id) => json!({ "index": index, "id": id }),
+                    Err(message) => json!({ "index": index, "error": message }),
+                }).collect::<Vec<JsonValue>>(),
+            }))
+        },
+        false => {
+            let ids = Entry::insert_many(tx, namespace.0.clone(), &entries)?;
+            Ok(json!({
+                "code": "info_many_items_ok",
+                "message": "Successfully created multiple entries!",
+                "item_ids": ids
+            }))
+        },
+    })).await;
+
+    match result {
+        Ok((body, replayed)) => Idempotent { inner: CustomResponder::Ok(body), replayed },
+        // Pointers of the violations are relative to the whole array.
+        Err(e @ ApiError::SchemaViolation(_)) | Err(e @ ApiError::IdempotencyConflict(_)) => CustomResponder::from(e).into(),
+        Err(e) if partial => CustomResponder::UnknownError(json!({
+            "code": "err_bulk_insert_failed",
+            "message": e.to_string(),
+        })).into(),
+        // Some of the entries can't be stored (e.g. string contains null character), so it's client's fault.
+        Err(ApiError::Rejected(e)) => CustomResponder::BadRequest(json!({
+            "code": "err_bulk_insert_rejected",
+            "message": format!("Entries were rejected by the database with error: '{}', nothing was created!", e),
+        })).into(),
+        Err(e) => CustomResponder::UnknownError(json!({
+            "code": "err_bulk_insert_failed",
+            "message": format!("{} Nothing was created!", e),
+        })).into(),
+    }
+}
+
+
+// Amount of lines which are read from NDJSON body and inserted at once.
+const INGEST_BATCH_SIZE: usize = 1000;
+// Rejected lines are listed in the response only up to this amount (but all are counted).
+const MAX_REPORTED_REJECTIONS: usize = 1000;
+
+
+/// This endpoint is used to create a lot of entries from newline-delimited JSON (one entry per
+/// line, content type "application/x-ndjson"). Body is streamed and inserted in batches, so it
+/// may be much bigger than what array endpoint accepts (by default up to 1GB, use header
+/// "X-Content-Length" to change this). For this endpoint you must provide namespace (url argument
+/// <namespace> or header "X-Namespace", of type <String>). Lines which can't be parsed or stored
+/// are rejected and the rest is created, so response contains amounts of inserted and rejected
+/// lines, ranges of IDs of created entries ([first, last], inclusive) and numbers of rejected
+/// lines (starting from 1) with the errors. Body which is longer than the limit is rejected with
+/// 413 and code 'err_buffer_too_large', and the same summary of entries created before that.
+#[post("/", format = "application/x-ndjson", data = "<body>", rank = 3)]
+pub async fn ingest_entries(namespace: Namespace, _key: Scoped<Write>, limit: BodyLimit, body: Data, conn: ApiDatabase) -> CustomResponder {
+    let mut reader = NdjsonReader::new(body, limit.0);
+    let (mut inserted, mut rejected) = (0u64, 0u64);
+    let mut id_ranges: Vec<(u64, u64)> = vec![];
+    let mut rejected_lines = vec![];
+
+    // Summary is built the same way for successful and failed ingestion.
+    macro_rules! summary {
+        ($code:expr, $message:expr) => (json!({
+            "code": $code,
+            "message": $message,
+            "namespace": &namespace.0,
+            "inserted": inserted,
+            "rejected": rejected,
+            "id_ranges": &id_ranges,
+            "rejected_lines": &rejected_lines,
+        }))
+    }
+
+    loop {
+        let batch = match reader.next_batch(INGEST_BATCH_SIZE).await {
+            Ok(batch) if batch.is_empty() => break,
+            Ok(batch) => batch,
+            Err(_) if reader.overflowed() => return CustomResponder::PayloadTooLarge(summary!(
+                "err_buffer_too_large",
+                format!("Request body is too large, entries before the last batch were created! Accepted size is {} bytes, \
+                    consider using X-Content-Length header to set expected buffer size.", limit.0.as_u64())
+            )),
+            Err(e) => return CustomResponder::BadRequest(summary!(
+                "err_request_body_read",
+                format!("Couldn't read request body with error: '{}', entries before the error were created!", e)
+            )),
+        };
+
+        let (lines, values): (Vec<u64>, Vec<Value>) = batch.into_iter()
+            .filter_map(|(line, value)| match value {
+                Ok(value) => Some((line, value)),
+                Err(error) => {
+                    rejected += 1;
+                    if rejected_lines.len() < MAX_REPORTED_REJECTIONS {
+                        rejected_lines.push(json!({ "line": line, "error": error }));
+                    }
+                    None
+                }
+            })
+            .unzip();
+
+        let ns = namespace.0.clone();
+        let results = match conn.run(move |c| Entry::insert_each(c, ns, &values)).await {
+            Ok(results) => results,
+            Err(e) => return CustomResponder::UnknownError(summary!(
+                "err_bulk_insert_failed",
+                format!("{} Entries before the error were created!", e)
+            )),
+        };
+
+        for (line, result) in lines.into_iter().zip(results) {
+            match result {
+                Ok(id) => {
+                    inserted += 1;
+                    match id_ranges.last_mut() {
+                        Some((_, last)) if *last + 1 == id => *last = id,
+                        _ => id_ranges.push((id, id)),
+                    }
+                },
+                Err(error) => {
+                    rejected += 1;
+                    if rejected_lines.len() < MAX_REPORTED_REJECTIONS {
+                        rejected_lines.push(json!({ "line": line, "error": error }));
+                    }
+                }
+            }
+        }
+    }
+
+    CustomResponder::Ok(summary!(
+        "info_ndjson_ingest_ok",
+        format!("Created {} entries, rejected {} lines!", inserted, rejected)
+    ))
 }
 
 
@@ -116,15 +609,212 @@ pub async fn create_many_entries(namespace: Namespace, entries: Entry, conn: Api
 /// be a valid JSON objects, so it can be recongnized by handler and interpreted for further
 /// dumping/loading. For this endpoint you must provide namespace (url argument <namespace>
 /// or header "X-Namespace", of type <String>). In addition to message code and message, correct
-/// response will contain ID of the put entry.
+/// response will contain ID of the put entry, and "ETag" header with its new version. With "If-Match"
+/// header entry is only replaced if it exists and its current ETag matches, otherwise response is
+/// 412 with code 'err_entry_precondition_failed'. Entry which belongs to another namespace is not
+/// replaced, response is 409 with code 'err_entry_namespace_conflict' (see `move_entry_by_id`), even
+/// if the key can't read that namespace (so the ID can be told apart from a free one, not its content).
 #[put("/<id>", format = "application/json", data = "<entry>")]
-pub async fn update_entry_by_id(id: u64, namespace: Namespace, entry: Entry, conn: ApiDatabase) -> JsonValue {
-    // TODO: This should return an error if the object exists but namespace is different, instead of updating (?).
-    json!({
-        "code": "info_put_item_ok",
-        "message": "Successfully updated/created entry!",
-        "item_id": conn.run(move |c| entry.put(c, id, namespace.0)).await
-    })
+pub async fn update_entry_by_id(id: u64, namespace: Namespace, _key: Scoped<Write>, preconditions: Preconditions, entry: Entry, conn: ApiDatabase) -> Tagged {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| entry.put(c, id, namespace.0, preconditions.if_match.as_ref())).await {
+        Ok((id, version)) => Tagged {
+            inner: CustomResponder::Ok(json!({
+                "code": "info_put_item_ok",
+                "message": "Successfully updated/created entry!",
+                "item_id": id
+            })),
+            etag: Some(preconditions::etag(version)),
+        },
+        Err(e) => e.in_namespace(&namespace_copy).into(),
+    }
+}
+
+
+/// This endpoint is used to move entry with certain ID to another namespace, keeping its ID. Every
+/// move is recorded in the audit log (see `get_entry_moves`) together with the name of the API key.
+/// Content of the entry must conform to JSON Schema of the target namespace, if there is one, and
+/// "If-Match" header is honored the same way as when entry is put. For this endpoint you must provide
+/// namespace (url argument <namespace> or header "X-Namespace", of type <String>) and target namespace
+/// (url argument <target> or header "X-Target-Namespace", of type <String>), and a key with delete
+/// scope for the namespace and write scope for the target. Correct response will contain the moved entry.
+#[post("/<id>/move")]
+pub async fn move_entry_by_id(id: u64, namespace: Namespace, target: TargetNamespace, key: Scoped<Delete>, preconditions: Preconditions, conn: ApiDatabase) -> Tagged {
+    let namespace_copy = namespace.0.clone();
+
+    if namespace.0 == target.0 {
+        return CustomResponder::BadRequest(json!({
+            "code": "err_namespace_same",
+            "message": "Source and target namespaces must be different!",
+            "namespace": &namespace_copy,
+        })).into();
+    }
+    if !key.allows_scope::<Write>(&target.0) {
+        return CustomResponder::Forbidden(json!({
+            "code": "err_api_key_scope",
+            "message": format!("API key doesn't grant 'write' scope for namespace '{}'!", &target.0),
+        })).into();
+    }
+
+    let moved_by = key.key.as_ref().map(|key| key.name.clone());
+    match conn.run(move |c| Entry::move_one(c, id, namespace.0, target.0, moved_by, preconditions.if_match.as_ref())).await {
+        Ok(entry) => Tagged {
+            etag: Some(preconditions::etag(entry.version)),
+            inner: CustomResponder::Ok(json!({
+                "code": "info_move_item_ok",
+                "message": format!("Successfully moved entry of ID '{}' from namespace '{}'!", id, &namespace_copy),
+                "item_id": id,
+                "data": entry,
+            })),
+        },
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy).into(),
+    }
+}
+
+
+/// This endpoint is used to receive the audit log of moves of the entry with certain ID between
+/// namespaces, from the oldest one. Each record contains source and target namespaces, name of the
+/// API key and time of the move. For this endpoint you must provide namespace (url argument <namespace>
+/// or header "X-Namespace", of type <String>), which the entry belongs to now.
+#[get("/<id>/moves")]
+pub async fn get_entry_moves(id: u64, namespace: Namespace, key: Scoped<Read>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Entry::moves(c, id, namespace.0)).await {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "id": id,
+            "data": data,
+        })),
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to receive revisions of the entry with certain ID (url path /<id> of type
+/// unsigned 64-bit integer), from the oldest one. Every write of the entry makes a new revision, which
+/// is an object with its number (starting from 1), namespace, version and the period during which it
+/// was current, example: {"revision": 2, "namespace": "a", "version": 17, "valid_from": "...",
+/// "valid_to": null}. Content is not included, see the endpoint below. For this endpoint you must
+/// provide namespace (url argument <namespace> or header "X-Namespace", of type <String>).
+#[get("/<id>/revisions")]
+pub async fn get_entry_revisions(id: u64, namespace: Namespace, key: Scoped<Read>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Entry::revisions(c, id, namespace.0)).await {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "id": id,
+            "data": data,
+        })),
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to receive a single revision (url path /<revision> of type unsigned 32-bit
+/// integer) of the entry with certain ID, together with its content. If there is no such revision,
+/// response is 404 with code 'err_revision_not_found'. For this endpoint you must provide namespace
+/// (url argument <namespace> or header "X-Namespace", of type <String>).
+#[get("/<id>/revisions/<revision>")]
+pub async fn get_entry_revision(id: u64, revision: u32, namespace: Namespace, key: Scoped<Read>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Entry::revision(c, id, &namespace.0, revision)).await {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "id": id,
+            "data": data,
+        })),
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to compare two revisions of the entry with certain ID (url arguments <from>
+/// and <to>, of type unsigned 32-bit integer). Response contains JSON Patch (RFC 6902) which turns
+/// content of the first revision into content of the second one, example: {"from": 1, "to": 3,
+/// "patch": [{"op": "replace", "path": "/status", "value": "ok"}]}. For this endpoint you must provide
+/// namespace (url argument <namespace> or header "X-Namespace", of type <String>).
+#[get("/<id>/revisions/diff?<from>&<to>")]
+pub async fn diff_entry_revisions(id: u64, from: u32, to: u32, namespace: Namespace, key: Scoped<Read>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    let result = conn.run(move |c| -> Result<_, ApiError> {
+        let old = Entry::revision(c, id, &namespace.0, from)?;
+        let new = Entry::revision(c, id, &namespace.0, to)?;
+        Ok(patch::diff(&old.content.unwrap_or_default(), &new.content.unwrap_or_default()))
+    }).await;
+
+    match result {
+        Ok(operations) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "id": id,
+            "from": from,
+            "to": to,
+            "patch": operations,
+        })),
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to restore content of the entry with certain ID from its older revision (url
+/// path /<revision> of type unsigned 32-bit integer). Restored content becomes a new revision, so
+/// nothing is lost, and it must conform to JSON Schema of the namespace, if there is one. "If-Match"
+/// header is honored the same way as when entry is put. For this endpoint you must provide namespace
+/// (url argument <namespace> or header "X-Namespace", of type <String>). Correct response will contain
+/// the restored entry.
+#[post("/<id>/revisions/<revision>/restore")]
+pub async fn restore_entry_revision(id: u64, revision: u32, namespace: Namespace, key: Scoped<Write>, preconditions: Preconditions, conn: ApiDatabase) -> Tagged {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Entry::restore(c, id, namespace.0, revision, preconditions.if_match.as_ref())).await {
+        Ok(entry) => Tagged {
+            etag: Some(preconditions::etag(entry.version)),
+            inner: CustomResponder::Ok(json!({
+                "code": "info_restore_item_ok",
+                "message": format!("Successfully restored entry of ID '{}' from revision '{}'!", id, revision),
+                "item_id": id,
+                "data": entry,
+            })),
+        },
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy).into(),
+    }
+}
+
+
+/// This endpoint is used to partially update existing entry with certain ID. Body of the request is
+/// either JSON Merge Patch (RFC 7396, content type "application/merge-patch+json"), which is merged
+/// into the content, or JSON Patch (RFC 6902, content type "application/json-patch+json"), which is a
+/// list of operations (add, remove, replace, move, copy and test). Patch is applied atomically: entry
+/// is locked while it's patched, and nothing is changed if any operation fails. Failed 'test'
+/// operation is reported with 409 'err_patch_test', and path which doesn't exist with 422
+/// 'err_patch_path', both with index of the operation. For this endpoint you must provide namespace
+/// (url argument <namespace> or header "X-Namespace", of type <String>). Correct response will
+/// contain the patched entry and "ETag" header with its new version. "If-Match" header is honored
+/// the same way as when entry is put.
+#[patch("/<id>", data = "<patch>")]
+pub async fn patch_entry_by_id(id: u64, namespace: Namespace, key: Scoped<Write>, preconditions: Preconditions, patch: Patch, conn: ApiDatabase) -> Tagged {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Entry::patch(c, id, namespace.0, &patch, preconditions.if_match.as_ref())).await {
+        Ok(entry) => Tagged {
+            etag: Some(preconditions::etag(entry.version)),
+            inner: CustomResponder::Ok(json!({
+                "code": "info_patch_item_ok",
+                "message": "Successfully patched entry!",
+                "item_id": id,
+                "data": entry,
+            })),
+        },
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy).into(),
+    }
 }
 
 
@@ -133,38 +823,41 @@ pub async fn update_entry_by_id(id: u64, namespace: Namespace, entry: Entry, con
 /// addition to message code and message, correct response will contain namespace itself and total
 /// amount of deleted entries.
 #[delete("/")]
-pub async fn delete_all_entries(namespace: Namespace, conn: ApiDatabase) -> JsonValue {
-    json!({
-        "code": "info_delete_entries_ok",
-        "message": format!("Successfully deleted all entries for namespace '{}'!", &namespace.0),
-        "namespace": &namespace.0,
-        "amount": conn.run(|c| Entry::delete_all(c, namespace.0)).await
-    })
+pub async fn delete_all_entries(namespace: Namespace, _key: Scoped<Delete>, conn: ApiDatabase) -> CustomResponder {
+    // @Robustness: This copy is required due to the way we handle errors right now.
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(|c| Entry::delete_all(c, namespace.0)).await {
+        Ok(amount) => CustomResponder::Ok(json!({
+            "code": "info_delete_entries_ok",
+            "message": format!("Successfully deleted all entries for namespace '{}'!", &namespace_copy),
+            "namespace": &namespace_copy,
+            "amount": amount
+        })),
+        Err(e) => e.into(),
+    }
 }
 
 
 /// This endpoint is used to delete single entry by ID of certain namespace. For this endpoint you
 /// must provide ID (url argument <id> of type unsigned 64-bit integer) namespace (url argument
 /// <namespace> or header "X-Namespace", of type <String>). In addition to message code and message,
-/// correct response will contain namespace itself and ID of the deleted entry.
+/// correct response will contain namespace itself and ID of the deleted entry. Missing entries are
+/// reported the same way as for the endpoint which receives single entry. "If-Match" header is
+/// honored the same way as when entry is put.
 #[delete("/<id>")]
-pub async fn delete_entry_by_id(namespace: Namespace, id: u64, conn: ApiDatabase) -> CustomResponder {
+pub async fn delete_entry_by_id(namespace: Namespace, key: Scoped<Delete>, id: u64, preconditions: Preconditions, conn: ApiDatabase) -> CustomResponder {
     // @Robustness: This copy is required due to the way we handle errors right now.
     let namespace_copy = namespace.0.clone();
 
-    match conn.run(move |c| Entry::delete_one(c, id, namespace.0)).await {
+    match conn.run(move |c| Entry::delete_one(c, id, namespace.0, preconditions.if_match.as_ref())).await {
         Ok(id) => CustomResponder::Ok(json!({
             "code": "info_delete_entry_ok",
             "message": format!("Successfully deleted an entry of ID '{}' for namespace '{}'!", &id, &namespace_copy),
             "namespace": &namespace_copy,
             "id": id,
         })),
-        Err(id) => CustomResponder::BadRequest(json!({
-            "code": "error_sql_get_one_by_id",
-            "message": format!("Entry with ID '{}' does not exist!", id),
-            "namespace": &namespace_copy,
-            "id": id,
-        }))
+        Err(e) => key.conceal(e).in_namespace(&namespace_copy),
     }
 }
 
@@ -186,3 +879,96 @@ pub fn handle_bad_request_errors(req: &Request) -> CustomResponder {
     }
 }
 
+
+
+/// Same as above, but for 404 http code errors: no route matched the request, e.g. because ID is not
+/// a number or required url argument (like <page>) is missing or has a bad value.
+#[catch(404)]
+pub fn handle_not_found_errors(req: &Request) -> CustomResponder {
+    match req.local_cache(|| ErrorMessage(None)) {
+        ErrorMessage(Some(v)) => CustomResponder::NotFound(v.clone()),
+        ErrorMessage(None) => CustomResponder::NotFound(json!({
+            "code":    "err_not_found",
+            "message": format!("Nothing matches '{} {}'! Make sure that url and its arguments are correct.", req.method(), req.uri()),
+        }))
+    }
+}
+
+
+/// Same as above, but for 422 http code errors: request body is well-formed but can't be processed.
+#[catch(422)]
+pub fn handle_unprocessable_entity_errors(req: &Request) -> CustomResponder {
+    match req.local_cache(|| ErrorMessage(None)) {
+        ErrorMessage(Some(v)) => CustomResponder::UnprocessableEntity(v.clone()),
+        ErrorMessage(None) => CustomResponder::UnprocessableEntity(json!({
+            "code":    "err_unprocessable_entity",
+            "message": "Request body is well-formed, but couldn't be processed!",
+        }))
+    }
+}
+
+
+/// Same as above, but for 500 http code errors, e.g. when a handler panicked. Details aren't exposed
+/// to the client, they are in the server logs.
+#[catch(500)]
+pub fn handle_internal_errors(req: &Request) -> CustomResponder {
+    match req.local_cache(|| ErrorMessage(None)) {
+        ErrorMessage(Some(v)) => CustomResponder::UnknownError(v.clone()),
+        ErrorMessage(None) => CustomResponder::UnknownError(json!({
+            "code":    "err_internal_error",
+            "message": "Some internal error occured! Please, report the bug by filing an issue.",
+        }))
+    }
+}
+
+
+/// Same as above, but for 503 http code errors, e.g. when database connection couldn't be acquired.
+#[catch(503)]
+pub fn handle_unavailable_errors(req: &Request) -> CustomResponder {
+    match req.local_cache(|| ErrorMessage(None)) {
+        ErrorMessage(Some(v)) => CustomResponder::Unavailable(v.clone()),
+        ErrorMessage(None) => CustomResponder::Unavailable(json!({
+            "code":    "err_service_unavailable",
+            "message": "Service is temporarily unavailable, please try again later!",
+        }))
+    }
+}
+
+
+/// Same as above, but for 401 http code errors: API key is missing or invalid.
+#[catch(401)]
+pub fn handle_unauthorized_errors(req: &Request) -> CustomResponder {
+    match req.local_cache(|| ErrorMessage(None)) {
+        ErrorMessage(Some(v)) => CustomResponder::Unauthorized(v.clone()),
+        ErrorMessage(None) => CustomResponder::Unauthorized(json!({
+            "code":    "err_unauthorized",
+            "message": "Request is not authenticated!",
+        }))
+    }
+}
+
+
+/// Same as above, but for 403 http code errors: API key doesn't grant access to the resource.
+#[catch(403)]
+pub fn handle_forbidden_errors(req: &Request) -> CustomResponder {
+    match req.local_cache(|| ErrorMessage(None)) {
+        ErrorMessage(Some(v)) => CustomResponder::Forbidden(v.clone()),
+        ErrorMessage(None) => CustomResponder::Forbidden(json!({
+            "code":    "err_forbidden",
+            "message": "Access to the resource is not allowed!",
+        }))
+    }
+}
+
+
+/// Same as above, but for 415 http code errors: request body has unsupported content type.
+#[catch(415)]
+pub fn handle_unsupported_media_type_errors(req: &Request) -> CustomResponder {
+    match req.local_cache(|| ErrorMessage(None)) {
+        ErrorMessage(Some(v)) => CustomResponder::UnsupportedMediaType(v.clone()),
+        ErrorMessage(None) => CustomResponder::UnsupportedMediaType(json!({
+            "code":    "err_unsupported_media_type",
+            "message": "Content type of the request body is not supported!",
+        }))
+    }
+}
@@ -1,6 +1,195 @@
+use rocket_contrib::databases::postgres;
+use crate::responders::CustomResponder;
+use crate::patch::{PatchError, PatchErrorKind};
+use crate::schema::Violation;
 use rocket_contrib::json::JsonValue;
+use std::fmt;
 
 
 /// Struct to hold any json-like error message.
 pub struct ErrorMessage(pub Option<JsonValue>);
 
+
+/// Error of the model layer. Every variant maps to a JSON response (see `CustomResponder`
+/// conversion below), so handlers can just pass it to the client.
+#[derive(Debug)]
+pub enum ApiError {
+    /// Entry with the ID doesn't exist.
+    NotFound(u64),
+    /// Entry with the ID exists, but belongs to another namespace (the second field). Clients
+    /// which can't read that namespace must get `NotFound` instead (see `Scoped::conceal`).
+    NamespaceMismatch(u64, String),
+    /// Entry with the ID belongs to another namespace, so it can't be replaced.
+    EntryNamespaceConflict(u64),
+    /// Version of the entry with the ID doesn't satisfy "If-Match" header.
+    PreconditionFailed(u64),
+    /// Entry with the ID doesn't have revision with the number.
+    RevisionNotFound(u64, u32),
+    /// API key with the ID doesn't exist.
+    KeyNotFound(u64),
+    /// Webhook with the ID doesn't exist in the namespace.
+    WebhookNotFound(u64),
+    /// Namespace has neither entries nor metadata.
+    NamespaceNotFound(String),
+    /// Namespace must not exist for the operation (e.g. it's the new name of a namespace).
+    NamespaceConflict(String),
+    /// Changes after the requested position were removed from the change log, the number is
+    /// the last removed one.
+    ChangesExpired(u64),
+    /// Idempotency key was already used with a different request.
+    IdempotencyConflict(String),
+    /// Document doesn't conform to the JSON Schema of the namespace.
+    SchemaViolation(Vec<Violation>),
+    /// JSON Patch couldn't be applied to the content of the entry.
+    Patch(PatchError),
+    /// Database refused to store provided data, e.g. string contains null character.
+    Rejected(postgres::Error),
+    /// Database can't be reached: connection was lost, server is shutting down, etc.
+    Unavailable(postgres::Error),
+    /// Any other database failure, e.g. query failed.
+    Database(postgres::Error),
+    /// Stored row couldn't be read into a response.
+    Malformed(String),
+}
+
+
+impl From<postgres::Error> for ApiError {
+    fn from(e: postgres::Error) -> Self {
+        let code = e.code().map(|state| state.code().to_string()).unwrap_or_default();
+        match code.as_str() {
+            // Class 22 is "data exception", which means that the value itself can't be stored.
+            c if c.starts_with("22") => ApiError::Rejected(e),
+            // Class 08 is "connection exception", and 57P01-57P03 mean that server is shutting
+            // down or is not accepting connections yet.
+            c if c.starts_with("08") || c == "57P01" || c == "57P02" || c == "57P03" => ApiError::Unavailable(e),
+            _ if e.is_closed() => ApiError::Unavailable(e),
+            _ => ApiError::Database(e),
+        }
+    }
+}
+
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound(id) => write!(f, "Entry with ID '{}' does not exist!", id),
+            ApiError::NamespaceMismatch(id, _) => write!(f, "Entry with ID '{}' belongs to another namespace!", id),
+            ApiError::EntryNamespaceConflict(id) => write!(f, "Entry with ID '{}' belongs to another namespace, it must be moved explicitly!", id),
+            ApiError::PreconditionFailed(id) => write!(f, "Entry with ID '{}' was changed, its ETag doesn't match 'If-Match'!", id),
+            ApiError::RevisionNotFound(id, revision) => write!(f, "Entry with ID '{}' has no revision '{}'!", id, revision),
+            ApiError::KeyNotFound(id) => write!(f, "API key with ID '{}' does not exist!", id),
+            ApiError::WebhookNotFound(id) => write!(f, "Webhook with ID '{}' does not exist!", id),
+            ApiError::NamespaceNotFound(name) => write!(f, "Namespace '{}' has no entries and no metadata!", name),
+            ApiError::NamespaceConflict(name) => write!(f, "Namespace '{}' already exists!", name),
+            ApiError::ChangesExpired(seq) => write!(f, "Changes up to '{}' were removed from the change log, data must be reloaded!", seq),
+            ApiError::IdempotencyConflict(key) => write!(f, "Idempotency key '{}' was already used with a different request!", key),
+            ApiError::SchemaViolation(_) => write!(f, "Document doesn't conform to the JSON Schema of the namespace!"),
+            ApiError::Patch(e) => write!(f, "{}", e),
+            ApiError::Rejected(e) => write!(f, "Data was rejected by the database with error: '{}'!", e),
+            ApiError::Unavailable(e) => write!(f, "Database is unavailable, error: '{}'!", e),
+            ApiError::Database(e) => write!(f, "Database request failed with error: '{}'!", e),
+            ApiError::Malformed(e) => write!(f, "Couldn't read stored entry with error: '{}'!", e),
+        }
+    }
+}
+
+
+impl ApiError {
+    /// Short code of the error, same as in all other JSON error responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "err_entry_not_found",
+            ApiError::NamespaceMismatch(..) => "err_entry_namespace_mismatch",
+            ApiError::EntryNamespaceConflict(_) => "err_entry_namespace_conflict",
+            ApiError::PreconditionFailed(_) => "err_entry_precondition_failed",
+            ApiError::RevisionNotFound(..) => "err_revision_not_found",
+            ApiError::KeyNotFound(_) => "err_api_key_not_found",
+            ApiError::WebhookNotFound(_) => "err_webhook_not_found",
+            ApiError::NamespaceNotFound(_) => "err_namespace_not_found",
+            ApiError::NamespaceConflict(_) => "err_namespace_conflict",
+            ApiError::ChangesExpired(_) => "err_changes_expired",
+            ApiError::IdempotencyConflict(_) => "err_idempotency_key_reused",
+            ApiError::SchemaViolation(_) => "err_schema_violation",
+            ApiError::Patch(e) if e.kind == PatchErrorKind::Test => "err_patch_test",
+            ApiError::Patch(_) => "err_patch_path",
+            ApiError::Rejected(_) => "err_database_rejected",
+            ApiError::Unavailable(_) => "err_database_unavailable",
+            ApiError::Database(_) => "err_database_error",
+            ApiError::Malformed(_) => "err_entry_malformed",
+        }
+    }
+
+    /// Same as conversion into `CustomResponder`, but adds namespace of the request to the response.
+    pub fn in_namespace(self, namespace: &str) -> CustomResponder {
+        self.respond(Some(namespace))
+    }
+
+    fn respond(self, namespace: Option<&str>) -> CustomResponder {
+        let mut body = json!({
+            "code":    self.code(),
+            "message": self.to_string(),
+        });
+        if let Some(namespace) = namespace {
+            body["namespace"] = namespace.into();
+        }
+
+        match self {
+            ApiError::NotFound(id) | ApiError::NamespaceMismatch(id, _) | ApiError::KeyNotFound(id) | ApiError::WebhookNotFound(id) => {
+                body["id"] = id.into();
+                CustomResponder::NotFound(body)
+            },
+            ApiError::RevisionNotFound(id, revision) => {
+                body["id"] = id.into();
+                body["revision"] = revision.into();
+                CustomResponder::NotFound(body)
+            },
+            ApiError::EntryNamespaceConflict(id) => {
+                body["id"] = id.into();
+                CustomResponder::Conflict(body)
+            },
+            ApiError::PreconditionFailed(id) => {
+                body["id"] = id.into();
+                CustomResponder::PreconditionFailed(body)
+            },
+            ApiError::NamespaceNotFound(name) => {
+                body["namespace"] = name.into();
+                CustomResponder::NotFound(body)
+            },
+            ApiError::NamespaceConflict(name) => {
+                body["target"] = name.into();
+                CustomResponder::Conflict(body)
+            },
+            ApiError::ChangesExpired(seq) => {
+                body["pruned_seq"] = seq.into();
+                CustomResponder::Gone(body)
+            },
+            ApiError::IdempotencyConflict(key) => {
+                body["key"] = key.into();
+                CustomResponder::Conflict(body)
+            },
+            ApiError::Patch(e) => {
+                body["index"] = e.index.into();
+                body["pointer"] = e.pointer.into();
+                match e.kind {
+                    // Patch is fine, but entry was changed since client looked at it.
+                    PatchErrorKind::Test => CustomResponder::Conflict(body),
+                    PatchErrorKind::Path => CustomResponder::UnprocessableEntity(body),
+                }
+            },
+            ApiError::SchemaViolation(violations) => {
+                body["errors"] = serde_json::to_value(violations).unwrap_or_default();
+                CustomResponder::UnprocessableEntity(body)
+            },
+            ApiError::Rejected(_) => CustomResponder::BadRequest(body),
+            ApiError::Unavailable(_) => CustomResponder::Unavailable(body),
+            ApiError::Database(_) | ApiError::Malformed(_) => CustomResponder::UnknownError(body),
+        }
+    }
+}
+
+
+impl From<ApiError> for CustomResponder {
+    fn from(e: ApiError) -> Self {
+        e.respond(None)
+    }
+}
@@ -0,0 +1,433 @@
+use rocket::request::{Outcome, Request, FromRequest};
+use rocket::response::stream::Event;
+use rocket::tokio::sync::broadcast;
+use rocket::tokio::task::spawn_blocking;
+use rocket_contrib::databases::postgres;
+use rocket_contrib::databases::postgres::fallible_iterator::FallibleIterator;
+use crate::errors::ErrorMessage;
+use crate::filter::Filter;
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use serde::{Serialize, Deserialize};
+use serde_json::{from_str, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use std::thread;
+
+
+// Amount of recent events which are kept for clients reconnecting with "Last-Event-ID".
+const REPLAY_BUFFER_SIZE: usize = 10000;
+// Amount of events which may wait for a slow subscriber, before it starts to miss them.
+const CHANNEL_CAPACITY: usize = 1024;
+// Channel of Postgres notifications, which are sent by the trigger on entries table.
+const NOTIFY_CHANNEL: &str = "entry_changes";
+// How often listener checks whether the broker is still alive, when there are no notifications.
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(1);
+// Delay before listener reconnects to the database.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+
+/// What happened to the entry.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+
+impl ChangeKind {
+    /// Name of the event, as it's sent to the client.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+
+/// Change of a single entry.
+#[derive(Serialize, Clone, Debug)]
+pub struct ChangeEvent {
+    pub namespace: String,
+    pub kind:      ChangeKind,
+    pub id:        u64,
+    /// Content of the entry after the change. There is none for deleted entries, and for big
+    /// entries which were deleted before their content was read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content:   Option<Value>,
+}
+
+
+/// Event which is sent to subscribers: change of a single entry, or reset of the namespace, after
+/// a statement which changed too many entries to announce each of them (e.g. deletion of all
+/// entries, rename or merge). Events are numbered in the order they were published, and the number
+/// is sent as ID of the event (together with ID of the replica), so clients can resume after it.
+#[derive(Clone, Debug)]
+pub enum Published {
+    Change(u64, ChangeEvent),
+    Reset(u64, String),
+}
+
+
+impl Published {
+    pub fn seq(&self) -> u64 {
+        match self {
+            Published::Change(seq, _) | Published::Reset(seq, _) => *seq,
+        }
+    }
+
+    /// Checks whether subscriber of the namespace with the filter should get the event. Events
+    /// without content can't be checked, so they are sent regardless of the filter.
+    pub fn matches(&self, namespace: &str, filter: &Filter) -> bool {
+        match self {
+            Published::Change(_, change) => {
+                change.namespace == namespace && change.content.as_ref().map_or(true, |content| filter.matches(content))
+            },
+            Published::Reset(_, reset) => reset == namespace,
+        }
+    }
+
+    /// Formats the event for the event stream of the replica.
+    pub fn to_sse(&self, replica: &str) -> Event {
+        let event = match self {
+            Published::Change(_, change) => Event::data(serde_json::to_string(change).unwrap_or_default())
+                .event(change.kind.name()),
+            Published::Reset(_, namespace) => Event::data(json!({
+                "code": "info_stream_reset",
+                "message": "Many entries of the namespace were changed at once, data should be reloaded!",
+                "namespace": namespace,
+            }).to_string()).event("reset"),
+        };
+        event.id(EventId::format(replica, self.seq()))
+    }
+}
+
+
+// Recently published events, the oldest first.
+struct Recent {
+    last_seq: u64,
+    events:   VecDeque<Published>,
+}
+
+
+// State of the broker, which is shared with the listener.
+struct Inner {
+    replica: String,
+    sender:  broadcast::Sender<Published>,
+    recent:  Mutex<Recent>,
+}
+
+
+/// Publishes changes of entries to all subscribers of this process. Changes come from Postgres
+/// notifications (see `fairing`), so every replica gets all of them, whichever replica (or SQL
+/// client) made the write. Recent events are buffered, so subscribers which reconnect get events
+/// they have missed. Numbers of events are local to the replica, so IDs of events include random
+/// ID of the replica, and clients which reconnect to another one are reset.
+#[derive(Clone)]
+pub struct Broker(Arc<Inner>);
+
+
+/// Events which were published after "Last-Event-ID" of the subscriber, and receiver of the
+/// following ones (no event is in both).
+pub struct Subscription {
+    pub missed:   Vec<Published>,
+    /// Some events after "Last-Event-ID" are not buffered anymore, or the ID is unknown (e.g. it's
+    /// from before restart, or from another replica), so client should reload the data.
+    pub gap:      bool,
+    /// ID of the replica, which is a part of IDs of events.
+    pub replica:  String,
+    /// Number of the last published event when subscription was made.
+    pub last_seq: u64,
+    pub receiver: broadcast::Receiver<Published>,
+}
+
+
+impl Broker {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Broker(Arc::new(Inner {
+            replica: format!("{:08x}", rand::random::<u32>()),
+            sender,
+            recent:  Mutex::new(Recent { last_seq: 0, events: VecDeque::new() }),
+        }))
+    }
+
+    pub fn publish(&self, namespace: &str, kind: ChangeKind, id: u64, content: Option<Value>) {
+        self.send(|seq| Published::Change(seq, ChangeEvent { namespace: namespace.to_string(), kind, id, content }));
+    }
+
+    /// Publishes reset of the namespace, which means that its data should be reloaded.
+    pub fn publish_reset(&self, namespace: &str) {
+        self.send(|seq| Published::Reset(seq, namespace.to_string()));
+    }
+
+    fn send(&self, event: impl FnOnce(u64) -> Published) {
+        // Events are numbered and sent under the lock, so their order is the same everywhere.
+        let mut recent = self.0.recent.lock().unwrap_or_else(|e| e.into_inner());
+        recent.last_seq += 1;
+        let event = event(recent.last_seq);
+
+        if recent.events.len() == REPLAY_BUFFER_SIZE {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(event.clone());
+        // Error only means that nobody is subscribed right now.
+        let _ = self.0.sender.send(event);
+    }
+
+    pub fn subscribe(&self, last_event_id: Option<EventId>) -> Subscription {
+        let recent = self.0.recent.lock().unwrap_or_else(|e| e.into_inner());
+
+        let (missed, gap) = match last_event_id {
+            None => (vec![], false),
+            Some(last) if last.replica != self.0.replica => (vec![], true),
+            Some(EventId { seq: last, .. }) => {
+                let oldest = recent.events.front().map_or(recent.last_seq + 1, |event| event.seq());
+                let missed = recent.events.iter().filter(|event| event.seq() > last).cloned().collect();
+                (missed, last > recent.last_seq || last + 1 < oldest)
+            },
+        };
+
+        Subscription {
+            missed,
+            gap,
+            replica:  self.0.replica.clone(),
+            last_seq: recent.last_seq,
+            receiver: self.0.sender.subscribe(),
+        }
+    }
+}
+
+
+impl Default for Broker {
+    fn default() -> Self {
+        Broker::new()
+    }
+}
+
+
+// Payload of the notification. Content is missing for deleted entries, and when it's too big.
+// Statements which change many entries are announced as a reset of the namespace instead, with
+// payload {"reset": <namespace>}.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Notification {
+    Reset {
+        reset: String,
+    },
+    Change {
+        kind:      ChangeKind,
+        namespace: String,
+        id:        u64,
+        content:   Option<Value>,
+    },
+}
+
+
+// Connects to the database and subscribes to notifications.
+fn connect(url: &str) -> Result<postgres::Client, postgres::Error> {
+    let mut client = postgres::Client::connect(url, postgres::NoTls)?;
+    client.batch_execute(&format!("LISTEN {}", NOTIFY_CHANNEL))?;
+    Ok(client)
+}
+
+
+// Publishes received notifications. Content which didn't fit into notification is read in one
+// query, so it may be newer than the change (and it's missing if entry is already deleted).
+fn publish_batch(client: &mut postgres::Client, broker: &Broker, batch: Vec<Notification>) -> Result<(), postgres::Error> {
+    let ids = batch.iter()
+        .filter_map(|n| match n {
+            Notification::Change { kind, id, content: None, .. } if *kind != ChangeKind::Deleted => Some(*id as i64),
+            _ => None,
+        })
+        .collect::<Vec<i64>>();
+    let mut contents = HashMap::new();
+    if !ids.is_empty() {
+        for row in client.query("SELECT id, namespace, content FROM entries WHERE id = ANY($1)", &[&ids])? {
+            let key = (row.get::<_, i64>("id") as u64, row.get::<_, String>("namespace"));
+            contents.insert(key, row.get::<_, Value>("content"));
+        }
+    }
+
+    for n in batch {
+        let (kind, namespace, id, content) = match n {
+            Notification::Reset { reset } => {
+                broker.publish_reset(&reset);
+                continue;
+            },
+            Notification::Change { kind, namespace, id, content } => (kind, namespace, id, content),
+        };
+        let content = match (kind, content) {
+            (ChangeKind::Deleted, _) => None,
+            (_, Some(content)) => Some(content),
+            (_, None) => contents.get(&(id, namespace.clone())).cloned(),
+        };
+        broker.publish(&namespace, kind, id, content);
+    }
+    Ok(())
+}
+
+
+// Receives notifications until the broker is dropped (i.e. Rocket instance is gone) or the
+// connection fails. Returns None in the first case, so listener stops.
+fn receive(client: &mut postgres::Client, broker: &Weak<Inner>) -> Option<postgres::Error> {
+    loop {
+        let mut batch = vec![];
+        {
+            let mut notifications = client.notifications();
+            // Waits for the first one, and takes the rest which were already received.
+            let first = match notifications.timeout_iter(LISTEN_TIMEOUT).next() {
+                Ok(first) => first,
+                Err(e) => return Some(e),
+            };
+            let rest = match notifications.iter().collect::<Vec<_>>() {
+                Ok(rest) => rest,
+                Err(e) => return Some(e),
+            };
+
+            for notification in first.into_iter().chain(rest) {
+                match from_str::<Notification>(notification.payload()) {
+                    Ok(n) => batch.push(n),
+                    Err(e) => eprintln!("Skipped malformed entry notification '{}' with error: '{}'!", notification.payload(), e),
+                }
+            }
+        }
+
+        let broker = Broker(broker.upgrade()?);
+        if !batch.is_empty() {
+            if let Err(e) = publish_batch(client, &broker, batch) {
+                return Some(e);
+            }
+        }
+    }
+}
+
+
+// Runs in its own thread with its own connection (which is not from the pool, since it's never
+// returned). Connection is restored after failures, but notifications sent in between are lost.
+fn listen(url: String, mut client: Option<postgres::Client>, broker: Weak<Inner>) {
+    loop {
+        if let Some(mut c) = client.take() {
+            match receive(&mut c, &broker) {
+                Some(e) => eprintln!("Lost connection of entry notifications listener with error: '{}'!", e),
+                None => return,
+            }
+        }
+        if broker.upgrade().is_none() {
+            return;
+        }
+
+        thread::sleep(RECONNECT_DELAY);
+        client = match connect(&url) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                eprintln!("Failed to connect entry notifications listener with error: '{}'!", e);
+                None
+            }
+        };
+    }
+}
+
+
+/// Fairing which creates the broker of change events and starts the listener of Postgres
+/// notifications, which feeds it. Listener is connected during ignition, so no change made
+/// after the launch is missed.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Change Events", |rocket| async {
+        let url = match rocket.figment().extract_inner::<String>("databases.storage.url") {
+            Ok(url) => url,
+            Err(e) => {
+                eprintln!("Failed to read database url for change events with error: '{}'!", e);
+                return Err(rocket);
+            }
+        };
+
+        let connect_url = url.clone();
+        let client = spawn_blocking(move || connect(&connect_url)).await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result.map_err(|e| e.to_string()));
+        let client = match client {
+            Ok(client) => Some(client),
+            // Listener will keep trying in the background.
+            Err(e) => {
+                eprintln!("Failed to connect entry notifications listener with error: '{}'!", e);
+                None
+            },
+        };
+
+        let broker = Broker::new();
+        let weak = Arc::downgrade(&broker.0);
+        thread::spawn(move || listen(url, client, weak));
+        Ok(rocket.manage(broker))
+    })
+}
+
+
+// Allows a route to publish and subscribe to change events.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Broker {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        Outcome::Success(req.rocket().state::<Broker>().expect("Events fairing is not attached!").clone())
+    }
+}
+
+
+/// ID of the event: ID of the replica and number of the event, as "<replica>-<seq>".
+pub struct EventId {
+    pub replica: String,
+    pub seq:     u64,
+}
+
+
+impl EventId {
+    pub fn format(replica: &str, seq: u64) -> String {
+        format!("{}-{}", replica, seq)
+    }
+
+    // ID without replica (e.g. from older version) is parsed, but it won't match any replica.
+    fn parse(raw: &str) -> Result<EventId, std::num::ParseIntError> {
+        let (replica, seq) = raw.rsplit_once('-').unwrap_or(("", raw));
+        Ok(EventId { replica: replica.to_string(), seq: seq.parse::<u64>()? })
+    }
+}
+
+
+/// Value which allows to access "Last-Event-ID" header (or url argument <last_event_id>, since
+/// browsers can't add headers to the first request of an event stream), if it was provided.
+pub struct LastEventId(pub Option<EventId>);
+
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        let raw = match req.headers().get_one("Last-Event-ID") {
+            Some(value) => value.to_string(),
+            None => match req.query_value::<String>("last_event_id") {
+                Some(Ok(value)) => value,
+                _ => return Outcome::Success(LastEventId(None)),
+            }
+        };
+
+        match EventId::parse(raw.trim()) {
+            Ok(id) => Outcome::Success(LastEventId(Some(id))),
+            Err(e) => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_last_event_id",
+                    "message": format!("Couldn't parse Last-Event-ID with error: '{}'!", e)
+                }))));
+                // Forward to error catcher.
+                Outcome::Failure((Status::BadRequest, ()))
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use rocket::request::{Outcome, Request, FromRequest};
+use rocket::http::{ContentType, Status};
+use crate::model::EntryResponse;
+use crate::errors::ErrorMessage;
+use serde_json::{Map, Value};
+
+
+/// Format of the exported entries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Ndjson,
+    Json,
+    Csv,
+}
+
+
+impl ExportFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "ndjson" => Some(ExportFormat::Ndjson),
+            "json"   => Some(ExportFormat::Json),
+            "csv"    => Some(ExportFormat::Csv),
+            _        => None,
+        }
+    }
+
+    fn from_media_type(top: &str, sub: &str) -> Option<Self> {
+        match (top, sub) {
+            ("application", "x-ndjson") => Some(ExportFormat::Ndjson),
+            ("application", "json")     => Some(ExportFormat::Json),
+            ("text", "csv")             => Some(ExportFormat::Csv),
+            _                           => None,
+        }
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            ExportFormat::Ndjson => ContentType::new("application", "x-ndjson"),
+            ExportFormat::Json   => ContentType::JSON,
+            ExportFormat::Csv    => ContentType::CSV,
+        }
+    }
+}
+
+
+// Allows a route to access export format. It's read from the 'format' url argument, or is the
+// first supported media type from "Accept" header. By default entries are exported as NDJSON.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ExportFormat {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        match req.query_value::<&str>("format") {
+            Some(Ok(name)) => match ExportFormat::parse(name) {
+                Some(format) => Outcome::Success(format),
+                None => {
+                    // Store error message.
+                    req.local_cache(|| ErrorMessage(Some(json!({
+                        "code":    "err_export_format",
+                        "message": format!("Unknown export format '{}', expected one of: ndjson, json, csv!", name),
+                        "format":  name,
+                    }))));
+                    Outcome::Failure((Status::BadRequest, ()))
+                }
+            },
+            Some(Err(e)) => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_export_format",
+                    "message": format!("Couldn't read export format from url argument with error: '{}'!", e)
+                }))));
+                Outcome::Failure((Status::BadRequest, ()))
+            },
+            None => Outcome::Success(req.accept()
+                .and_then(|accept| accept.iter()
+                    .filter_map(|media| ExportFormat::from_media_type(media.top().as_str(), media.sub().as_str()))
+                    .next())
+                .unwrap_or(ExportFormat::Ndjson)),
+        }
+    }
+}
+
+
+/// Value which allows to access list of columns for CSV export. Columns are dotted paths into
+/// the content (same as in filters), separated by commas, e.g. "status,metrics.latency_ms".
+/// When nothing was provided, columns are taken from the first exported entries.
+#[derive(Debug, Clone, Default)]
+pub struct Columns(pub Option<Vec<String>>);
+
+
+// Allows a route to access CSV columns, if they were provided.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Columns {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        // Extract value from header or url.
+        let raw = match req.headers().get_one("X-Columns") {
+            Some(value) => value.to_string(),
+            None => match req.query_value::<String>("columns") {
+                Some(Ok(value)) => value,
+                Some(Err(e)) => {
+                    // Store error message.
+                    req.local_cache(|| ErrorMessage(Some(json!({
+                        "code":    "err_export_columns",
+                        "message": format!("Couldn't read columns from url argument with error: '{}'!", e)
+                    }))));
+                    return Outcome::Failure((Status::BadRequest, ()));
+                },
+                None => return Outcome::Success(Columns::default()),
+            }
+        };
+
+        let columns = raw.split(',').map(|column| column.trim().to_string()).collect::<Vec<String>>();
+        match columns.iter().find(|column| column.is_empty() || column.split('.').any(|segment| segment.is_empty())) {
+            Some(column) => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_export_columns",
+                    "message": format!("Column '{}' is malformed, it must be a non-empty list of keys separated by dots!", column),
+                    "column":  column,
+                }))));
+                Outcome::Failure((Status::BadRequest, ()))
+            },
+            None => Outcome::Success(Columns(Some(columns))),
+        }
+    }
+}
+
+
+// Flattens nested objects into dotted keys, e.g. {"a": {"b": 1}} becomes {"a.b": 1}. Arrays
+// and scalars are kept as they are. Content which is not an object is stored under "content".
+fn flatten(value: &Value, prefix: &str, output: &mut Map<String, Value>) {
+    match value {
+        Value::Object(fields) => for (key, value) in fields {
+            match prefix.is_empty() {
+                true => flatten(value, key, output),
+                false => flatten(value, &format!("{}.{}", prefix, key), output),
+            }
+        },
+        _ if prefix.is_empty() => { output.insert("content".to_string(), value.clone()); },
+        _ => { output.insert(prefix.to_string(), value.clone()); },
+    }
+}
+
+
+// Escapes single CSV field according to RFC 4180.
+fn csv_field(field: &str) -> String {
+    match field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+
+// Builds a single CSV line out of fields.
+fn csv_line<'a>(fields: impl Iterator<Item = &'a str>) -> String {
+    let mut line = fields.map(csv_field).collect::<Vec<String>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+
+/// Returns columns for CSV export of the given entries: all flattened keys in the order they
+/// appear first.
+pub fn csv_columns(entries: &[EntryResponse]) -> Vec<String> {
+    let mut columns: Vec<String> = vec![];
+    for entry in entries {
+        let mut fields = Map::new();
+        flatten(&entry.content, "", &mut fields);
+        for key in fields.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+    columns
+}
+
+
+/// Returns CSV header: entry metadata followed by the content columns.
+pub fn csv_header(columns: &[String]) -> String {
+    csv_line(["id", "created_at", "updated_at"].iter().copied().chain(columns.iter().map(|c| c.as_str())))
+}
+
+
+/// Returns CSV line which marks the export as broken: error code in place of the ID (so it can't
+/// be read as an entry) and the message after it.
+pub fn csv_error(code: &str, message: &str) -> String {
+    csv_line([code, message].iter().copied())
+}
+
+
+/// Returns CSV line of the entry. Strings are written as they are, missing values and nulls
+/// are empty, and everything else (numbers, booleans and arrays) is written as JSON.
+pub fn csv_row(entry: &EntryResponse, columns: &[String]) -> String {
+    let mut fields = Map::new();
+    flatten(&entry.content, "", &mut fields);
+
+    let values = columns.iter()
+        .map(|column| match fields.get(column) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+        })
+        .collect::<Vec<String>>();
+
+    let id = entry.id.to_string();
+    csv_line([id.as_str(), entry.created_at.as_str(), entry.updated_at.as_str()].iter().copied()
+        .chain(values.iter().map(|v| v.as_str())))
+}
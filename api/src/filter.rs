@@ -0,0 +1,237 @@
+use rocket::request::{Outcome, Request, FromRequest};
+use rocket_contrib::json::JsonValue;
+use crate::errors::ErrorMessage;
+use serde_json::{from_str, Value};
+use rocket::http::Status;
+
+
+/// Comparison which is applied to the value found at the path of predicate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    In,
+    Exists,
+    Contains,
+}
+
+
+impl Operator {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "eq"       => Some(Operator::Eq),
+            "ne"       => Some(Operator::Ne),
+            "lt"       => Some(Operator::Lt),
+            "lte"      => Some(Operator::Lte),
+            "gt"       => Some(Operator::Gt),
+            "gte"      => Some(Operator::Gte),
+            "in"       => Some(Operator::In),
+            "exists"   => Some(Operator::Exists),
+            "contains" => Some(Operator::Contains),
+            _          => None,
+        }
+    }
+}
+
+
+/// Single condition on the content of an entry, e.g. "value at 'metrics.latency_ms' is
+/// greater than 500". Path is a list of object keys (or array indexes) to follow.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    pub path:  Vec<String>,
+    pub op:    Operator,
+    pub value: Value,
+}
+
+
+/// Value which allows to access structured filter on entry content. Filter is a JSON object,
+/// where keys are dotted paths and values are objects of operators with their operands, e.g.
+/// {"status": {"eq": "failed"}, "metrics.latency_ms": {"gt": 500}}. All predicates must match.
+/// Empty filter (when nothing was provided) matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct Filter(pub Vec<Predicate>);
+
+
+impl Filter {
+    /// Parses filter from its JSON representation. On failure returns an error message
+    /// which is ready to be sent to the client.
+    pub fn parse(raw: &str) -> Result<Filter, JsonValue> {
+        let value = from_str::<Value>(raw).map_err(|e| json!({
+            "code":    "err_filter_parse",
+            "message": format!("Couldn't parse filter as JSON with error: '{}'!", e)
+        }))?;
+
+        Filter::from_value(&value)
+    }
+
+    /// Same as `Filter::parse`, but for filters which were already parsed as JSON (e.g. when
+    /// filter is a part of the request body).
+    pub fn from_value(value: &Value) -> Result<Filter, JsonValue> {
+        let fields = value.as_object().ok_or_else(|| json!({
+            "code":    "err_filter_parse",
+            "message": "Filter must be a JSON object of paths mapped to operators, \
+                e.g. {\"status\": {\"eq\": \"failed\"}}!"
+        }))?;
+
+        let mut predicates = vec![];
+        for (raw_path, operators) in fields {
+            let path = raw_path.split('.').map(|s| s.to_string()).collect::<Vec<String>>();
+            if path.iter().any(|segment| segment.is_empty()) {
+                return Err(json!({
+                    "code":    "err_filter_path",
+                    "message": format!("Filter path '{}' is malformed, it must be a non-empty list of keys separated by dots!", raw_path),
+                    "path":    raw_path,
+                }));
+            }
+
+            let operators = operators.as_object().ok_or_else(|| json!({
+                "code":    "err_filter_parse",
+                "message": format!("Filter value for path '{}' must be a JSON object of operators!", raw_path),
+                "path":    raw_path,
+            }))?;
+
+            for (name, value) in operators {
+                let op = Operator::parse(name).ok_or_else(|| json!({
+                    "code":     "err_filter_operator",
+                    "message":  format!("Unknown filter operator '{}', expected one of: eq, ne, lt, lte, gt, gte, in, exists, contains!", name),
+                    "path":     raw_path,
+                    "operator": name,
+                }))?;
+
+                let valid = match op {
+                    Operator::Eq | Operator::Ne => true,
+                    Operator::Lt | Operator::Lte | Operator::Gt | Operator::Gte => value.is_number() || value.is_string(),
+                    Operator::In => value.is_array(),
+                    Operator::Exists => value.is_boolean(),
+                    Operator::Contains => value.is_string() || value.is_array() || value.is_object(),
+                };
+
+                if !valid {
+                    return Err(json!({
+                        "code":     "err_filter_value",
+                        "message":  format!("Value '{}' is not allowed for filter operator '{}' (path '{}')!", value, name, raw_path),
+                        "path":     raw_path,
+                        "operator": name,
+                    }));
+                }
+
+                predicates.push(Predicate { path: path.clone(), op, value: value.clone() });
+            }
+        }
+
+        Ok(Filter(predicates))
+    }
+
+    /// Checks the content against the filter in memory, with the same semantics as the SQL
+    /// conditions on stored entries (e.g. when entries are not read from the database).
+    pub fn matches(&self, content: &Value) -> bool {
+        self.0.iter().all(|predicate| {
+            let target = predicate.path.iter().try_fold(content, |value, key| match value {
+                Value::Object(fields) => fields.get(key),
+                Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => None,
+            });
+
+            match (predicate.op, target) {
+                (Operator::Exists, target) => match predicate.value.as_bool() {
+                    Some(false) => target.is_none(),
+                    _           => target.is_some(),
+                },
+                // Missing paths are NULL, which never matches anything else.
+                (_, None) => false,
+                (Operator::Eq, Some(target)) => equal(target, &predicate.value),
+                (Operator::Ne, Some(target)) => !equal(target, &predicate.value),
+                (Operator::In, Some(target)) => predicate.value.as_array()
+                    .map_or(false, |values| values.iter().any(|value| equal(target, value))),
+                (Operator::Contains, Some(target)) => match (&predicate.value, target) {
+                    (Value::String(substring), Value::String(string)) => string.contains(substring.as_str()),
+                    (Value::String(_), _) => false,
+                    (value, target) => contains(target, value, true),
+                },
+                (op, Some(target)) => {
+                    let ordering = match (target, &predicate.value) {
+                        (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+                        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                        // Only values of the same type are compared.
+                        _ => None,
+                    };
+                    match ordering {
+                        Some(ordering) => match op {
+                            Operator::Lt  => ordering.is_lt(),
+                            Operator::Lte => ordering.is_le(),
+                            Operator::Gt  => ordering.is_gt(),
+                            _             => ordering.is_ge(),
+                        },
+                        None => false,
+                    }
+                },
+            }
+        })
+    }
+}
+
+
+// Compares values the way JSONB does, i.e. numbers are equal regardless of their representation.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b)),
+        (Value::Object(a), Value::Object(b)) => a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).map_or(false, |w| equal(v, w))),
+        _ => a == b,
+    }
+}
+
+
+// JSONB containment ("@>"): objects contain subsets of their members, arrays contain subsets of
+// their elements, and only top level array may contain a bare scalar.
+fn contains(target: &Value, value: &Value, top_level: bool) -> bool {
+    match (target, value) {
+        (Value::Object(target), Value::Object(fields)) => fields.iter()
+            .all(|(key, value)| target.get(key).map_or(false, |t| contains(t, value, false))),
+        (Value::Array(target), Value::Array(items)) => items.iter()
+            .all(|item| target.iter().any(|t| contains(t, item, false))),
+        (Value::Array(target), scalar) if top_level && !scalar.is_object() => target.iter().any(|t| equal(t, scalar)),
+        (target, value) => equal(target, value),
+    }
+}
+
+
+// Allows a route to access structured filter, if it was provided.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Filter {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        // Extract value from header or url.
+        let raw = match req.headers().get_one("X-Filter") {
+            Some(value) => value.to_string(),
+            None => match req.query_value::<String>("filter") {
+                Some(Ok(value)) => value,
+                Some(Err(e)) => {
+                    // Store error message.
+                    req.local_cache(|| ErrorMessage(Some(json!({
+                        "code":    "err_filter_parse",
+                        "message": format!("Couldn't read filter from url argument with error: '{}'!", e)
+                    }))));
+                    return Outcome::Failure((Status::BadRequest, ()));
+                },
+                // Filter is optional, so no filter means "match everything".
+                None => return Outcome::Success(Filter::default()),
+            }
+        };
+
+        match Filter::parse(&raw) {
+            Ok(filter) => Outcome::Success(filter),
+            Err(message) => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(message)));
+                // Forward to error catcher.
+                Outcome::Failure((Status::BadRequest, ()))
+            }
+        }
+    }
+}
@@ -1,11 +1,141 @@
+use rocket::request::{Outcome, Request, FromRequest};
+use rocket_contrib::databases::{postgres, Config as DatabaseConfig};
+use crate::responders::CustomResponder;
+use crate::errors::ErrorMessage;
+use rocket::{Rocket, Orbit};
+use rocket::http::Status;
 use rocket_contrib::json::JsonValue;
+use crate::model::ApiDatabase;
+use std::time::{Duration, Instant};
+use crate::migrations;
 
 
+// How long readiness check waits for a connection and for the database to answer (together).
+const DATABASE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+
+/// Configuration of the database pool, so it can be reported by readiness check.
+pub struct PoolConfig {
+    pub pool_size: u32,
+    pub timeout:   u8,
+}
+
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PoolConfig {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        // Database fairing wouldn't let the server start without valid config, but a guard
+        // mustn't panic anyway.
+        match DatabaseConfig::from("storage", req.rocket()) {
+            Ok(config) => Outcome::Success(PoolConfig { pool_size: config.pool_size, timeout: config.timeout }),
+            Err(e) => {
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_database_config",
+                    "message": format!("Couldn't read database config with error: '{}'!", e),
+                }))));
+                Outcome::Failure((Status::InternalServerError, ()))
+            }
+        }
+    }
+}
+
+
+/// Liveness check: responds as long as the server is able to handle requests, without
+/// touching any dependencies. Kept at "/" as well for compatibility.
 #[get("/")]
 pub async fn health_check_handler() -> JsonValue {
-    // TODO: more sophisticated healthcheck
     json!({
         "code": "info_status_check_ok"
     })
 }
 
+
+#[get("/live")]
+pub async fn liveness_check_handler() -> JsonValue {
+    health_check_handler().await
+}
+
+
+// Connections of this database which come from the address of the one running the query (i.e.
+// from this host), as seen by the database: idle ones and ones running a query or a transaction.
+const CONNECTIONS_QUERY: &str = "SELECT \
+    count(*) FILTER (WHERE state = 'idle') AS idle, \
+    count(*) FILTER (WHERE state <> 'idle') AS in_use \
+    FROM pg_stat_activity \
+    WHERE datname = current_database() AND backend_type = 'client backend' \
+    AND client_addr IS NOT DISTINCT FROM inet_client_addr()";
+
+
+/// Readiness check: verifies that database connection can be checked out from the pool, that
+/// database answers a trivial query and that its schema is up to date, all within 2 seconds (the
+/// pool's own timeout isn't waited for). Responds with 503 if any of it fails, so reverse proxy
+/// and container health checks can act on it. Response also contains the version of the server,
+/// configuration of the database pool and amounts of idle and in use connections from this host,
+/// as seen by the database (so they include background workers of the server, e.g. the listener
+/// of change events, and connections are only in use while they run a query or a transaction).
+#[get("/ready")]
+pub async fn readiness_check_handler(rocket: &Rocket<Orbit>, pool: PoolConfig) -> CustomResponder {
+    let started = Instant::now();
+
+    let check = async {
+        let conn = match ApiDatabase::get_one(rocket).await {
+            Some(conn) => conn,
+            None => return None,
+        };
+        Some(conn.run(|c| -> Result<_, postgres::Error> {
+            let connections = c.query_one(CONNECTIONS_QUERY, &[])?;
+            let pending = migrations::pending(c)?;
+            Ok((connections.get::<_, i64>("idle"), connections.get::<_, i64>("in_use"), pending))
+        }).await)
+    };
+
+    // Unknown (null) unless the database answered.
+    let mut connections = (None, None);
+    let (database, migrations) = match rocket::tokio::time::timeout(DATABASE_CHECK_TIMEOUT, check).await {
+        Ok(None) => (json!({
+            "status":  "error",
+            "message": "Couldn't get database connection from the pool!",
+        }), json!({ "status": "unknown" })),
+        Ok(Some(Ok((idle, in_use, pending)))) => {
+            connections = (Some(idle), Some(in_use));
+            (json!({
+                "status":     "ok",
+                "latency_ms": started.elapsed().as_millis() as u64,
+            }), json!({
+                "status":  if pending.is_empty() { "ok" } else { "error" },
+                "pending": pending.iter().map(|m| format!("{:04}_{}", m.version, m.name)).collect::<Vec<String>>(),
+            }))
+        },
+        Ok(Some(Err(e))) => (json!({
+            "status":  "error",
+            "message": format!("Database query failed with error: '{}'!", e),
+        }), json!({ "status": "unknown" })),
+        Err(_) => (json!({
+            "status":  "error",
+            "message": format!("Database didn't respond in {} ms!", DATABASE_CHECK_TIMEOUT.as_millis()),
+        }), json!({ "status": "unknown" })),
+    };
+
+    let ready = database["status"] == "ok" && migrations["status"] == "ok";
+    let body = json!({
+        "code":    if ready { "info_ready_ok" } else { "err_not_ready" },
+        "version": env!("CARGO_PKG_VERSION"),
+        "checks": {
+            "database":   database,
+            "migrations": migrations,
+        },
+        "pool": {
+            "size":    pool.pool_size,
+            "timeout": pool.timeout,
+            "idle":    connections.0,
+            "in_use":  connections.1,
+        },
+    });
+
+    match ready {
+        true => CustomResponder::Ok(body),
+        false => CustomResponder::Unavailable(body),
+    }
+}
@@ -0,0 +1,134 @@
+use rocket::request::{Outcome, Request, FromRequest};
+use rocket_contrib::databases::postgres;
+use rocket_contrib::json::JsonValue;
+use crate::errors::{ErrorMessage, ApiError};
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use serde::Deserialize;
+use sha2::{Sha256, Digest};
+use serde_json::Value;
+
+
+// Keys longer than this are rejected, same as the database column.
+const MAX_KEY_LENGTH: usize = 255;
+
+
+fn default_window() -> u32 {
+    // 24 hours, which covers retries of any reasonable job.
+    24 * 60 * 60
+}
+
+
+/// Idempotency settings, read from Rocket config. `idempotency_window` is the amount of seconds
+/// during which requests with the same "Idempotency-Key" get the response of the first one.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdempotencyConfig {
+    #[serde(default = "default_window")]
+    pub idempotency_window: u32,
+}
+
+
+/// Fairing which reads idempotency settings into managed state.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Idempotency Keys", |rocket| async {
+        match rocket.figment().extract::<IdempotencyConfig>() {
+            Ok(config) => Ok(rocket.manage(config)),
+            Err(e) => {
+                eprintln!("Failed to read idempotency config with error: '{}'!", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+
+/// Value which allows to access "Idempotency-Key" header (any printable ASCII string of up to
+/// 255 characters), if it was provided. Keys are scoped by namespace.
+pub struct IdempotencyKey {
+    pub key: Option<String>,
+    window:  u32,
+}
+
+
+impl IdempotencyKey {
+    /// Runs `work` in a transaction and returns the response it made. If key was provided, the
+    /// response is stored in the same transaction, so it's only stored if `work` succeeded. Repeated
+    /// request with the key gets stored response back (and the flag is true) without running `work`,
+    /// or 'err_idempotency_key_reused' error if it's a different request. Concurrent requests with the
+    /// same key wait for the first one to finish. Request is any string which identifies what is done,
+    /// e.g. name of the handler with the body.
+    pub fn run<F>(&self, c: &mut postgres::Client, namespace: &str, request: &str, work: F) -> Result<(JsonValue, bool), ApiError>
+        where F: FnOnce(&mut postgres::Transaction<'_>) -> Result<JsonValue, ApiError>
+    {
+        let mut tx = c.transaction()?;
+
+        let key = match &self.key {
+            Some(key) => key,
+            None => {
+                let response = work(&mut tx)?;
+                tx.commit()?;
+                return Ok((response, false));
+            }
+        };
+        let hash = format!("{:x}", Sha256::digest(request.as_bytes()));
+
+        // Expired keys are removed first, so they can be used again.
+        tx.execute(
+            "DELETE FROM idempotency_keys WHERE created_at < now() - make_interval(secs => $1)",
+            &[&(self.window as f64)]
+        )?;
+        let inserted = tx.execute(
+            "INSERT INTO idempotency_keys (namespace, key, request_hash) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            &[&namespace, key, &hash]
+        )?;
+
+        if inserted == 0 {
+            // Only committed keys are visible here, and those always have a response.
+            let row = tx.query_one(
+                "SELECT request_hash, response FROM idempotency_keys WHERE namespace = $1 AND key = $2",
+                &[&namespace, key]
+            )?;
+            if row.get::<_, String>("request_hash") != hash {
+                return Err(ApiError::IdempotencyConflict(key.clone()));
+            }
+            let response = row.try_get::<_, Value>("response").map_err(|e| ApiError::Malformed(e.to_string()))?;
+            return Ok((JsonValue(response), true));
+        }
+
+        let response = work(&mut tx)?;
+        tx.execute(
+            "UPDATE idempotency_keys SET response = $3 WHERE namespace = $1 AND key = $2",
+            &[&namespace, key, &response.0]
+        )?;
+
+        tx.commit()?;
+        Ok((response, false))
+    }
+}
+
+
+// Allows a route to access idempotency key, if it was provided.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IdempotencyKey {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        let config = req.rocket().state::<IdempotencyConfig>().expect("Idempotency fairing is not attached!");
+
+        match req.headers().get_one("Idempotency-Key") {
+            None => Outcome::Success(IdempotencyKey { key: None, window: config.idempotency_window }),
+            Some(key) if !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.chars().all(|c| c.is_ascii_graphic()) => {
+                Outcome::Success(IdempotencyKey { key: Some(key.to_string()), window: config.idempotency_window })
+            },
+            Some(_) => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_idempotency_key",
+                    "message": format!("Idempotency-Key must be a string of 1 to {} printable ASCII characters!", MAX_KEY_LENGTH),
+                }))));
+                // Forward to error catcher.
+                Outcome::Failure((Status::BadRequest, ()))
+            }
+        }
+    }
+}
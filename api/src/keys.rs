@@ -0,0 +1,66 @@
+use crate::auth::{ApiKey, Grant, Scoped, ManageKeys};
+use crate::responders::CustomResponder;
+use crate::model::{ApiDatabase, Entry};
+
+
+/// This endpoint is used to create an API key. Body of the request must be a JSON object with
+/// the name of the key (<String>, up to 64 characters) and its grants: list of objects with a
+/// namespace (exact name, prefix ending with '*', or just '*' for all namespaces) and scopes
+/// (any of "read", "write", "delete" and "admin", which implies the others), example:
+/// {"name": "team-a", "grants": [{"namespace": "team_a_*", "scopes": ["read", "write"]}]}.
+/// Response contains the key itself, which is not stored and can't be received again.
+/// This endpoint requires a key with admin scope for all namespaces.
+#[post("/", format = "application/json", data = "<body>")]
+pub async fn create_key(_key: Scoped<ManageKeys>, body: Entry, conn: ApiDatabase) -> CustomResponder {
+    let name = match body.0.get("name").and_then(|name| name.as_str()) {
+        Some(name) if !name.is_empty() && name.len() <= 64 => name.to_string(),
+        _ => return CustomResponder::BadRequest(json!({
+            "code":    "err_api_key_name",
+            "message": "API key must have a 'name' of 1 to 64 characters!",
+        })),
+    };
+    let grants = match Grant::parse_all(body.0.get("grants").unwrap_or(&serde_json::Value::Null)) {
+        Ok(grants) => grants,
+        Err(message) => return CustomResponder::BadRequest(message),
+    };
+
+    match conn.run(move |c| ApiKey::create(c, name, grants)).await {
+        Ok((data, key)) => CustomResponder::Ok(json!({
+            "code": "info_api_key_created",
+            "message": "Successfully created new API key! Store it now, it can't be received again.",
+            "key": key,
+            "data": data,
+        })),
+        Err(e) => e.into(),
+    }
+}
+
+
+/// This endpoint is used to receive all API keys, including revoked ones (keys themselves are
+/// never returned). This endpoint requires a key with admin scope for all namespaces.
+#[get("/")]
+pub async fn list_keys(_key: Scoped<ManageKeys>, conn: ApiDatabase) -> CustomResponder {
+    match conn.run(|c| ApiKey::list(c)).await {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "data": data,
+        })),
+        Err(e) => e.into(),
+    }
+}
+
+
+/// This endpoint is used to revoke API key by ID (url path /<id> of type unsigned 64-bit integer).
+/// Revoked key can't be used anymore, but is still listed. This endpoint requires a key with admin
+/// scope for all namespaces.
+#[delete("/<id>")]
+pub async fn revoke_key(_key: Scoped<ManageKeys>, id: u64, conn: ApiDatabase) -> CustomResponder {
+    match conn.run(move |c| ApiKey::revoke(c, id)).await {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "info_api_key_revoked",
+            "message": format!("Successfully revoked API key of ID '{}'!", id),
+            "data": data,
+        })),
+        Err(e) => e.into(),
+    }
+}
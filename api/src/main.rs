@@ -7,36 +7,137 @@
 
 
 mod model;
+mod auth;
+mod keys;
+mod changes;
+mod schema;
+mod schemas;
 mod health;
+mod idempotency;
 mod errors;
+mod events;
 mod entries;
+mod filter;
+mod export;
 mod namespace;
+mod namespaces;
+mod ndjson;
+mod migrations;
 mod pagination;
+mod patch;
+mod preconditions;
 mod responders;
+mod timestamps;
+mod webhook;
+mod webhooks;
 
 
-#[launch]
 fn rocket() -> rocket::Rocket<rocket::Build> {
+    rocket_custom(rocket::Config::figment())
+}
+
+
+/// Same as `rocket()`, but with custom configuration (e.g. tests can enable API keys).
+fn rocket_custom(figment: rocket::figment::Figment) -> rocket::Rocket<rocket::Build> {
     #[cfg(not(debug_assertions))] println!("Voyeur is starting..");
-    rocket::build()
+    rocket::custom(figment)
         .mount("/api/v1/entries", routes![
             entries::get_entry_by_id,
             entries::get_query_content,
             entries::get_paginated_entries,
+            entries::get_entries_by_cursor,
+            entries::export_entries,
+            entries::stream_entries,
+            entries::get_entry_changes,
+            entries::get_entry_changes_head,
             entries::create_one_entry,
             entries::create_many_entries,
+            entries::ingest_entries,
             entries::update_entry_by_id,
+            entries::patch_entry_by_id,
+            entries::move_entry_by_id,
+            entries::get_entry_moves,
+            entries::get_entry_revisions,
+            entries::get_entry_revision,
+            entries::diff_entry_revisions,
+            entries::restore_entry_revision,
             entries::delete_all_entries,
             entries::delete_entry_by_id,
         ])
+        .mount("/api/v1/schemas", routes![
+            schemas::get_schema,
+            schemas::put_schema,
+            schemas::delete_schema,
+            schemas::validate_documents,
+        ])
+        .mount("/api/v1/namespaces", routes![
+            namespaces::list_namespaces,
+            namespaces::get_namespace,
+            namespaces::put_namespace,
+            namespaces::delete_namespace_metadata,
+            namespaces::rename_namespace,
+            namespaces::copy_namespace,
+            namespaces::merge_namespace,
+        ])
+        .mount("/api/v1/keys", routes![
+            keys::create_key,
+            keys::list_keys,
+            keys::revoke_key,
+        ])
+        .mount("/api/v1/webhooks", routes![
+            webhooks::create_webhook,
+            webhooks::list_webhooks,
+            webhooks::delete_webhook,
+            webhooks::get_webhook_deliveries,
+        ])
         .mount("/api/v1/health", routes![
-            health::health_check_handler
+            health::health_check_handler,
+            health::liveness_check_handler,
+            health::readiness_check_handler,
         ])
         // API V1 error handlers
         .register("/api/v1", catchers![
             entries::handle_bad_request_errors,
+            entries::handle_not_found_errors,
+            entries::handle_unprocessable_entity_errors,
+            entries::handle_internal_errors,
+            entries::handle_unavailable_errors,
+            entries::handle_unauthorized_errors,
+            entries::handle_forbidden_errors,
+            entries::handle_unsupported_media_type_errors,
         ])
         // Databases
         .attach(model::ApiDatabase::fairing())
+        .attach(migrations::fairing())
+        .attach(auth::fairing())
+        .attach(idempotency::fairing())
+        .attach(events::fairing())
+        .attach(changes::fairing())
+        .attach(webhook::fairing())
+}
+
+
+#[rocket::main]
+async fn main() {
+    match std::env::args().nth(1).as_deref() {
+        // Only apply pending migrations (ignition runs the fairing), without serving requests. Other
+        // fairings are not attached, because they start background workers (e.g. webhook dispatcher).
+        Some("--migrate-only") => {
+            let rocket = rocket::build().attach(model::ApiDatabase::fairing()).attach(migrations::fairing());
+            if let Err(e) = rocket.ignite().await {
+                eprintln!("Failed to apply migrations with error: '{}'!", e);
+                std::process::exit(1);
+            }
+        },
+        // Report pending migrations without applying them, e.g. before the deployment.
+        Some("--check") => {
+            let rocket = rocket::build().attach(model::ApiDatabase::fairing());
+            if !migrations::check(rocket).await {
+                std::process::exit(1);
+            }
+        },
+        // Note: launch errors are reported by Rocket itself when dropped.
+        _ => { let _ = rocket().launch().await; }
+    }
 }
 
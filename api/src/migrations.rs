@@ -0,0 +1,219 @@
+use rocket_contrib::databases::postgres;
+use crate::model::ApiDatabase;
+use rocket::fairing::AdHoc;
+use rocket::{Rocket, Build};
+
+
+// Arbitrary (but constant) key of the advisory lock, which is held while migrations are
+// applied. This way multiple API replicas starting at the same time won't race each other.
+const MIGRATIONS_LOCK_KEY: i64 = 0x766f_7965_7572;
+
+
+/// Single schema migration, which is embedded into the binary at compile time.
+pub struct Migration {
+    pub version: i64,
+    pub name:    &'static str,
+    pub sql:     &'static str,
+}
+
+
+/// All migrations known to this build, in the order they must be applied. New migration
+/// must be appended at the end with the next version number, and migrations which were
+/// already released must never be edited (write a new one instead).
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name:    "create_entries",
+        sql:     include_str!("../migrations/0001_create_entries.sql"),
+    },
+    Migration {
+        version: 2,
+        name:    "entries_content_jsonb",
+        sql:     include_str!("../migrations/0002_entries_content_jsonb.sql"),
+    },
+    Migration {
+        version: 3,
+        name:    "entries_timestamps",
+        sql:     include_str!("../migrations/0003_entries_timestamps.sql"),
+    },
+    Migration {
+        version: 4,
+        name:    "api_keys",
+        sql:     include_str!("../migrations/0004_api_keys.sql"),
+    },
+    Migration {
+        version: 5,
+        name:    "namespace_schemas",
+        sql:     include_str!("../migrations/0005_namespace_schemas.sql"),
+    },
+    Migration {
+        version: 6,
+        name:    "namespaces",
+        sql:     include_str!("../migrations/0006_namespaces.sql"),
+    },
+    Migration {
+        version: 7,
+        name:    "entries_version",
+        sql:     include_str!("../migrations/0007_entries_version.sql"),
+    },
+    Migration {
+        version: 8,
+        name:    "entry_moves",
+        sql:     include_str!("../migrations/0008_entry_moves.sql"),
+    },
+    Migration {
+        version: 9,
+        name:    "idempotency_keys",
+        sql:     include_str!("../migrations/0009_idempotency_keys.sql"),
+    },
+    Migration {
+        version: 10,
+        name:    "entry_notifications",
+        sql:     include_str!("../migrations/0010_entry_notifications.sql"),
+    },
+    Migration {
+        version: 11,
+        name:    "webhooks",
+        sql:     include_str!("../migrations/0011_webhooks.sql"),
+    },
+    Migration {
+        version: 12,
+        name:    "entry_change_log",
+        sql:     include_str!("../migrations/0012_entry_change_log.sql"),
+    },
+    Migration {
+        version: 13,
+        name:    "entry_revisions",
+        sql:     include_str!("../migrations/0013_entry_revisions.sql"),
+    },
+    Migration {
+        version: 14,
+        name:    "entry_revision_generations",
+        sql:     include_str!("../migrations/0014_entry_revision_generations.sql"),
+    },
+    Migration {
+        version: 15,
+        name:    "entry_notifications_per_statement",
+        sql:     include_str!("../migrations/0015_entry_notifications_per_statement.sql"),
+    },
+];
+
+
+/// Returns versions of all migrations which were applied to the database. This doesn't
+/// create bookkeeping table, so it's safe to use on read-only checks.
+pub fn applied(c: &mut impl postgres::GenericClient) -> Result<Vec<i64>, postgres::Error> {
+    let exists = c.query_one("SELECT to_regclass('schema_migrations') IS NOT NULL AS exists", &[])?
+        .get::<_, bool>("exists");
+
+    if !exists {
+        return Ok(vec![]);
+    }
+
+    Ok(c.query("SELECT version FROM schema_migrations ORDER BY version ASC", &[])?
+        .iter()
+        .map(|row| row.get::<_, i64>("version"))
+        .collect())
+}
+
+
+/// Returns migrations which are known to this build, but weren't applied yet.
+pub fn pending(c: &mut impl postgres::GenericClient) -> Result<Vec<&'static Migration>, postgres::Error> {
+    let applied = applied(c)?;
+    Ok(MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)).collect())
+}
+
+
+/// Applies all pending migrations in a single transaction, so either every one of them is
+/// applied or none. Returns list of migrations that were applied.
+pub fn run(c: &mut postgres::Client) -> Result<Vec<&'static Migration>, postgres::Error> {
+    let mut tx = c.transaction()?;
+
+    tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATIONS_LOCK_KEY])?;
+    tx.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+           version BIGINT PRIMARY KEY,
+           name TEXT NOT NULL,
+           applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+         )"
+    )?;
+
+    // Note: this is checked only after the lock is acquired, because another replica
+    //       might have just applied some of the migrations.
+    let pending = pending(&mut tx)?;
+    for migration in &pending {
+        tx.batch_execute(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+            &[&migration.version, &migration.name]
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(pending)
+}
+
+
+/// Fairing which applies pending migrations during ignition. It must be attached after
+/// the database fairing, and aborts the launch if the schema can't be brought up to date.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Database Migrations", |rocket| async {
+        let conn = match ApiDatabase::get_one(&rocket).await {
+            Some(conn) => conn,
+            None => {
+                eprintln!("Couldn't get database connection to apply migrations!");
+                return Err(rocket);
+            }
+        };
+
+        match conn.run(|c| run(c)).await {
+            Ok(migrations) => {
+                for m in migrations {
+                    println!("Applied migration {:04}_{}.", m.version, m.name);
+                }
+                Ok(rocket)
+            },
+            Err(e) => {
+                eprintln!("Failed to apply migrations with error: '{}'!", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+
+/// Reports whether database schema is up to date without changing anything. Returns
+/// `false` if there are pending migrations or the database couldn't be reached.
+pub async fn check(rocket: Rocket<Build>) -> bool {
+    let rocket = match rocket.ignite().await {
+        Ok(rocket) => rocket,
+        Err(e) => {
+            eprintln!("Failed to start with error: '{}'!", e);
+            return false;
+        }
+    };
+
+    let conn = match ApiDatabase::get_one(&rocket).await {
+        Some(conn) => conn,
+        None => {
+            eprintln!("Couldn't get database connection to check migrations!");
+            return false;
+        }
+    };
+
+    match conn.run(|c| pending(c)).await {
+        Ok(pending) if pending.is_empty() => {
+            println!("Database schema is up to date.");
+            true
+        },
+        Ok(pending) => {
+            for m in pending {
+                println!("Pending migration {:04}_{}.", m.version, m.name);
+            }
+            false
+        },
+        Err(e) => {
+            eprintln!("Failed to check migrations with error: '{}'!", e);
+            false
+        }
+    }
+}
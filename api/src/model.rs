@@ -1,15 +1,26 @@
 use rocket::{http::{Status, ContentType}, Request, Data};
 use rocket::data::{Outcome, FromData, ToByteUnit};
+use crate::timestamps::{TimeRange, format_timestamp};
+use crate::pagination::{Cursor, Sort};
+use crate::filter::{Filter, Operator};
 use rocket_contrib::databases::postgres;
+use rocket_contrib::databases::postgres::types::ToSql;
 use serde::{Serialize, Deserialize};
 use serde_json::{from_str, Value};
-use crate::errors::ErrorMessage;
-use serde_json::ser::to_string;
+use crate::errors::{ErrorMessage, ApiError};
+use crate::schema::{Schema, Violation};
+use crate::patch::Patch;
+use crate::preconditions::Precondition;
+use time::OffsetDateTime;
 
 
 // Limit is 1MB here, should be enough for common use. If you are sending
 // anything bigger, you should be required to provide appropriate header.
 const DEFAULT_BUFFER_LIMIT: u32 = 1024 * 1024;
+// Amount of entries inserted by a single multi-row INSERT statement.
+const INSERT_BATCH_SIZE: usize = 1000;
+// Amount of stored entries loaded at once, when they are validated before copying or moving.
+const VALIDATE_BATCH_SIZE: i64 = 1000;
 
 
 #[database("storage")]
@@ -22,98 +33,740 @@ pub struct Entry(pub Value);
 
 #[derive(Serialize, Clone, Debug)]
 pub struct EntryResponse {
-    pub id:      u64,
-    pub content: Value
+    pub id:         u64,
+    pub content:    Value,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Changes on every write of the entry, it's also sent as "ETag" header.
+    pub version:    u64,
+}
+
+
+/// Record of the audit log of entry moves between namespaces.
+#[derive(Serialize, Clone, Debug)]
+pub struct EntryMove {
+    pub from:     String,
+    pub to:       String,
+    /// Name of the API key, None if request was made without a key.
+    pub moved_by: Option<String>,
+    pub moved_at: String,
+}
+
+
+/// Revision of the entry: its state during some period. Content is only returned when a
+/// single revision is requested.
+#[derive(Serialize, Clone, Debug)]
+pub struct EntryRevision {
+    pub revision:   u32,
+    pub namespace:  String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content:    Option<Value>,
+    pub version:    u64,
+    pub valid_from: String,
+    /// None for the current revision.
+    pub valid_to:   Option<String>,
+}
+
+
+/// Describes which entries of the namespace are selected by listing endpoints: optional partial
+/// match of the content, structured filter and time range.
+#[derive(Clone, Debug)]
+pub struct Selection {
+    pub namespace: String,
+    pub query:     Option<String>,
+    pub filter:    Filter,
+    pub range:     TimeRange,
+}
+
+
+/// Helper which collects conditions of the WHERE clause together with their parameters.
+/// User provided values are always passed as numbered parameters, and never interpolated
+/// into the query itself.
+struct Conditions {
+    clauses: Vec<String>,
+    params:  Vec<Box<dyn ToSql + Sync + Send>>,
+}
+
+
+impl Conditions {
+    fn new(namespace: String) -> Self {
+        let mut conditions = Conditions { clauses: vec![], params: vec![] };
+        let namespace = conditions.bind(namespace);
+        conditions.push(format!("namespace = {}", namespace));
+        conditions
+    }
+
+    /// Adds parameter and returns its placeholder, e.g. "$3".
+    fn bind<T: ToSql + Sync + Send + 'static>(&mut self, value: T) -> String {
+        self.params.push(Box::new(value));
+        format!("${}", self.params.len())
+    }
+
+    fn push(&mut self, clause: String) {
+        self.clauses.push(clause);
+    }
+
+    fn sql(&self) -> String {
+        self.clauses.join(" AND ")
+    }
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params.iter().map(|p| p.as_ref() as &(dyn ToSql + Sync)).collect()
+    }
 }
 
 
 impl Entry {
-    pub fn from_row(row: &postgres::Row) -> EntryResponse {
-        EntryResponse {
-            id: row.get::<_, i64>("id") as u64,
-            content: from_str::<Value>(&row.get::<_, String>("content")).unwrap(),
+    /// Translates structured filter into SQL conditions on the content. Note that paths which
+    /// don't exist in the content evaluate to NULL, so only 'exists' can match them.
+    fn filter_conditions(conditions: &mut Conditions, filter: &Filter) {
+        for predicate in &filter.0 {
+            let target = format!("(content #> {}::text[])", conditions.bind(predicate.path.clone()));
+            let clause = match predicate.op {
+                Operator::Eq => format!("{} = {}::jsonb", target, conditions.bind(predicate.value.clone())),
+                Operator::Ne => format!("{} <> {}::jsonb", target, conditions.bind(predicate.value.clone())),
+                Operator::Lt | Operator::Lte | Operator::Gt | Operator::Gte => {
+                    let sign = match predicate.op {
+                        Operator::Lt  => "<",
+                        Operator::Lte => "<=",
+                        Operator::Gt  => ">",
+                        _             => ">=",
+                    };
+                    let value = conditions.bind(predicate.value.clone());
+                    // JSONB ordering is defined across types (e.g. any string is bigger than any number),
+                    // so we only compare values of the same type.
+                    format!("(jsonb_typeof({t}) = jsonb_typeof({v}::jsonb) AND {t} {s} {v}::jsonb)", t = target, v = value, s = sign)
+                },
+                Operator::In => format!("{} IN (SELECT jsonb_array_elements({}::jsonb))", target, conditions.bind(predicate.value.clone())),
+                Operator::Exists => match predicate.value.as_bool() {
+                    Some(false) => format!("{} IS NULL", target),
+                    _           => format!("{} IS NOT NULL", target),
+                },
+                Operator::Contains => match predicate.value.as_str() {
+                    // Substring match for strings, containment for arrays and objects.
+                    Some(substring) => format!(
+                        "(jsonb_typeof({t}) = 'string' AND strpos({t} #>> '{{}}', {v}::text) > 0)",
+                        t = target, v = conditions.bind(substring.to_string())
+                    ),
+                    None => format!("{} @> {}::jsonb", target, conditions.bind(predicate.value.clone())),
+                },
+            };
+            conditions.push(clause);
+        }
+    }
+
+    pub fn from_row(row: &postgres::Row) -> Result<EntryResponse, ApiError> {
+        let malformed = |e: postgres::Error| ApiError::Malformed(e.to_string());
+        Ok(EntryResponse {
+            id: row.try_get::<_, i64>("id").map_err(malformed)? as u64,
+            content: row.try_get::<_, Value>("content").map_err(malformed)?,
+            created_at: format_timestamp(row.try_get::<_, OffsetDateTime>("created_at").map_err(malformed)?),
+            updated_at: format_timestamp(row.try_get::<_, OffsetDateTime>("updated_at").map_err(malformed)?),
+            version: row.try_get::<_, i64>("version").map_err(malformed)? as u64,
+        })
+    }
+
+    // Reads all rows into responses, failing if any of them is malformed.
+    fn from_rows(rows: Vec<postgres::Row>) -> Result<Vec<EntryResponse>, ApiError> {
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    pub fn get_one(c: &mut postgres::Client, id: u64, namespace: String) -> Result<EntryResponse, ApiError> {
+        match c.query_opt(
+            "SELECT *, namespace = $2 AS in_namespace FROM entries WHERE id = $1",
+            &[&(id as i64), &namespace]
+        )? {
+            Some(row) if row.get::<_, bool>("in_namespace") => Self::from_row(&row),
+            Some(row) => Err(ApiError::NamespaceMismatch(id, row.get("namespace"))),
+            None => Err(ApiError::NotFound(id)),
+        }
+    }
+
+    // Locks the entry until the end of the transaction and returns it. Fails if it's missing,
+    // belongs to another namespace, or its version doesn't satisfy the precondition.
+    fn lock(tx: &mut postgres::Transaction<'_>, id: u64, namespace: &str, if_match: Option<&Precondition>) -> Result<EntryResponse, ApiError> {
+        let entry = match tx.query_opt(
+            "SELECT *, namespace = $2 AS in_namespace FROM entries WHERE id = $1 FOR UPDATE",
+            &[&(id as i64), &namespace]
+        )? {
+            Some(row) if row.get::<_, bool>("in_namespace") => Self::from_row(&row)?,
+            Some(row) => return Err(ApiError::NamespaceMismatch(id, row.get("namespace"))),
+            None => return Err(ApiError::NotFound(id)),
+        };
+
+        match if_match {
+            Some(precondition) if !precondition.matches(Some(entry.version)) => Err(ApiError::PreconditionFailed(id)),
+            _ => Ok(entry),
+        }
+    }
+
+    /// Builds conditions shared by all listing queries from the selection.
+    fn list_conditions(selection: &Selection) -> Conditions {
+        let mut conditions = Conditions::new(selection.namespace.clone());
+        if let Some(query) = &selection.query {
+            // Here we use format to add postgres-specific syntax for partial matching. It's important that this is done in the input data, before being sanitized for vulnarabilities.
+            let pattern = conditions.bind(format!("%{}%", query));
+            conditions.push(format!("content::text LIKE {}", pattern));
+        }
+        Self::filter_conditions(&mut conditions, &selection.filter);
+
+        let bounds = [
+            ("created_at >=", selection.range.since),
+            ("created_at <",  selection.range.until),
+            ("updated_at >=", selection.range.updated_since),
+            ("updated_at <",  selection.range.updated_until),
+        ];
+        for (comparison, bound) in bounds.iter() {
+            if let Some(bound) = bound {
+                let bound = conditions.bind(*bound);
+                conditions.push(format!("{} {}", comparison, bound));
+            }
+        }
+
+        conditions
+    }
+
+    /// Returns the table which listing queries read: entries themselves, or their revisions which
+    /// were current at the time, if selection is made as of some time. Both have the same columns.
+    fn list_source(conditions: &mut Conditions, selection: &Selection) -> String {
+        match selection.range.as_of {
+            Some(as_of) => {
+                let as_of = conditions.bind(as_of);
+                format!(
+                    "(SELECT entry_id AS id, namespace, content, created_at, updated_at, version FROM entry_revisions \
+                    WHERE valid_from <= {t} AND (valid_to IS NULL OR valid_to > {t})) AS entries",
+                    t = as_of
+                )
+            },
+            None => "entries".to_string(),
+        }
+    }
+
+    /// Returns page of selected entries together with a flag whether there is a next page.
+    pub fn get_page(c: &mut postgres::Client, selection: &Selection, sort: Sort, page: u32, page_size: u16) -> Result<(Vec<EntryResponse>, bool), ApiError> {
+        let mut conditions = Self::list_conditions(selection);
+        let source = Self::list_source(&mut conditions, selection);
+        // We request one more entry than needed to find out whether there is a next page.
+        let limit = conditions.bind(page_size as i64 + 1);
+        let offset = conditions.bind(page as i64 * page_size as i64);
+
+        let mut entries = Self::from_rows(c.query(
+            format!(
+                "SELECT * FROM {} WHERE {} ORDER BY {} LIMIT {} OFFSET {}",
+                source, conditions.sql(), sort.sql(), limit, offset
+            ).as_str(),
+            &conditions.params()
+        )?)?;
+
+        let has_next = entries.len() > page_size as usize;
+        entries.truncate(page_size as usize);
+        Ok((entries, has_next))
+    }
+
+    /// Returns total amount of selected entries. This has to scan all matching rows, so it's
+    /// only done when client asks for it.
+    pub fn count(c: &mut postgres::Client, selection: &Selection) -> Result<u64, ApiError> {
+        let mut conditions = Self::list_conditions(selection);
+        let source = Self::list_source(&mut conditions, selection);
+
+        Ok(c.query_one(
+            format!("SELECT COUNT(*) FROM {} WHERE {}", source, conditions.sql()).as_str(),
+            &conditions.params()
+        )?
+        .get::<_, i64>("count") as u64)
+    }
+
+    /// Returns page of selected entries which come after the cursor (by ID) together with the
+    /// cursor of the next page, which is None if this page is the last one.
+    pub fn get_after(c: &mut postgres::Client, selection: &Selection, cursor: Cursor, page_size: u16) -> Result<(Vec<EntryResponse>, Option<Cursor>), ApiError> {
+        let mut conditions = Self::list_conditions(selection);
+        let source = Self::list_source(&mut conditions, selection);
+        if let Some(after) = cursor.0 {
+            let after = conditions.bind(after as i64);
+            conditions.push(format!("id > {}", after));
+        }
+        // We request one more entry than needed to find out whether there is a next page.
+        let limit = conditions.bind(page_size as i64 + 1);
+
+        let mut entries = Self::from_rows(c.query(
+            format!("SELECT * FROM {} WHERE {} ORDER BY id ASC LIMIT {}", source, conditions.sql(), limit).as_str(),
+            &conditions.params()
+        )?)?;
+
+        let next = match entries.len() > page_size as usize {
+            true => {
+                entries.truncate(page_size as usize);
+                entries.last().map(|entry| Cursor(Some(entry.id)))
+            },
+            false => None,
+        };
+
+        Ok((entries, next))
+    }
+
+    // Checks the content against JSON Schema of the namespace, if it has one.
+    fn validate(c: &mut impl postgres::GenericClient, namespace: &str, content: &Value) -> Result<(), ApiError> {
+        match Schema::load(c, namespace)? {
+            Some(schema) => match schema.validate(content) {
+                violations if violations.is_empty() => Ok(()),
+                violations => Err(ApiError::SchemaViolation(violations)),
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub fn insert(&self, c: &mut impl postgres::GenericClient, namespace: String) -> Result<u64, ApiError> {
+        Self::validate(c, &namespace, &self.0)?;
+        Ok(c.query_one(
+            "INSERT INTO entries (namespace, content) VALUES ($1, $2) RETURNING id",
+            &[&namespace, &self.0]
+        )?
+        .get::<_, i64>("id") as u64)
+    }
+
+    // Inserts batch of entries with a single statement. Returned ids are in the same order as
+    // entries: ids are taken from the sequence together with the position of the entry, since
+    // Postgres doesn't promise to call nextval in the order of the SELECT.
+    fn insert_batch(c: &mut impl postgres::GenericClient, namespace: &String, batch: &[Value]) -> Result<Vec<u64>, postgres::Error> {
+        Ok(c.query(
+            "WITH batch AS ( \
+                SELECT nextval(pg_get_serial_sequence('entries', 'id')) AS id, content, n \
+                FROM unnest($2::jsonb[]) WITH ORDINALITY AS batch (content, n) \
+            ), inserted AS ( \
+                INSERT INTO entries (id, namespace, content) SELECT id, $1, content FROM batch RETURNING id \
+            ) \
+            SELECT batch.id FROM batch JOIN inserted USING (id) ORDER BY batch.n",
+            &[namespace, &batch]
+        )?
+        .iter()
+        .map(|row| row.get::<_, i64>("id") as u64)
+        .collect())
+    }
+
+    /// Inserts all entries in a single transaction using batched multi-row inserts, so either
+    /// every entry is inserted or none of them. Returns ids of the entries in the same order.
+    pub fn insert_many(c: &mut impl postgres::GenericClient, namespace: String, entries: &[Value]) -> Result<Vec<u64>, ApiError> {
+        let mut tx = c.transaction()?;
+        let mut ids = Vec::with_capacity(entries.len());
+
+        // Documents are validated before anything is inserted. Pointers of violations are
+        // relative to the whole array, e.g. "/3/name".
+        if let Some(schema) = Schema::load(&mut tx, &namespace)? {
+            let violations = entries.iter().enumerate()
+                .flat_map(|(i, entry)| schema.validate(entry).into_iter().map(move |v| Violation {
+                    pointer: format!("/{}{}", i, v.pointer),
+                    message: v.message,
+                }))
+                .collect::<Vec<Violation>>();
+            if !violations.is_empty() {
+                return Err(ApiError::SchemaViolation(violations));
+            }
+        }
+
+        for batch in entries.chunks(INSERT_BATCH_SIZE) {
+            ids.extend(Self::insert_batch(&mut tx, &namespace, batch)?);
+        }
+
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /// Inserts as many entries as possible and returns result for each of them, in the same
+    /// order: either ID of created entry, or an error message. Batches are still inserted with
+    /// a single statement, and only batch which failed is retried entry by entry. Entries which
+    /// don't conform to the schema of the namespace are not inserted.
+    pub fn insert_each(c: &mut impl postgres::GenericClient, namespace: String, entries: &[Value]) -> Result<Vec<Result<u64, String>>, ApiError> {
+        let mut tx = c.transaction()?;
+
+        let schema = Schema::load(&mut tx, &namespace)?;
+        let mut results = entries.iter()
+            .map(|entry| match schema.as_ref().map(|schema| schema.validate(entry)) {
+                Some(violations) if !violations.is_empty() => Err(format!(
+                    "Document doesn't conform to the JSON Schema of the namespace: {}",
+                    violations.iter().map(|v| format!("'{}' {}", v.pointer, v.message)).collect::<Vec<String>>().join(" ")
+                )),
+                _ => Ok(0),
+            })
+            .collect::<Vec<Result<u64, String>>>();
+        let valid = entries.iter().zip(&results)
+            .filter(|(_, result)| result.is_ok())
+            .map(|(entry, _)| entry.clone())
+            .collect::<Vec<Value>>();
+
+        // Valid entries get their results in the same order.
+        let inserted = Self::insert_valid(&mut tx, &namespace, &valid)?;
+        for (result, inserted) in results.iter_mut().filter(|result| result.is_ok()).zip(inserted) {
+            *result = inserted;
+        }
+
+        tx.commit()?;
+        Ok(results)
+    }
+
+    fn insert_valid(tx: &mut postgres::Transaction<'_>, namespace: &String, entries: &[Value]) -> Result<Vec<Result<u64, String>>, ApiError> {
+        let mut results = Vec::with_capacity(entries.len());
+
+        for batch in entries.chunks(INSERT_BATCH_SIZE) {
+            // Nested transactions are savepoints, so failure here doesn't abort the whole thing.
+            let mut savepoint = tx.transaction()?;
+            match Self::insert_batch(&mut savepoint, namespace, batch) {
+                Ok(ids) => {
+                    savepoint.commit()?;
+                    results.extend(ids.into_iter().map(Ok));
+                    continue;
+                },
+                Err(_) => savepoint.rollback()?,
+            }
+
+            for entry in batch {
+                let mut savepoint = tx.transaction()?;
+                match Self::insert_batch(&mut savepoint, namespace, std::slice::from_ref(entry)) {
+                    Ok(ids) => {
+                        savepoint.commit()?;
+                        results.push(Ok(ids[0]));
+                    },
+                    Err(e) => {
+                        savepoint.rollback()?;
+                        results.push(Err(e.to_string()));
+                    }
+                }
+            }
         }
+
+        Ok(results)
     }
 
-    pub fn get_one(c: &mut postgres::Client, id: u64, namespace: String) -> Result<EntryResponse, u64> {
-        match c.query_one(
-            "SELECT * FROM entries WHERE id = $1 AND namespace = $2",
+    /// Creates or replaces the entry. Entry which belongs to another namespace is never replaced,
+    /// it must be moved explicitly (see `move_one`). If "If-Match" precondition is provided, entry
+    /// must exist and its version must match. Returns ID and the new version of the entry.
+    pub fn put(&self, c: &mut postgres::Client, id: u64, namespace: String, if_match: Option<&Precondition>) -> Result<(u64, u64), ApiError> {
+        let mut tx = c.transaction()?;
+
+        let current = tx.query_opt(
+            "SELECT version, namespace = $2 AS in_namespace FROM entries WHERE id = $1 FOR UPDATE",
             &[&(id as i64), &namespace]
-        ) {
-            Ok(row) => Ok(Self::from_row(&row)),
-            Err(_) => Err(id),
+        )?;
+        if current.as_ref().map_or(false, |row| !row.get::<_, bool>("in_namespace")) {
+            return Err(ApiError::EntryNamespaceConflict(id));
         }
+        if let Some(precondition) = if_match {
+            if !precondition.matches(current.map(|row| row.get::<_, i64>("version") as u64)) {
+                return Err(ApiError::PreconditionFailed(id));
+            }
+        }
+        Self::validate(&mut tx, &namespace, &self.0)?;
+
+        // Condition on namespace also covers entry which was created concurrently (after the check above).
+        let row = tx.query_opt(
+            "INSERT INTO entries (id, namespace, content) VALUES ($1, $2, $3) ON CONFLICT (id) \
+            DO UPDATE SET content = EXCLUDED.content, updated_at = now(), version = nextval('entries_version_seq') \
+            WHERE entries.namespace = EXCLUDED.namespace RETURNING id, version",
+            &[&(id as i64), &namespace, &self.0]
+        )?
+        .ok_or(ApiError::EntryNamespaceConflict(id))?;
+        tx.commit()?;
+        Ok((row.get::<_, i64>("id") as u64, row.get::<_, i64>("version") as u64))
+    }
+
+    /// Moves the entry to another namespace (its content must conform to JSON Schema of the target,
+    /// if there is one). Every move is recorded with the name of the API key which made it.
+    /// Returns the moved entry.
+    pub fn move_one(c: &mut postgres::Client, id: u64, namespace: String, target: String, moved_by: Option<String>, if_match: Option<&Precondition>) -> Result<EntryResponse, ApiError> {
+        let mut tx = c.transaction()?;
+
+        let entry = Self::lock(&mut tx, id, &namespace, if_match)?;
+        Self::validate(&mut tx, &target, &entry.content)?;
+
+        let row = tx.query_one(
+            "UPDATE entries SET namespace = $2, updated_at = now(), version = nextval('entries_version_seq') \
+            WHERE id = $1 RETURNING *",
+            &[&(id as i64), &target]
+        )?;
+        tx.execute(
+            "INSERT INTO entry_moves (entry_id, from_namespace, to_namespace, moved_by) VALUES ($1, $2, $3, $4)",
+            &[&(id as i64), &namespace, &target, &moved_by]
+        )?;
+
+        tx.commit()?;
+        Self::from_row(&row)
     }
 
-    pub fn get_page(c: &mut postgres::Client, namespace: String, page: u32, page_size: u16) -> Vec<EntryResponse> {
+    /// Returns all moves of the entry (which must be in the namespace now), from the oldest one.
+    pub fn moves(c: &mut postgres::Client, id: u64, namespace: String) -> Result<Vec<EntryMove>, ApiError> {
+        Self::get_one(c, id, namespace)?;
+
         c.query(
-            "SELECT * FROM entries WHERE namespace = $1 \
-             ORDER BY id ASC LIMIT $2 OFFSET $3",
-            &[&namespace, &(page_size as i64), &(page as i64 * page_size as i64)]
-        )
-        .unwrap()
+            "SELECT * FROM entry_moves WHERE entry_id = $1 ORDER BY id ASC",
+            &[&(id as i64)]
+        )?
         .iter()
-        .map(|row| Self::from_row(row))
+        .map(|row| {
+            let malformed = |e: postgres::Error| ApiError::Malformed(e.to_string());
+            Ok(EntryMove {
+                from:     row.try_get("from_namespace").map_err(malformed)?,
+                to:       row.try_get("to_namespace").map_err(malformed)?,
+                moved_by: row.try_get("moved_by").map_err(malformed)?,
+                moved_at: format_timestamp(row.try_get::<_, OffsetDateTime>("moved_at").map_err(malformed)?),
+            })
+        })
         .collect()
     }
 
-    pub fn get_query(c: &mut postgres::Client, namespace: String, page: u32, page_size: u16, query: String) -> Vec<EntryResponse> {
+    // Reads revision row, with content if it was selected.
+    fn revision_from_row(row: &postgres::Row, with_content: bool) -> Result<EntryRevision, ApiError> {
+        let malformed = |e: postgres::Error| ApiError::Malformed(e.to_string());
+        Ok(EntryRevision {
+            revision: row.try_get::<_, i32>("revision").map_err(malformed)? as u32,
+            namespace: row.try_get("namespace").map_err(malformed)?,
+            content: match with_content {
+                true => Some(row.try_get("content").map_err(malformed)?),
+                false => None,
+            },
+            version: row.try_get::<_, i64>("version").map_err(malformed)? as u64,
+            valid_from: format_timestamp(row.try_get::<_, OffsetDateTime>("valid_from").map_err(malformed)?),
+            valid_to: row.try_get::<_, Option<OffsetDateTime>>("valid_to").map_err(malformed)?.map(format_timestamp),
+        })
+    }
+
+    /// Returns all revisions of the entry (which must be in the namespace now), from the oldest
+    /// one, without their content. Revisions from before the entry was last deleted and created
+    /// again with the same ID are not included.
+    pub fn revisions(c: &mut postgres::Client, id: u64, namespace: String) -> Result<Vec<EntryRevision>, ApiError> {
+        Self::get_one(c, id, namespace)?;
+
         c.query(
-            "SELECT * FROM entries WHERE namespace = $1 AND content LIKE $2 \
-             ORDER BY id ASC LIMIT $3 OFFSET $4",
-            // Here we use format to add postgres-specific syntax for partial matching. It's important that this is done in the input data, before being sanitized for vulnarabilities.
-            &[&namespace, &format!("%{}%", query), &(page_size as i64), &(page as i64 * page_size as i64)]
-        )
-        .unwrap()
+            "SELECT revision, namespace, version, valid_from, valid_to FROM entry_revisions \
+            WHERE entry_id = $1 AND generation = (SELECT max(generation) FROM entry_revisions WHERE entry_id = $1) \
+            ORDER BY revision ASC",
+            &[&(id as i64)]
+        )?
         .iter()
-        .map(|row| Self::from_row(row))
+        .map(|row| Self::revision_from_row(row, false))
         .collect()
     }
 
-    pub fn insert(&self, c: &mut postgres::Client, namespace: String) -> u64 {
-        c.query_one(
-            "INSERT INTO entries (namespace, content) VALUES ($1, $2) RETURNING id",
-            &[&namespace, &to_string(&self.0).unwrap()]
-        )
-        .expect("Failed to insert item!")
-        .get::<_, i64>("id") as u64
+    /// Returns the revision of the entry (which must be in the namespace now) with its content. Only
+    /// revisions of the current generation of the entry are available (see `revisions`).
+    pub fn revision(c: &mut impl postgres::GenericClient, id: u64, namespace: &str, revision: u32) -> Result<EntryRevision, ApiError> {
+        match c.query_opt(
+            "SELECT r.*, e.namespace AS actual_namespace, e.namespace = $3 AS in_namespace FROM entries e \
+            LEFT JOIN entry_revisions r ON r.entry_id = e.id AND r.revision = $2 \
+                AND r.generation = (SELECT max(generation) FROM entry_revisions WHERE entry_id = e.id) \
+            WHERE e.id = $1",
+            &[&(id as i64), &(revision as i32), &namespace]
+        )? {
+            Some(row) if !row.get::<_, bool>("in_namespace") => Err(ApiError::NamespaceMismatch(id, row.get("actual_namespace"))),
+            Some(row) if row.get::<_, Option<i64>>("entry_id").is_some() => Self::revision_from_row(&row, true),
+            Some(_) => Err(ApiError::RevisionNotFound(id, revision)),
+            None => Err(ApiError::NotFound(id)),
+        }
     }
 
-    pub fn insert_raw(c: &mut postgres::Client, namespace: String, entry: &Value) -> u64 {
-        c.query_one(
-            "INSERT INTO entries (namespace, content) VALUES ($1, $2) RETURNING id",
-            &[&namespace, &to_string(entry).unwrap()]
-        )
-        .expect("Failed to insert item!")
-        .get::<_, i64>("id") as u64
+    /// Replaces content of the entry with the content of its revision, which makes a new revision.
+    /// Content must conform to JSON Schema of the namespace, if there is one. Returns the entry.
+    pub fn restore(c: &mut postgres::Client, id: u64, namespace: String, revision: u32, if_match: Option<&Precondition>) -> Result<EntryResponse, ApiError> {
+        let mut tx = c.transaction()?;
+
+        Self::lock(&mut tx, id, &namespace, if_match)?;
+        let content = Self::revision(&mut tx, id, &namespace, revision)?.content.unwrap_or_default();
+        Self::validate(&mut tx, &namespace, &content)?;
+
+        let row = tx.query_one(
+            "UPDATE entries SET content = $2, updated_at = now(), version = nextval('entries_version_seq') \
+            WHERE id = $1 RETURNING *",
+            &[&(id as i64), &content]
+        )?;
+        tx.commit()?;
+        Self::from_row(&row)
     }
 
-    pub fn put(&self, c: &mut postgres::Client, id: u64, namespace: String) -> u64 {
-        c.query_one(
-            "INSERT INTO entries (id, namespace, content) VALUES ($1, $2, $3) ON CONFLICT (id) \
-            DO UPDATE SET namespace = EXCLUDED.namespace, content = EXCLUDED.content RETURNING id",
-            &[&(id as i64), &namespace, &to_string(&self.0).unwrap()]
-        )
-        .unwrap().get::<_, i64>("id") as u64
+    /// Applies the patch to the content of the entry. Entry is locked while the patch is applied,
+    /// so concurrent writers can't change it in between. Returns the patched entry.
+    pub fn patch(c: &mut postgres::Client, id: u64, namespace: String, patch: &Patch, if_match: Option<&Precondition>) -> Result<EntryResponse, ApiError> {
+        let mut tx = c.transaction()?;
+
+        let entry = Self::lock(&mut tx, id, &namespace, if_match)?;
+        let content = patch.apply(entry.content).map_err(ApiError::Patch)?;
+        Self::validate(&mut tx, &namespace, &content)?;
+
+        let row = tx.query_one(
+            "UPDATE entries SET content = $2, updated_at = now(), version = nextval('entries_version_seq') \
+            WHERE id = $1 RETURNING *",
+            &[&(id as i64), &content]
+        )?;
+        tx.commit()?;
+        Self::from_row(&row)
     }
 
-    pub fn delete_all(c: &mut postgres::Client, namespace: String) -> u64 {
-        c.query_one(
+    pub fn delete_all(c: &mut postgres::Client, namespace: String) -> Result<u64, ApiError> {
+        let mut tx = c.transaction()?;
+        mark_bulk_write(&mut tx)?;
+
+        let deleted = tx.query_one(
             "WITH rows as (DELETE FROM entries WHERE namespace = $1 RETURNING *) \
             SELECT COUNT(*) FROM rows",
             &[&namespace]
-        )
-        .expect("Fatal error on deletion!")
-        .get::<_, i64>("count") as u64
+        )?
+        .get::<_, i64>("count") as u64;
+
+        tx.commit()?;
+        Ok(deleted)
     }
 
-    pub fn delete_one(c: &mut postgres::Client, id: u64, namespace: String) -> Result<u64, u64> {
-        match c.query_one(
-            "DELETE FROM entries WHERE id = $1 AND namespace = $2 RETURNING id",
-            &[&(id as i64), &namespace]
-        ) {
-            Ok(_) => Ok(id),
-            Err(_) => Err(id)
+    /// Checks selected entries against JSON Schema of another namespace, before they are copied
+    /// or moved there. Pointers of violations start with ID of the entry, e.g. "/42/name".
+    fn validate_selected(c: &mut impl postgres::GenericClient, selection: &Selection, target: &str) -> Result<(), ApiError> {
+        let schema = match Schema::load(c, target)? {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+
+        let mut violations = vec![];
+        let mut after = 0;
+        loop {
+            let mut conditions = Self::list_conditions(selection);
+            let source = Self::list_source(&mut conditions, selection);
+            let cursor = conditions.bind(after);
+            conditions.push(format!("id > {}", cursor));
+            let limit = conditions.bind(VALIDATE_BATCH_SIZE);
+
+            let rows = c.query(
+                format!("SELECT id, content FROM {} WHERE {} ORDER BY id ASC LIMIT {}", source, conditions.sql(), limit).as_str(),
+                &conditions.params()
+            )?;
+            for row in &rows {
+                let id = row.try_get::<_, i64>("id").map_err(|e| ApiError::Malformed(e.to_string()))?;
+                let content = row.try_get::<_, Value>("content").map_err(|e| ApiError::Malformed(e.to_string()))?;
+                violations.extend(schema.validate(&content).into_iter().map(|v| Violation {
+                    pointer: format!("/{}{}", id, v.pointer),
+                    message: v.message,
+                }));
+                after = id;
+            }
+
+            if (rows.len() as i64) < VALIDATE_BATCH_SIZE {
+                break;
+            }
+        }
+
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(ApiError::SchemaViolation(violations)),
+        }
+    }
+
+    /// Copies selected entries into another namespace, in the order of their IDs (as they were at
+    /// the time, if selection is made as of some time). Copies get new IDs, but keep timestamps
+    /// of the originals. Returns amount of copied entries.
+    pub fn copy_selected(c: &mut impl postgres::GenericClient, selection: &Selection, target: &str) -> Result<u64, ApiError> {
+        Self::validate_selected(c, selection, target)?;
+        mark_bulk_write(c)?;
+
+        let mut conditions = Self::list_conditions(selection);
+        let source = Self::list_source(&mut conditions, selection);
+        let target = conditions.bind(target.to_string());
+        Ok(c.execute(
+            format!(
+                "INSERT INTO entries (namespace, content, created_at, updated_at) \
+                SELECT {}, content, created_at, updated_at FROM {} WHERE {} ORDER BY id ASC",
+                target, source, conditions.sql()
+            ).as_str(),
+            &conditions.params()
+        )?)
+    }
+
+    /// Moves all entries of the namespace into another one. Entries keep their IDs and timestamps.
+    /// Returns amount of moved entries.
+    pub fn move_all(c: &mut impl postgres::GenericClient, namespace: &str, target: &str) -> Result<u64, ApiError> {
+        let selection = Selection {
+            namespace: namespace.to_string(),
+            query:     None,
+            filter:    Filter::default(),
+            range:     TimeRange::default(),
+        };
+        Self::validate_selected(c, &selection, target)?;
+        mark_bulk_write(c)?;
+
+        Ok(c.execute("UPDATE entries SET namespace = $2 WHERE namespace = $1", &[&namespace, &target])?)
+    }
+
+    pub fn delete_one(c: &mut postgres::Client, id: u64, namespace: String, if_match: Option<&Precondition>) -> Result<u64, ApiError> {
+        let mut tx = c.transaction()?;
+
+        Self::lock(&mut tx, id, &namespace, if_match)?;
+        tx.execute("DELETE FROM entries WHERE id = $1", &[&(id as i64)])?;
+
+        tx.commit()?;
+        Ok(id)
+    }
+}
+
+
+// Marks the rest of the transaction as a namespace-wide operation, so its statements which change
+// many entries are announced as resets of namespaces, not entry by entry (see migration 0015).
+fn mark_bulk_write(c: &mut impl postgres::GenericClient) -> Result<(), ApiError> {
+    c.execute("SELECT set_config('voyeur.bulk_write', 'on', true)", &[])?;
+    Ok(())
+}
+
+
+/// Reads request body and parses it as JSON. On failure error message is stored for the
+/// catcher, and caller should fail with 400 status.
+pub async fn read_json(req: &Request<'_>, data: Data) -> Result<Value, ()> {
+    // This is an optional header which defines the size in bytes of data sent
+    // in the request. By default size is capped at 1MB, and if you want to send
+    // bigger data, you must provide X-Content-Length. If body of the request is
+    // any longer than provided length, server will return an error.
+    let limit = match req.headers().get_one("X-Content-Length") {
+        Some(raw_size) => match raw_size.parse::<u32>() {
+            Ok(size) => size.bytes(),
+            // If we got bad data we better off making it clear
+            // than silently setting default buffer limit.
+            Err(e) => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_content_length_parse",
+                    "message": format!("Couldn't parse X-Content-Length with error: '{}'!", e)
+                }))));
+                return Err(());
+            }
+        },
+        None => DEFAULT_BUFFER_LIMIT.bytes()
+    };
+
+    // Reading body into the buffer and trying to parse it.
+    match data.open(limit).into_string().await {
+        Ok(string) => match string {
+            s if s.is_complete() => match from_str::<Value>(&s) {
+                Ok(valid_data) => Ok(valid_data),
+                Err(e) => {
+                    req.local_cache(|| ErrorMessage(Some(json!({
+                        "code":    "err_request_body_parse",
+                        "message": format!("Couldn't parse request body into proper JSON with error: '{}'!", e)
+                    }))));
+                    Err(())
+                }
+            },
+            // Here we handle error that indicates "too big buffer". We don't re-use an actual
+            // error because it contains message in the format '<some message>: "<body>"' and
+            // this is not good, because server needs to write the whole body (as in buffer) back,
+            // which, in addition to being extremely inefficient, makes the error message completely
+            // unreadable.
+            _ => {
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_buffer_too_large",
+                    "message": "Couldn't parse request body, it's too large! Default accepted size is 1MB. \
+                        Consider using X-Content-Length header to set expected buffer size."
+                }))));
+                Err(())
+            }
+        },
+        Err(e) => {
+            req.local_cache(|| ErrorMessage(Some(json!({
+                "code":    "err_request_body_read",
+                "message": format!("Couldn't read request body into string with error: '{}'!", e)
+            }))));
+            Err(())
         }
     }
 }
@@ -133,69 +786,18 @@ impl<'r> FromData<'r> for Entry {
         // request routing info to avoid forward second time. We need this instead of
         // simpler handling that existed before, because all proper error handling below
         // is then unaccessible for second handler, which makes it much less usable.
-        if data.peek(1).await == b"[" {
+        // JSON may start with whitespace, so the first byte which isn't one is checked.
+        let first = data.peek(512).await.iter().copied().find(|b| !matches!(b, b' ' | b'\t' | b'\n' | b'\r'));
+        if first == Some(b'[') {
             match req.route().expect("Route is empty during handling request body!").rank {
                 1 => return Outcome::Forward(data),
                 _ => ()
             };
         }
 
-        // This is an optional header which defines the size in bytes of data sent
-        // in the request. By default size is capped at 1MB, and if you want to send
-        // bigger data, you must provide X-Content-Length. If body of the request is
-        // any longer than provided length, server will return an error.
-        let limit = match req.headers().get_one("X-Content-Length") {
-            Some(raw_size) => match raw_size.parse::<u32>() {
-                Ok(size) => size.bytes(),
-                // If we got bad data we better off making it clear
-                // than silently setting default buffer limit.
-                Err(e) => {
-                    // Store error message.
-                    req.local_cache(|| ErrorMessage(Some(json!({
-                        "code":    "err_content_length_parse",
-                        "message": format!("Couldn't parse X-Content-Length with error: '{}'!", e)
-                    }))));
-                    return Outcome::Failure((Status::BadRequest, ()));
-                }
-            },
-            None => DEFAULT_BUFFER_LIMIT.bytes()
-        };
-
-        // Reading body into the buffer and trying to parse it.
-        match data.open(limit).into_string().await {
-            Ok(string) => match string {
-                s if s.is_complete() => match from_str::<Value>(&s) {
-                    // Return successfully.
-                    Ok(valid_data) => Outcome::Success(Entry(valid_data)),
-                    Err(e) => {
-                        req.local_cache(|| ErrorMessage(Some(json!({
-                            "code":    "err_request_body_parse",
-                            "message": format!("Couldn't parse request body into proper JSON with error: '{}'!", e)
-                        }))));
-                        Outcome::Failure((Status::BadRequest, ()))
-                    }
-                },
-                // Here we handle error that indicates "too big buffer". We don't re-use an actual
-                // error because it contains message in the format '<some message>: "<body>"' and
-                // this is not good, because server needs to write the whole body (as in buffer) back,
-                // which, in addition to being extremely inefficient, makes the error message completely
-                // unreadable.
-                _ => {
-                    req.local_cache(|| ErrorMessage(Some(json!({
-                        "code":    "err_buffer_too_large",
-                        "message": "Couldn't parse request body, it's too large! Default accepted size is 1MB. \
-                            Consider using X-Content-Length header to set expected buffer size."
-                    }))));
-                    Outcome::Failure((Status::BadRequest, ()))
-                }
-            },
-            Err(e) => {
-                req.local_cache(|| ErrorMessage(Some(json!({
-                    "code":    "err_request_body_read",
-                    "message": format!("Couldn't read request body into string with error: '{}'!", e)
-                }))));
-                Outcome::Failure((Status::BadRequest, ()))
-            }
+        match read_json(req, data).await {
+            Ok(value) => Outcome::Success(Entry(value)),
+            Err(()) => Outcome::Failure((Status::BadRequest, ())),
         }
     }
 }
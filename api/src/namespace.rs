@@ -1,58 +1,365 @@
-use crate::errors::ErrorMessage;
+use crate::timestamps::format_timestamp;
+use rocket_contrib::databases::postgres;
+use crate::errors::{ErrorMessage, ApiError};
+use crate::model::{Entry, Selection};
+use rocket_contrib::json::JsonValue;
 use rocket::{request, Request};
+use serde::Serialize;
+use time::OffsetDateTime;
 use rocket::http::Status;
+use serde_json::Value;
+
+
+// Limits of the namespace metadata.
+const MAX_DESCRIPTION_LENGTH: usize = 1024;
+const MAX_TAGS: usize = 32;
+
+
+/// Maximum length of the namespace, same as of the database column.
+pub const MAX_NAMESPACE_LENGTH: usize = 64;
 
 
 /// Value which allows to access namespace value.
 pub struct Namespace(pub String);
 
 
+/// Value which allows to access namespace which is the destination of namespace-wide operation
+/// (rename, copy or merge). It's provided with "X-Target-Namespace" header or "target" url argument.
+pub struct TargetNamespace(pub String);
+
+
+/// Checks that namespace value is usable: it must not be empty and must fit into the database
+/// column. Header and argument are the places where the value was expected, for the message.
+/// On failure returns an error message which is ready to be sent to the client.
+pub fn validate_namespace(value: &str, header: &str, arg: &str) -> Result<(), JsonValue> {
+    match value {
+        v if v.is_empty() => Err(json!({
+            "code":    "err_namespace_empty",
+            "message": format!("You must provide '{}' header or '{}' URL argument with request!", header, arg),
+        })),
+        v if v.len() > MAX_NAMESPACE_LENGTH => Err(json!({
+            "code":      "err_namespace_long",
+            "message":   format!("Provided namespace value is too big (max is {} characters, received {})!", MAX_NAMESPACE_LENGTH, v.len()),
+            "namespace": v,
+        })),
+        _ => Ok(()),
+    }
+}
+
+
+// Extracts namespace value from header or url argument and validates it.
+fn extract(req: &Request<'_>, header: &str, arg: &str) -> Result<String, ()> {
+    let namespace = match req.headers().get_one(header) {
+        Some(value) => value.to_string(),
+        // Headers are empty, so we look for url argument.
+        None => match req.query_value::<&str>(arg) {
+            // This returns some result (of parsed value).
+            Some(unparsed_value) => match unparsed_value {
+                // Return token if parsed correctly.
+                Ok(value) => value.to_string(),
+                // Return empty string if failed to parse.
+                Err(_) => "".to_string()
+            },
+            // Return empty string immediately if there is no argument.
+            None => "".to_string(),
+        }
+    };
+
+    match validate_namespace(&namespace, header, arg) {
+        // Good namespace is ready for use.
+        Ok(()) => Ok(namespace),
+        Err(message) => {
+            // Store error message.
+            req.local_cache(|| ErrorMessage(Some(message)));
+            Err(())
+        }
+    }
+}
+
+
 // Allows a route to access good namespace value.
 #[rocket::async_trait]
 impl<'r> request::FromRequest<'r> for Namespace {
     type Error = ();
 
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        // Extract value from header or url.
-        let namespace = match req.headers().get_one("X-Namespace") {
-            Some(value) => value.to_string(),
-            // Headers are empty, so we look for url argument.
-            None => match req.query_value::<&str>("namespace") {
-                // This returns some result (of parsed value).
-                Some(unparsed_value) => match unparsed_value {
-                    // Return token if parsed correctly.
-                    Ok(value) => value.to_string(),
-                    // Return empty string if failed to parse.
-                    Err(_) => "".to_string()
-                },
-                // Return empty string immediately if there is no argument.
-                None => "".to_string(),
-            }
+        match extract(req, "X-Namespace", "namespace") {
+            Ok(namespace) => request::Outcome::Success(Namespace(namespace)),
+            // Forward to error catcher.
+            Err(()) => request::Outcome::Failure((Status::BadRequest, ())),
+        }
+    }
+}
+
+
+// Allows a route to access good target namespace value.
+#[rocket::async_trait]
+impl<'r> request::FromRequest<'r> for TargetNamespace {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match extract(req, "X-Target-Namespace", "target") {
+            Ok(namespace) => request::Outcome::Success(TargetNamespace(namespace)),
+            // Forward to error catcher.
+            Err(()) => request::Outcome::Failure((Status::BadRequest, ())),
+        }
+    }
+}
+
+
+/// Optional metadata of the namespace, which is provided by its users.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Metadata {
+    pub description: Option<String>,
+    pub owner:       Option<String>,
+    pub tags:        Vec<String>,
+}
+
+
+impl Metadata {
+    /// Parses metadata from the request body: {"description": <string>, "owner": <string>,
+    /// "tags": [<string>]}, every field is optional. On failure returns an error message which
+    /// is ready to be sent to the client.
+    pub fn parse(value: &Value) -> Result<Metadata, JsonValue> {
+        let fail = |message: &str| Err(json!({
+            "code":    "err_namespace_metadata",
+            "message": message,
+        }));
+
+        let fields = match value.as_object() {
+            Some(fields) => fields,
+            None => return fail("Namespace metadata must be a JSON object!"),
         };
+        if let Some(key) = fields.keys().find(|key| !["description", "owner", "tags"].contains(&key.as_str())) {
+            return fail(&format!("Unknown field '{}', expected any of: description, owner, tags!", key));
+        }
 
-        match namespace {
-            v if v.is_empty() => {
-                // Store error message.
-                req.local_cache(|| ErrorMessage(Some(json!({
-                    "code":    "err_namespace_empty",
-                    "message": "You must provide 'X-Namespace' header or 'namespace' URL argument with request!",
-                }))));
-                // Forward to error catcher.
-                request::Outcome::Failure((Status::BadRequest, ()))
+        let description = match fields.get("description").unwrap_or(&Value::Null) {
+            Value::Null => None,
+            Value::String(description) if description.chars().count() <= MAX_DESCRIPTION_LENGTH => Some(description.clone()),
+            _ => return fail(&format!("Description must be a string of up to {} characters!", MAX_DESCRIPTION_LENGTH)),
+        };
+        let owner = match fields.get("owner").unwrap_or(&Value::Null) {
+            Value::Null => None,
+            Value::String(owner) if !owner.is_empty() && owner.len() <= 64 => Some(owner.clone()),
+            _ => return fail("Owner must be a string of 1 to 64 characters!"),
+        };
+        let tags = match fields.get("tags").unwrap_or(&Value::Null) {
+            Value::Null => vec![],
+            Value::Array(tags) if tags.len() <= MAX_TAGS => {
+                let mut parsed = vec![];
+                for tag in tags {
+                    match tag.as_str() {
+                        Some(tag) if !tag.is_empty() && tag.len() <= 64 => if !parsed.iter().any(|t| t == tag) {
+                            parsed.push(tag.to_string());
+                        },
+                        _ => return fail("Tags must be strings of 1 to 64 characters!"),
+                    }
+                }
+                parsed
             },
-            v if v.len() > 64 => {
-                // Store error message.
-                req.local_cache(|| ErrorMessage(Some(json!({
-                    "code":      "err_namespace_long",
-                    "message":   format!("Provided namespace value is too big (max is 64 characters, received {})!", v.len()),
-                    "namespace": v,
-                }))));
-                // Forward to error catcher.
-                request::Outcome::Failure((Status::BadRequest, ()))
+            _ => return fail(&format!("Tags must be an array of up to {} strings!", MAX_TAGS)),
+        };
+
+        Ok(Metadata { description, owner, tags })
+    }
+}
+
+
+/// Namespace together with its metadata and statistics of its entries. Namespaces appear when
+/// first entry is created, or when metadata is saved, whichever happens first.
+#[derive(Debug, Clone, Serialize)]
+pub struct NamespaceInfo {
+    pub name:          String,
+    #[serde(flatten)]
+    pub metadata:      Metadata,
+    pub entries:       u64,
+    /// Size of the content of all entries, as stored by the database (binary and maybe compressed).
+    pub content_bytes: u64,
+    pub first_id:      Option<u64>,
+    pub last_id:       Option<u64>,
+    /// Time of the last creation or update of an entry (deletions are not tracked).
+    pub last_write_at: Option<String>,
+}
+
+
+// Statistics are computed on the fly, so there is nothing to keep in sync, but listing reads every
+// entry (for a single namespace, every entry of it). Stored size is used, because it doesn't make
+// the database serialize (or decompress) the content. Placeholder is replaced with the condition
+// on namespace (for single namespace) or with nothing.
+const INFO_QUERY: &str = "\
+    WITH stats AS ( \
+      SELECT namespace, count(*) AS entries, sum(pg_column_size(content)) AS content_bytes, \
+             min(id) AS first_id, max(id) AS last_id, max(updated_at) AS last_write_at \
+      FROM entries {entries_filter} GROUP BY namespace \
+    ) \
+    SELECT COALESCE(n.name, s.namespace) AS name, n.description, n.owner, COALESCE(n.tags, '[]') AS tags, \
+           COALESCE(s.entries, 0)::BIGINT AS entries, COALESCE(s.content_bytes, 0)::BIGINT AS content_bytes, \
+           s.first_id, s.last_id, s.last_write_at \
+    FROM (SELECT * FROM namespaces {namespaces_filter}) n FULL OUTER JOIN stats s ON s.namespace = n.name \
+    ORDER BY 1 ASC";
+
+
+impl NamespaceInfo {
+    fn from_row(row: &postgres::Row) -> Result<NamespaceInfo, ApiError> {
+        let malformed = |e: postgres::Error| ApiError::Malformed(e.to_string());
+        Ok(NamespaceInfo {
+            name: row.try_get("name").map_err(malformed)?,
+            metadata: Metadata {
+                description: row.try_get("description").map_err(malformed)?,
+                owner: row.try_get("owner").map_err(malformed)?,
+                tags: serde_json::from_value(row.try_get::<_, Value>("tags").map_err(malformed)?)
+                    .map_err(|e| ApiError::Malformed(e.to_string()))?,
             },
-            // Good namespace is ready for use.
-            v => request::Outcome::Success(Namespace(v))
+            entries: row.try_get::<_, i64>("entries").map_err(malformed)? as u64,
+            content_bytes: row.try_get::<_, i64>("content_bytes").map_err(malformed)? as u64,
+            first_id: row.try_get::<_, Option<i64>>("first_id").map_err(malformed)?.map(|id| id as u64),
+            last_id: row.try_get::<_, Option<i64>>("last_id").map_err(malformed)?.map(|id| id as u64),
+            last_write_at: row.try_get::<_, Option<OffsetDateTime>>("last_write_at").map_err(malformed)?.map(format_timestamp),
+        })
+    }
+
+    /// Returns all namespaces, ordered by name.
+    pub fn list(c: &mut postgres::Client) -> Result<Vec<NamespaceInfo>, ApiError> {
+        let query = INFO_QUERY.replace("{entries_filter}", "").replace("{namespaces_filter}", "");
+        c.query(query.as_str(), &[])?
+            .iter()
+            .map(Self::from_row)
+            .collect()
+    }
+
+    /// Returns single namespace, or None if it has neither entries nor metadata.
+    pub fn get(c: &mut postgres::Client, namespace: &str) -> Result<Option<NamespaceInfo>, ApiError> {
+        let query = INFO_QUERY.replace("{entries_filter}", "WHERE namespace = $1").replace("{namespaces_filter}", "WHERE name = $1");
+        match c.query_opt(query.as_str(), &[&namespace])? {
+            Some(row) => Ok(Some(Self::from_row(&row)?)),
+            None => Ok(None),
         }
     }
+
+    /// Saves metadata of the namespace, replacing the previous one.
+    pub fn save_metadata(c: &mut postgres::Client, namespace: &str, metadata: &Metadata) -> Result<(), ApiError> {
+        let tags = serde_json::to_value(&metadata.tags).map_err(|e| ApiError::Malformed(e.to_string()))?;
+        c.execute(
+            "INSERT INTO namespaces (name, description, owner, tags) VALUES ($1, $2, $3, $4) ON CONFLICT (name) \
+            DO UPDATE SET description = EXCLUDED.description, owner = EXCLUDED.owner, tags = EXCLUDED.tags, updated_at = now()",
+            &[&namespace, &metadata.description, &metadata.owner, &tags]
+        )?;
+        Ok(())
+    }
+
+    /// Deletes metadata of the namespace (entries are kept). Returns false if there was none.
+    pub fn delete_metadata(c: &mut postgres::Client, namespace: &str) -> Result<bool, ApiError> {
+        Ok(c.execute("DELETE FROM namespaces WHERE name = $1", &[&namespace])? > 0)
+    }
+}
+
+
+// Returns true if namespace has entries, metadata, JSON Schema or webhooks.
+fn exists(c: &mut impl postgres::GenericClient, namespace: &str) -> Result<bool, ApiError> {
+    Ok(c.query_one(
+        "SELECT EXISTS (SELECT 1 FROM entries WHERE namespace = $1) \
+            OR EXISTS (SELECT 1 FROM namespaces WHERE name = $1) \
+            OR EXISTS (SELECT 1 FROM namespace_schemas WHERE namespace = $1) \
+            OR EXISTS (SELECT 1 FROM webhooks WHERE namespace = $1) AS exists",
+        &[&namespace]
+    )?
+    .get::<_, bool>("exists"))
+}
+
+
+// Locks both namespaces until the end of the transaction, so checks of their existence stay true
+// until commit. It's the same lock which writers of entries take for the change log (see migration
+// 0012), in the same order (as the database sorts the names), so renames and merges wait for writers
+// of both namespaces and the other way around. Writers of an entry lock its row before the namespace,
+// so when entries are moved, their rows must be locked first (see `lock_entries`), otherwise moving
+// one of them would wait for a writer which waits for this lock.
+fn lock(tx: &mut postgres::Transaction<'_>, namespace: &str, target: &str) -> Result<(), ApiError> {
+    let names = tx.query_one(
+        "SELECT LEAST($1::TEXT, $2::TEXT) AS first, GREATEST($1::TEXT, $2::TEXT) AS second",
+        &[&namespace, &target]
+    )?;
+    for column in &["first", "second"] {
+        tx.execute("SELECT lock_entry_change_log($1)", &[&names.get::<_, String>(*column)])?;
+    }
+    Ok(())
+}
+
+
+// Locks all entries of the namespace until the end of the transaction (in order of IDs, so two
+// of these don't deadlock each other). Entries created after this are left unlocked, but their
+// writers only wait for the namespace, which isn't locked yet.
+fn lock_entries(tx: &mut postgres::Transaction<'_>, namespace: &str) -> Result<(), ApiError> {
+    tx.execute("SELECT id FROM entries WHERE namespace = $1 ORDER BY id FOR UPDATE", &[&namespace])?;
+    Ok(())
+}
+
+
+/// Renames the namespace: its entries, metadata, JSON Schema and webhooks are moved to the target,
+/// which must not exist yet. Returns amount of moved entries. Note that API key grants are not changed.
+pub fn rename(c: &mut postgres::Client, namespace: &str, target: &str) -> Result<u64, ApiError> {
+    let mut tx = c.transaction()?;
+    lock_entries(&mut tx, namespace)?;
+    lock(&mut tx, namespace, target)?;
+
+    if !exists(&mut tx, namespace)? {
+        return Err(ApiError::NamespaceNotFound(namespace.to_string()));
+    }
+    if exists(&mut tx, target)? {
+        return Err(ApiError::NamespaceConflict(target.to_string()));
+    }
+
+    let moved = Entry::move_all(&mut tx, namespace, target)?;
+    tx.execute("UPDATE namespaces SET name = $2, updated_at = now() WHERE name = $1", &[&namespace, &target])?;
+    tx.execute("UPDATE namespace_schemas SET namespace = $2 WHERE namespace = $1", &[&namespace, &target])?;
+    // Entries didn't really change, so deliveries of their "deletion" which the move has just queued
+    // (in this transaction) are dropped, and webhooks keep receiving changes under the new name.
+    tx.execute(
+        "DELETE FROM webhook_deliveries d USING webhooks w \
+        WHERE w.id = d.webhook_id AND w.namespace = $1 AND d.created_at = now()",
+        &[&namespace]
+    )?;
+    tx.execute("UPDATE webhooks SET namespace = $2 WHERE namespace = $1", &[&namespace, &target])?;
+
+    tx.commit()?;
+    Ok(moved)
+}
+
+
+/// Copies selected entries of the namespace into the target, which may already exist. Entries
+/// must conform to JSON Schema of the target, if it has one. Returns amount of copied entries.
+pub fn copy(c: &mut postgres::Client, selection: &Selection, target: &str) -> Result<u64, ApiError> {
+    let mut tx = c.transaction()?;
+    lock(&mut tx, &selection.namespace, target)?;
+
+    if !exists(&mut tx, &selection.namespace)? {
+        return Err(ApiError::NamespaceNotFound(selection.namespace.clone()));
+    }
+    let copied = Entry::copy_selected(&mut tx, selection, target)?;
+
+    tx.commit()?;
+    Ok(copied)
 }
 
+
+/// Merges the namespace into the target: all entries are moved to the target (they must conform
+/// to its JSON Schema, if it has one), while metadata, JSON Schema and webhooks (with their
+/// deliveries) of the namespace are deleted.
+/// Returns amount of moved entries.
+pub fn merge(c: &mut postgres::Client, namespace: &str, target: &str) -> Result<u64, ApiError> {
+    let mut tx = c.transaction()?;
+    lock_entries(&mut tx, namespace)?;
+    lock(&mut tx, namespace, target)?;
+
+    if !exists(&mut tx, namespace)? {
+        return Err(ApiError::NamespaceNotFound(namespace.to_string()));
+    }
+
+    let moved = Entry::move_all(&mut tx, namespace, target)?;
+    tx.execute("DELETE FROM namespaces WHERE name = $1", &[&namespace])?;
+    tx.execute("DELETE FROM namespace_schemas WHERE namespace = $1", &[&namespace])?;
+    tx.execute("DELETE FROM webhooks WHERE namespace = $1", &[&namespace])?;
+
+    tx.commit()?;
+    Ok(moved)
+}
@@ -0,0 +1,197 @@
+use crate::auth::{Scoped, Read, Admin, ListNamespaces};
+use crate::namespace::{self, Namespace, TargetNamespace, NamespaceInfo, Metadata};
+use crate::model::{ApiDatabase, Entry, Selection};
+use crate::responders::CustomResponder;
+use crate::errors::ApiError;
+use crate::timestamps::TimeRange;
+use crate::filter::Filter;
+
+
+// Checks that target namespace differs from the source one and that the key is an admin of it
+// as well (scope guard only checks the source namespace).
+fn check_target(namespace: &Namespace, target: &TargetNamespace, key: &Scoped<Admin>) -> Result<(), CustomResponder> {
+    if namespace.0 == target.0 {
+        return Err(CustomResponder::BadRequest(json!({
+            "code": "err_namespace_same",
+            "message": "Source and target namespaces must be different!",
+            "namespace": &namespace.0,
+        })));
+    }
+    if !key.allows(&target.0) {
+        return Err(CustomResponder::Forbidden(json!({
+            "code": "err_api_key_scope",
+            "message": format!("API key doesn't grant 'admin' scope for namespace '{}'!", &target.0),
+        })));
+    }
+    Ok(())
+}
+
+
+/// This endpoint is used to receive all namespaces (ordered by name) with their metadata and
+/// statistics: amount of entries, total size of their content (as stored), first and last ID and
+/// time of the last write. Namespaces which the API key can't read are not listed. Statistics are
+/// computed on every request from all entries, so on large stores this endpoint is as slow as
+/// a full scan of entries; prefer the endpoint below for a single namespace.
+#[get("/")]
+pub async fn list_namespaces(key: Scoped<ListNamespaces>, conn: ApiDatabase) -> CustomResponder {
+    match conn.run(|c| NamespaceInfo::list(c)).await {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "data": data.into_iter().filter(|info| key.allows(&info.name)).collect::<Vec<NamespaceInfo>>(),
+        })),
+        Err(e) => e.into(),
+    }
+}
+
+
+/// This endpoint is used to receive single namespace with its metadata and statistics. For this
+/// endpoint you must provide namespace (url argument <namespace> or header "X-Namespace", of type <String>).
+#[get("/info")]
+pub async fn get_namespace(namespace: Namespace, _key: Scoped<Read>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| NamespaceInfo::get(c, &namespace.0)).await {
+        Ok(Some(data)) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "data": data,
+        })),
+        Ok(None) => ApiError::NamespaceNotFound(namespace_copy).into(),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to describe the namespace (or replace its description). Body of the
+/// request is a JSON object with optional fields: "description" (<String>, up to 1024 characters),
+/// "owner" (<String>, up to 64 characters) and "tags" (list of up to 32 <String>), example:
+/// {"description": "Nightly benchmarks", "owner": "team-a", "tags": ["ci"]}. Namespace doesn't have
+/// to contain entries yet. For this endpoint you must provide namespace (url argument <namespace>
+/// or header "X-Namespace", of type <String>), and a key with admin scope for it.
+#[put("/", format = "application/json", data = "<body>")]
+pub async fn put_namespace(namespace: Namespace, _key: Scoped<Admin>, body: Entry, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+    let metadata = match Metadata::parse(&body.0) {
+        Ok(metadata) => metadata,
+        Err(message) => return CustomResponder::BadRequest(message),
+    };
+
+    let result = conn.run(move |c| {
+        NamespaceInfo::save_metadata(c, &namespace.0, &metadata)?;
+        NamespaceInfo::get(c, &namespace.0)
+    }).await;
+
+    match result {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "info_namespace_saved",
+            "message": format!("Successfully saved metadata of namespace '{}'!", &namespace_copy),
+            "data": data,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to delete metadata of the namespace, entries are not affected. For this
+/// endpoint you must provide namespace (url argument <namespace> or header "X-Namespace", of type
+/// <String>), and a key with admin scope for it.
+#[delete("/")]
+pub async fn delete_namespace_metadata(namespace: Namespace, _key: Scoped<Admin>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| NamespaceInfo::delete_metadata(c, &namespace.0)).await {
+        Ok(true) => CustomResponder::Ok(json!({
+            "code": "info_namespace_metadata_deleted",
+            "message": format!("Successfully deleted metadata of namespace '{}'!", &namespace_copy),
+            "namespace": &namespace_copy,
+        })),
+        Ok(false) => CustomResponder::NotFound(json!({
+            "code": "err_namespace_not_found",
+            "message": format!("Namespace '{}' has no metadata!", &namespace_copy),
+            "namespace": &namespace_copy,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to rename the namespace (e.g. to promote staging namespace to production).
+/// Entries keep their IDs and are moved together with metadata, JSON Schema and webhooks in a single
+/// transaction. Target namespace must not exist yet (have entries, metadata, JSON Schema or webhooks),
+/// otherwise 409 'err_namespace_conflict' is returned. API keys are not changed, so grants for the new name must be added separately. For this
+/// endpoint you must provide namespace (url argument <namespace> or header "X-Namespace", of type <String>)
+/// and target namespace (url argument <target> or header "X-Target-Namespace", of type <String>),
+/// and a key with admin scope for both of them.
+#[post("/rename")]
+pub async fn rename_namespace(namespace: Namespace, target: TargetNamespace, key: Scoped<Admin>, conn: ApiDatabase) -> CustomResponder {
+    if let Err(response) = check_target(&namespace, &target, &key) {
+        return response;
+    }
+    let (namespace_copy, target_copy) = (namespace.0.clone(), target.0.clone());
+
+    match conn.run(move |c| namespace::rename(c, &namespace.0, &target.0)).await {
+        Ok(moved) => CustomResponder::Ok(json!({
+            "code": "info_namespace_renamed",
+            "message": format!("Successfully renamed namespace '{}' to '{}'!", &namespace_copy, &target_copy),
+            "namespace": &namespace_copy,
+            "target": &target_copy,
+            "moved": moved,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to copy entries of the namespace into the target namespace (e.g. to clone
+/// a dataset before an experiment), which may already have entries. Copies get new IDs (in the order
+/// of originals) and keep timestamps. Which entries are copied can be limited with the same filter
+/// and time range as for listing (see `get_paginated_entries`). If target namespace has JSON Schema,
+/// all copied entries must conform to it, otherwise nothing is copied. For this endpoint you must
+/// provide namespace (url argument <namespace> or header "X-Namespace", of type <String>) and target
+/// namespace (url argument <target> or header "X-Target-Namespace", of type <String>), and a key
+/// with admin scope for both of them.
+#[post("/copy")]
+pub async fn copy_namespace(namespace: Namespace, target: TargetNamespace, key: Scoped<Admin>, filter: Filter, range: TimeRange, conn: ApiDatabase) -> CustomResponder {
+    if let Err(response) = check_target(&namespace, &target, &key) {
+        return response;
+    }
+    let (namespace_copy, target_copy) = (namespace.0.clone(), target.0.clone());
+    let selection = Selection { namespace: namespace.0, query: None, filter, range };
+
+    match conn.run(move |c| namespace::copy(c, &selection, &target.0)).await {
+        Ok(copied) => CustomResponder::Ok(json!({
+            "code": "info_namespace_copied",
+            "message": format!("Successfully copied {} entries from namespace '{}' to '{}'!", copied, &namespace_copy, &target_copy),
+            "namespace": &namespace_copy,
+            "target": &target_copy,
+            "copied": copied,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to merge the namespace into the target namespace (e.g. after a producer
+/// wrote into a wrong namespace). All entries are moved (keeping their IDs) and the namespace
+/// ceases to exist: its metadata, JSON Schema and webhooks are deleted, while the ones of the target are kept.
+/// If target namespace has JSON Schema, all moved entries must conform to it, otherwise nothing is
+/// moved. For this endpoint you must provide namespace (url argument <namespace> or header
+/// "X-Namespace", of type <String>) and target namespace (url argument <target> or header
+/// "X-Target-Namespace", of type <String>), and a key with admin scope for both of them.
+#[post("/merge")]
+pub async fn merge_namespace(namespace: Namespace, target: TargetNamespace, key: Scoped<Admin>, conn: ApiDatabase) -> CustomResponder {
+    if let Err(response) = check_target(&namespace, &target, &key) {
+        return response;
+    }
+    let (namespace_copy, target_copy) = (namespace.0.clone(), target.0.clone());
+
+    match conn.run(move |c| namespace::merge(c, &namespace.0, &target.0)).await {
+        Ok(moved) => CustomResponder::Ok(json!({
+            "code": "info_namespace_merged",
+            "message": format!("Successfully merged namespace '{}' into '{}'!", &namespace_copy, &target_copy),
+            "namespace": &namespace_copy,
+            "target": &target_copy,
+            "moved": moved,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
@@ -0,0 +1,137 @@
+use rocket::tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use rocket::request::{Outcome, Request, FromRequest};
+use rocket::data::{Data, DataStream, ByteUnit, ToByteUnit};
+use serde_json::{from_slice, Value};
+use crate::errors::ErrorMessage;
+use rocket::http::Status;
+
+
+// Maximum size of a single line (entry). Same as the default limit of the JSON body.
+const MAX_LINE_SIZE: u64 = 1024 * 1024;
+// Default limit of the whole NDJSON body is 1GB, use X-Content-Length to send more.
+const DEFAULT_BODY_LIMIT: u64 = 1024 * 1024 * 1024;
+
+
+/// Value which allows to access limit of the streamed request body. It's read from the
+/// "X-Content-Length" header, same as for JSON bodies, but with much bigger default.
+pub struct BodyLimit(pub ByteUnit);
+
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BodyLimit {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        match req.headers().get_one("X-Content-Length") {
+            Some(raw_size) => match raw_size.parse::<u64>() {
+                Ok(size) => Outcome::Success(BodyLimit(size.bytes())),
+                Err(e) => {
+                    // Store error message.
+                    req.local_cache(|| ErrorMessage(Some(json!({
+                        "code":    "err_content_length_parse",
+                        "message": format!("Couldn't parse X-Content-Length with error: '{}'!", e)
+                    }))));
+                    Outcome::Failure((Status::BadRequest, ()))
+                }
+            },
+            None => Outcome::Success(BodyLimit(DEFAULT_BODY_LIMIT.bytes())),
+        }
+    }
+}
+
+
+/// Single line of NDJSON body: line number (starting from 1) and either parsed value, or an
+/// error message explaining why the line was rejected.
+pub type Line = (u64, Result<Value, String>);
+
+
+/// Reader of newline-delimited JSON body, which reads it in batches of lines, so only a single
+/// batch is kept in memory at a time. Empty lines are skipped. Body which is longer than the limit
+/// is an error, and a line cut by the limit is never parsed.
+pub struct NdjsonReader {
+    stream:   BufReader<DataStream>,
+    limit:    u64,
+    read:     u64,
+    line:     u64,
+    finished: bool,
+}
+
+
+impl NdjsonReader {
+    pub fn new(body: Data, limit: ByteUnit) -> Self {
+        // Opening one byte more than allowed, so we can tell if the body is too long.
+        let stream = body.open(limit.as_u64().saturating_add(1).bytes());
+        NdjsonReader {
+            stream:   BufReader::new(stream),
+            limit:    limit.as_u64(),
+            read:     0,
+            line:     0,
+            finished: false,
+        }
+    }
+
+    /// Returns true if body is longer than the limit. Reading stops with an error then.
+    pub fn overflowed(&self) -> bool {
+        self.read > self.limit
+    }
+
+    // Error which is returned when the body is over the limit.
+    fn overflow(&mut self) -> std::io::Error {
+        self.finished = true;
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Body is larger than {} bytes!", self.limit))
+    }
+
+    /// Reads up to `size` non-empty lines. Returns empty batch when the body is over, and an error
+    /// if it's longer than the limit (lines of the batch are dropped then).
+    pub async fn next_batch(&mut self, size: usize) -> std::io::Result<Vec<Line>> {
+        let mut batch = Vec::with_capacity(size);
+        let mut buffer = Vec::new();
+
+        while batch.len() < size && !self.finished {
+            buffer.clear();
+
+            // Reading one byte more than allowed, so we can tell if the line is too long.
+            let n = (&mut self.stream).take(MAX_LINE_SIZE + 1).read_until(b'\n', &mut buffer).await?;
+            if n == 0 {
+                self.finished = true;
+                break;
+            }
+
+            self.read += n as u64;
+            self.line += 1;
+            if self.overflowed() {
+                return Err(self.overflow());
+            }
+
+            if buffer.last() != Some(&b'\n') && n as u64 > MAX_LINE_SIZE {
+                // Skip the rest of the line, we don't need it anyway.
+                loop {
+                    buffer.clear();
+                    let n = (&mut self.stream).take(MAX_LINE_SIZE).read_until(b'\n', &mut buffer).await?;
+                    self.read += n as u64;
+                    if self.overflowed() {
+                        return Err(self.overflow());
+                    }
+                    if n == 0 || buffer.last() == Some(&b'\n') {
+                        break;
+                    }
+                }
+
+                batch.push((self.line, Err(format!("Line is too long, max size of a line is {} bytes!", MAX_LINE_SIZE))));
+                continue;
+            }
+
+            let line = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+                continue;
+            }
+
+            batch.push((self.line, from_slice::<Value>(line).map_err(|e| {
+                format!("Couldn't parse line into proper JSON with error: '{}'!", e)
+            })));
+        }
+
+        Ok(batch)
+    }
+}
@@ -1,10 +1,14 @@
 use rocket::request::{Outcome, Request, FromRequest};
+use rocket_contrib::json::JsonValue;
 use crate::errors::ErrorMessage;
 use rocket::http::Status;
 use serde::Serialize;
 
 
 static DEFAULT_PAGE_SIZE: u16 = 25;
+// Version prefix of the encoded cursor, so we can change its format later without
+// silently misinterpreting cursors which clients have saved.
+static CURSOR_PREFIX: &str = "v1:";
 
 
 #[derive(Debug, Clone, Serialize)]
@@ -77,3 +81,129 @@ impl<'r> FromRequest<'r> for PageSize {
     }
 }
 
+
+
+/// Column which entries are ordered by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Id,
+    CreatedAt,
+    UpdatedAt,
+}
+
+
+/// Value which allows to access ordering of entries. Value is the name of the column (id,
+/// created_at or updated_at), optionally prefixed with '-' for descending order. By default
+/// entries are ordered by ID in ascending order.
+#[derive(Debug, Clone, Copy)]
+pub struct Sort {
+    pub key:        SortKey,
+    pub descending: bool,
+}
+
+
+impl Default for Sort {
+    fn default() -> Self {
+        Sort { key: SortKey::Id, descending: false }
+    }
+}
+
+
+impl Sort {
+    pub fn parse(raw: &str) -> Option<Sort> {
+        let (descending, name) = match raw.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, raw),
+        };
+
+        let key = match name {
+            "id"         => SortKey::Id,
+            "created_at" => SortKey::CreatedAt,
+            "updated_at" => SortKey::UpdatedAt,
+            _            => return None,
+        };
+
+        Some(Sort { key, descending })
+    }
+
+    /// Returns ORDER BY clause. Timestamps are not unique, so ID is always used as a tie-breaker
+    /// to keep pages stable.
+    pub fn sql(&self) -> String {
+        let direction = match self.descending {
+            true => "DESC",
+            false => "ASC",
+        };
+
+        match self.key {
+            SortKey::Id        => format!("id {}", direction),
+            SortKey::CreatedAt => format!("created_at {d}, id {d}", d = direction),
+            SortKey::UpdatedAt => format!("updated_at {d}, id {d}", d = direction),
+        }
+    }
+}
+
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Sort {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        // Extract value from header or url.
+        let raw = match req.headers().get_one("X-Sort") {
+            Some(value) => value.to_string(),
+            None => match req.query_value::<String>("sort") {
+                Some(Ok(value)) => value,
+                Some(Err(_)) => "".to_string(),
+                // Return default ordering if there is no argument.
+                None => return Outcome::Success(Sort::default()),
+            }
+        };
+
+        match Sort::parse(&raw) {
+            Some(sort) => Outcome::Success(sort),
+            None => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_sort_invalid",
+                    "message": format!("Couldn't sort by '{}', expected one of: id, created_at, updated_at (prefixed with '-' for descending order)!", raw)
+                }))));
+                Outcome::Failure((Status::BadRequest, ()))
+            }
+        }
+    }
+}
+
+
+/// Position in the list of entries for keyset (cursor) pagination. Cursor points right after
+/// the last entry of the previous page (entries are ordered by ID), so unlike page numbers it
+/// doesn't skip or duplicate entries when something is inserted or deleted during the scan.
+/// For clients it's an opaque string, and an empty string means "start from the beginning".
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor(pub Option<u64>);
+
+
+impl Cursor {
+    pub fn decode(raw: &str) -> Result<Cursor, JsonValue> {
+        if raw.is_empty() {
+            return Ok(Cursor(None));
+        }
+
+        base64::decode_config(raw, base64::URL_SAFE_NO_PAD).ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|decoded| decoded.strip_prefix(CURSOR_PREFIX).and_then(|id| id.parse::<u64>().ok()))
+            .map(|id| Cursor(Some(id)))
+            .ok_or_else(|| json!({
+                "code":    "err_cursor_invalid",
+                "message": "Provided cursor is invalid! Use empty cursor to start from the beginning, \
+                    and 'next_cursor' value from the response to get next page.",
+                "cursor":  raw,
+            }))
+    }
+
+    pub fn encode(&self) -> String {
+        match self.0 {
+            Some(id) => base64::encode_config(format!("{}{}", CURSOR_PREFIX, id), base64::URL_SAFE_NO_PAD),
+            None => String::new(),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use rocket::{http::{Status, ContentType}, Request, Data};
+use rocket::data::{Outcome, FromData};
+use rocket_contrib::json::JsonValue;
+use crate::errors::ErrorMessage;
+use crate::model::read_json;
+use serde_json::Value;
+use std::fmt;
+
+
+/// Partial update of the entry content, either JSON Merge Patch (RFC 7396, which is sent as
+/// "application/merge-patch+json") or JSON Patch (RFC 6902, "application/json-patch+json").
+#[derive(Debug, Clone)]
+pub enum Patch {
+    Merge(Value),
+    Json(Vec<Operation>),
+}
+
+
+/// Single operation of JSON Patch. Paths are already parsed JSON pointers.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Add { path: Vec<String>, value: Value },
+    Remove { path: Vec<String> },
+    Replace { path: Vec<String>, value: Value },
+    Move { from: Vec<String>, path: Vec<String> },
+    Copy { from: Vec<String>, path: Vec<String> },
+    Test { path: Vec<String>, value: Value },
+}
+
+
+/// Reason why JSON Patch couldn't be applied to the content.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchErrorKind {
+    /// Path (or 'from') doesn't exist in the content, or its parent can't contain it.
+    Path,
+    /// Value at the path of 'test' operation is different.
+    Test,
+}
+
+
+/// Failed operation of JSON Patch. Whole patch is discarded if any operation fails.
+#[derive(Debug, Clone)]
+pub struct PatchError {
+    pub kind:    PatchErrorKind,
+    /// Index of the failed operation in the patch.
+    pub index:   usize,
+    pub pointer: String,
+    pub message: String,
+}
+
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Operation {} of the patch failed at '{}': {}", self.index, self.pointer, self.message)
+    }
+}
+
+
+// Parses JSON pointer (RFC 6901) into the list of unescaped reference tokens.
+fn parse_pointer(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(vec![]);
+    }
+    if !pointer.starts_with('/') {
+        return None;
+    }
+
+    pointer[1..].split('/')
+        .map(|token| match token.contains('~') {
+            // Only '~0' and '~1' escapes are allowed.
+            true if token.replace("~0", "").replace("~1", "").contains('~') => None,
+            _ => Some(token.replace("~1", "/").replace("~0", "~")),
+        })
+        .collect()
+}
+
+
+// Formats list of reference tokens back into JSON pointer.
+fn format_pointer(path: &[String]) -> String {
+    path.iter().map(|token| format!("/{}", token.replace('~', "~0").replace('/', "~1"))).collect()
+}
+
+
+// Parses array index of the pointer: digits without leading zeros. Returns None for anything else.
+fn parse_index(token: &str) -> Option<usize> {
+    match token {
+        "0" => Some(0),
+        t if !t.is_empty() && !t.starts_with('0') && t.chars().all(|c| c.is_ascii_digit()) => t.parse().ok(),
+        _ => None,
+    }
+}
+
+
+impl Operation {
+    /// Parses JSON Patch document. On failure returns an error message which is ready to be sent
+    /// to the client.
+    pub fn parse_all(value: &Value) -> Result<Vec<Operation>, JsonValue> {
+        let operations = value.as_array().ok_or_else(|| json!({
+            "code":    "err_patch_parse",
+            "message": "JSON Patch must be an array of operations!",
+        }))?;
+
+        operations.iter().enumerate().map(|(index, operation)| {
+            let fail = |message: String| json!({
+                "code":    "err_patch_parse",
+                "message": format!("Operation {} of the patch is malformed: {}", index, message),
+                "index":   index,
+            });
+            let pointer = |field: &str| match operation.get(field).and_then(|v| v.as_str()) {
+                Some(raw) => parse_pointer(raw).ok_or_else(|| fail(format!("'{}' is not a valid JSON pointer!", raw))),
+                None => Err(fail(format!("'{}' must be a string!", field))),
+            };
+            let value = || operation.get("value").cloned().ok_or_else(|| fail("'value' is missing!".to_string()));
+
+            match operation.get("op").and_then(|op| op.as_str()) {
+                Some("add") => Ok(Operation::Add { path: pointer("path")?, value: value()? }),
+                Some("remove") => Ok(Operation::Remove { path: pointer("path")? }),
+                Some("replace") => Ok(Operation::Replace { path: pointer("path")?, value: value()? }),
+                Some("move") => Ok(Operation::Move { from: pointer("from")?, path: pointer("path")? }),
+                Some("copy") => Ok(Operation::Copy { from: pointer("from")?, path: pointer("path")? }),
+                Some("test") => Ok(Operation::Test { path: pointer("path")?, value: value()? }),
+                Some(op) => Err(fail(format!("unknown operation '{}', expected one of: add, remove, replace, move, copy, test!", op))),
+                None => Err(fail("'op' must be a string!".to_string())),
+            }
+        }).collect()
+    }
+}
+
+
+// Returns value at the path.
+fn get<'a>(document: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(document, |value, token| match value {
+        Value::Object(fields) => fields.get(token),
+        Value::Array(items) => parse_index(token).and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+
+// Returns mutable value at the path.
+fn get_mut<'a>(document: &'a mut Value, path: &[String]) -> Option<&'a mut Value> {
+    path.iter().try_fold(document, |value, token| match value {
+        Value::Object(fields) => fields.get_mut(token),
+        Value::Array(items) => parse_index(token).and_then(move |i| items.get_mut(i)),
+        _ => None,
+    })
+}
+
+
+// Adds value at the path: replaces member of an object, or inserts into an array ('-' appends).
+fn add(document: &mut Value, path: &[String], value: Value) -> Result<(), String> {
+    let (last, parent) = match path.split_last() {
+        Some(split) => split,
+        None => {
+            *document = value;
+            return Ok(());
+        }
+    };
+
+    match get_mut(document, parent) {
+        Some(Value::Object(fields)) => {
+            fields.insert(last.clone(), value);
+            Ok(())
+        },
+        Some(Value::Array(items)) => match last.as_str() {
+            "-" => {
+                items.push(value);
+                Ok(())
+            },
+            token => match parse_index(token) {
+                Some(i) if i <= items.len() => {
+                    items.insert(i, value);
+                    Ok(())
+                },
+                _ => Err(format!("index '{}' is out of bounds of the array!", token)),
+            },
+        },
+        Some(_) => Err("parent of the path is neither an object nor an array!".to_string()),
+        None => Err("parent of the path doesn't exist!".to_string()),
+    }
+}
+
+
+// Removes value at the path and returns it.
+fn remove(document: &mut Value, path: &[String]) -> Result<Value, String> {
+    let (last, parent) = match path.split_last() {
+        Some(split) => split,
+        None => return Err("the whole document can't be removed!".to_string()),
+    };
+
+    let removed = match get_mut(document, parent) {
+        Some(Value::Object(fields)) => fields.remove(last),
+        Some(Value::Array(items)) => match parse_index(last) {
+            Some(i) if i < items.len() => Some(items.remove(i)),
+            _ => None,
+        },
+        _ => None,
+    };
+    removed.ok_or_else(|| "path doesn't exist!".to_string())
+}
+
+
+impl Patch {
+    /// Applies the patch to the content and returns the new content. Merge patch always succeeds,
+    /// while JSON Patch fails on the first operation which can't be applied.
+    pub fn apply(&self, content: Value) -> Result<Value, PatchError> {
+        match self {
+            Patch::Merge(patch) => Ok(merge(content, patch)),
+            Patch::Json(operations) => {
+                let mut document = content;
+                for (index, operation) in operations.iter().enumerate() {
+                    let fail = |kind: PatchErrorKind, path: &[String], message: String| PatchError {
+                        kind, index, pointer: format_pointer(path), message,
+                    };
+
+                    match operation {
+                        Operation::Add { path, value } => add(&mut document, path, value.clone())
+                            .map_err(|message| fail(PatchErrorKind::Path, path, message))?,
+                        Operation::Remove { path } => {
+                            remove(&mut document, path).map_err(|message| fail(PatchErrorKind::Path, path, message))?;
+                        },
+                        Operation::Replace { path, value } => match get_mut(&mut document, path) {
+                            Some(target) => *target = value.clone(),
+                            None => return Err(fail(PatchErrorKind::Path, path, "path doesn't exist!".to_string())),
+                        },
+                        Operation::Move { from, path } => {
+                            if path.len() > from.len() && path[..from.len()] == from[..] {
+                                return Err(fail(PatchErrorKind::Path, from, "value can't be moved into its own child!".to_string()));
+                            }
+                            let value = remove(&mut document, from).map_err(|message| fail(PatchErrorKind::Path, from, message))?;
+                            add(&mut document, path, value).map_err(|message| fail(PatchErrorKind::Path, path, message))?;
+                        },
+                        Operation::Copy { from, path } => {
+                            let value = get(&document, from).cloned()
+                                .ok_or_else(|| fail(PatchErrorKind::Path, from, "path doesn't exist!".to_string()))?;
+                            add(&mut document, path, value).map_err(|message| fail(PatchErrorKind::Path, path, message))?;
+                        },
+                        Operation::Test { path, value } => match get(&document, path) {
+                            Some(actual) if actual == value => (),
+                            Some(actual) => return Err(fail(PatchErrorKind::Test, path, format!("expected {}, but found {}!", value, actual))),
+                            None => return Err(fail(PatchErrorKind::Test, path, "path doesn't exist!".to_string())),
+                        },
+                    }
+                }
+                Ok(document)
+            },
+        }
+    }
+}
+
+
+// Applies JSON Merge Patch: objects are merged recursively, nulls remove members and anything
+// else replaces the target.
+fn merge(target: Value, patch: &Value) -> Value {
+    let fields = match patch {
+        Value::Object(fields) => fields,
+        _ => return patch.clone(),
+    };
+
+    let mut target = match target {
+        Value::Object(target) => target,
+        _ => serde_json::Map::new(),
+    };
+    for (key, value) in fields {
+        match value {
+            Value::Null => { target.remove(key); },
+            _ => {
+                let current = target.remove(key).unwrap_or(Value::Null);
+                target.insert(key.clone(), merge(current, value));
+            },
+        }
+    }
+    Value::Object(target)
+}
+
+
+/// Makes JSON Patch (as a list of operations) which turns `from` into `to`. Objects and arrays are
+/// compared member by member, arrays by index, so an item inserted at the front shows up as changes
+/// of all following items. Applying the patch to `from` always gives `to`.
+pub fn diff(from: &Value, to: &Value) -> Vec<Value> {
+    let mut operations = vec![];
+    diff_into(from, to, &mut vec![], &mut operations);
+    operations
+}
+
+
+fn diff_into(from: &Value, to: &Value, path: &mut Vec<String>, operations: &mut Vec<Value>) {
+    if from == to {
+        return;
+    }
+
+    match (from, to) {
+        (Value::Object(old), Value::Object(new)) => {
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                path.push(key.clone());
+                operations.push(json!({ "op": "remove", "path": format_pointer(path) }).0);
+                path.pop();
+            }
+            for (key, value) in new {
+                path.push(key.clone());
+                match old.get(key) {
+                    Some(previous) => diff_into(previous, value, path, operations),
+                    None => operations.push(json!({ "op": "add", "path": format_pointer(path), "value": value }).0),
+                }
+                path.pop();
+            }
+        },
+        (Value::Array(old), Value::Array(new)) => {
+            for (i, (previous, value)) in old.iter().zip(new).enumerate() {
+                path.push(i.to_string());
+                diff_into(previous, value, path, operations);
+                path.pop();
+            }
+            // Extra items are removed from the end, so indexes of the rest don't shift.
+            for i in (new.len()..old.len()).rev() {
+                path.push(i.to_string());
+                operations.push(json!({ "op": "remove", "path": format_pointer(path) }).0);
+                path.pop();
+            }
+            for (i, value) in new.iter().enumerate().skip(old.len()) {
+                path.push(i.to_string());
+                operations.push(json!({ "op": "add", "path": format_pointer(path), "value": value }).0);
+                path.pop();
+            }
+        },
+        _ => operations.push(json!({ "op": "replace", "path": format_pointer(path), "value": to }).0),
+    }
+}
+
+
+#[rocket::async_trait]
+impl<'r> FromData<'r> for Patch {
+    type Error = ();
+
+    async fn from_data(req: &'r Request<'_>, data: Data) -> Outcome<Self, ()> {
+        let merge = ContentType::new("application", "merge-patch+json");
+        let json = ContentType::new("application", "json-patch+json");
+
+        let is_merge = match req.content_type() {
+            Some(t) if *t == merge => true,
+            Some(t) if *t == json => false,
+            _ => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_patch_content_type",
+                    "message": "Patch must be sent with 'application/merge-patch+json' or 'application/json-patch+json' content type!",
+                }))));
+                return Outcome::Failure((Status::UnsupportedMediaType, ()));
+            }
+        };
+
+        let value = match read_json(req, data).await {
+            Ok(value) => value,
+            Err(()) => return Outcome::Failure((Status::BadRequest, ())),
+        };
+
+        match is_merge {
+            true => Outcome::Success(Patch::Merge(value)),
+            false => match Operation::parse_all(&value) {
+                Ok(operations) => Outcome::Success(Patch::Json(operations)),
+                Err(message) => {
+                    // Store error message.
+                    req.local_cache(|| ErrorMessage(Some(message)));
+                    // Forward to error catcher.
+                    Outcome::Failure((Status::BadRequest, ()))
+                }
+            },
+        }
+    }
+}
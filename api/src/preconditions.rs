@@ -0,0 +1,70 @@
+use rocket::request::{Outcome, Request, FromRequest};
+
+
+/// Returns entity tag of the entry version, as it's sent in "ETag" header (with quotes).
+pub fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+
+/// Condition of "If-Match" or "If-None-Match" header: either any version ("*") or one of the
+/// listed ones. Tags which weren't issued by us (e.g. malformed) never match.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Precondition {
+    Any,
+    Versions(Vec<u64>),
+}
+
+
+impl Precondition {
+    // Parses list of entity tags. Weak tags are skipped when comparison must be strong.
+    fn parse(raw: &str, weak: bool) -> Precondition {
+        let mut versions = vec![];
+        for tag in raw.split(',').map(|tag| tag.trim()) {
+            if tag == "*" {
+                return Precondition::Any;
+            }
+            let tag = match tag.strip_prefix("W/") {
+                Some(_) if !weak => continue,
+                Some(tag) => tag,
+                None => tag,
+            };
+            if let Some(version) = tag.strip_prefix('"').and_then(|t| t.strip_suffix('"')).and_then(|t| t.parse().ok()) {
+                versions.push(version);
+            }
+        }
+        Precondition::Versions(versions)
+    }
+
+    /// Returns true if current version of the entry (None if there is no entry) satisfies the condition.
+    pub fn matches(&self, version: Option<u64>) -> bool {
+        match (self, version) {
+            (_, None) => false,
+            (Precondition::Any, Some(_)) => true,
+            (Precondition::Versions(versions), Some(version)) => versions.contains(&version),
+        }
+    }
+}
+
+
+/// Value which allows to access conditional request headers (RFC 7232). "If-Match" is compared
+/// strongly and "If-None-Match" weakly, headers which weren't provided are None.
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    pub if_match:      Option<Precondition>,
+    pub if_none_match: Option<Precondition>,
+}
+
+
+// Allows a route to access conditional headers, if they were provided.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Preconditions {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        Outcome::Success(Preconditions {
+            if_match: req.headers().get_one("If-Match").map(|raw| Precondition::parse(raw, false)),
+            if_none_match: req.headers().get_one("If-None-Match").map(|raw| Precondition::parse(raw, true)),
+        })
+    }
+}
@@ -1,13 +1,128 @@
+use rocket::response::{self, Responder};
 use rocket_contrib::json::JsonValue;
+use rocket::Request;
 
 
 #[derive(Responder)]
 pub enum CustomResponder {
     #[response(status = 200, content_type = "json")]
     Ok(JsonValue),
+    #[response(status = 304)]
+    NotModified(()),
     #[response(status = 400, content_type = "json")]
     BadRequest(JsonValue),
+    #[response(status = 401, content_type = "json")]
+    Unauthorized(JsonValue),
+    #[response(status = 403, content_type = "json")]
+    Forbidden(JsonValue),
+    #[response(status = 404, content_type = "json")]
+    NotFound(JsonValue),
+    #[response(status = 409, content_type = "json")]
+    Conflict(JsonValue),
+    #[response(status = 410, content_type = "json")]
+    Gone(JsonValue),
+    #[response(status = 412, content_type = "json")]
+    PreconditionFailed(JsonValue),
+    #[response(status = 413, content_type = "json")]
+    PayloadTooLarge(JsonValue),
+    #[response(status = 415, content_type = "json")]
+    UnsupportedMediaType(JsonValue),
+    #[response(status = 422, content_type = "json")]
+    UnprocessableEntity(JsonValue),
     #[response(status = 500, content_type = "json")]
     UnknownError(JsonValue),
+    #[response(status = 503, content_type = "json")]
+    Unavailable(JsonValue),
 }
 
+
+
+/// Responder which adds RFC 8288 "Link" header to the wrapped response. Each link is a copy
+/// of the request URL with one url argument replaced, e.g. ("next", "page", "3") produces
+/// '</api/v1/entries?namespace=a&page=3>; rel="next"'.
+pub struct Linked {
+    pub inner: CustomResponder,
+    pub links: Vec<(&'static str, &'static str, String)>,
+}
+
+
+impl Linked {
+    fn link(req: &Request<'_>, rel: &str, arg: &str, value: &str) -> String {
+        let mut args = req.uri().query()
+            .map(|query| query.as_str().split('&')
+                .filter(|pair| !pair.is_empty() && pair.split('=').next() != Some(arg))
+                .map(|pair| pair.to_string())
+                .collect::<Vec<String>>())
+            .unwrap_or_default();
+        args.push(format!("{}={}", arg, value));
+
+        format!("<{}?{}>; rel=\"{}\"", req.uri().path().as_str(), args.join("&"), rel)
+    }
+}
+
+
+impl<'r> Responder<'r, 'static> for Linked {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let links = self.links.iter()
+            .map(|(rel, arg, value)| Linked::link(req, rel, arg, value))
+            .collect::<Vec<String>>();
+
+        let mut response = self.inner.respond_to(req)?;
+        if !links.is_empty() {
+            response.set_raw_header("Link", links.join(", "));
+        }
+        Ok(response)
+    }
+}
+
+
+/// Responder which adds "ETag" header to the wrapped response (if there is a tag), so clients
+/// can use it with "If-Match" and "If-None-Match" headers.
+pub struct Tagged {
+    pub inner: CustomResponder,
+    pub etag:  Option<String>,
+}
+
+
+impl From<CustomResponder> for Tagged {
+    fn from(inner: CustomResponder) -> Self {
+        Tagged { inner, etag: None }
+    }
+}
+
+
+impl<'r> Responder<'r, 'static> for Tagged {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.inner.respond_to(req)?;
+        if let Some(etag) = self.etag {
+            response.set_raw_header("ETag", etag);
+        }
+        Ok(response)
+    }
+}
+
+
+/// Responder which adds "Idempotency-Replayed: true" header to the wrapped response if it's a
+/// stored response of an earlier request with the same "Idempotency-Key".
+pub struct Idempotent {
+    pub inner:    CustomResponder,
+    pub replayed: bool,
+}
+
+
+impl From<CustomResponder> for Idempotent {
+    fn from(inner: CustomResponder) -> Self {
+        Idempotent { inner, replayed: false }
+    }
+}
+
+
+impl<'r> Responder<'r, 'static> for Idempotent {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.inner.respond_to(req)?;
+        if self.replayed {
+            response.set_raw_header("Idempotency-Replayed", "true");
+        }
+        Ok(response)
+    }
+}
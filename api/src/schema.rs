@@ -0,0 +1,310 @@
+use rocket_contrib::databases::postgres;
+use rocket_contrib::json::JsonValue;
+use serde::Serialize;
+use crate::errors::ApiError;
+use serde_json::Value;
+
+
+// Keywords which only describe the schema and don't affect validation.
+static ANNOTATIONS: &[&str] = &["$schema", "$id", "$comment", "title", "description", "default", "examples"];
+// Keywords which are supported by the validator.
+static KEYWORDS: &[&str] = &[
+    "type", "enum", "const",
+    "properties", "required", "additionalProperties",
+    "items", "minItems", "maxItems", "uniqueItems",
+    "minLength", "maxLength",
+    "minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum",
+    "allOf", "anyOf", "oneOf", "not",
+];
+static TYPES: &[&str] = &["null", "boolean", "object", "array", "number", "integer", "string"];
+
+
+/// Single failed check of a document: JSON pointer (RFC 6901) to the failing value and an
+/// explanation, e.g. {"pointer": "/metrics/latency_ms", "message": "Expected type 'number'!"}.
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub pointer: String,
+    pub message: String,
+}
+
+
+/// JSON Schema attached to a namespace. Only a subset of JSON Schema is supported (see
+/// `KEYWORDS` above): no references, formats or patterns. Schemas with unsupported keywords
+/// are rejected when attached, so documents are never validated by a partially understood schema.
+#[derive(Debug, Clone)]
+pub struct Schema(pub Value);
+
+
+// Escapes a key to be used as a segment of JSON pointer.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+
+fn type_matches(name: &str, value: &Value) -> bool {
+    match name {
+        "null"    => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object"  => value.is_object(),
+        "array"   => value.is_array(),
+        "number"  => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().map_or(false, |n| n.fract() == 0.0),
+        "string"  => value.is_string(),
+        _         => false,
+    }
+}
+
+
+impl Schema {
+    /// Checks that schema only uses supported keywords. On failure returns an error message
+    /// which is ready to be sent to the client.
+    pub fn parse(value: Value) -> Result<Schema, JsonValue> {
+        let mut errors = vec![];
+        Self::check(&value, String::new(), &mut errors);
+
+        match errors.is_empty() {
+            true => Ok(Schema(value)),
+            false => Err(json!({
+                "code":    "err_schema_invalid",
+                "message": format!(
+                    "Provided JSON Schema is invalid or uses unsupported keywords! Only a subset of JSON Schema \
+                    is supported, keywords: {} (and annotations: {}).", KEYWORDS.join(", "), ANNOTATIONS.join(", ")
+                ),
+                "errors":  errors,
+            })),
+        }
+    }
+
+    fn check(schema: &Value, pointer: String, errors: &mut Vec<Violation>) {
+        let fields = match schema {
+            Value::Bool(_) => return,
+            Value::Object(fields) => fields,
+            _ => return errors.push(Violation { pointer, message: "Schema must be an object or a boolean!".to_string() }),
+        };
+
+        for (keyword, value) in fields {
+            let at = format!("{}/{}", pointer, escape(keyword));
+            let mut fail = |message: &str| errors.push(Violation { pointer: at.clone(), message: message.to_string() });
+
+            if ANNOTATIONS.contains(&keyword.as_str()) {
+                continue;
+            }
+            if !KEYWORDS.contains(&keyword.as_str()) {
+                fail(&format!("Keyword '{}' is not supported!", keyword));
+                continue;
+            }
+
+            match keyword.as_str() {
+                "type" => {
+                    let valid = match value {
+                        Value::String(name) => TYPES.contains(&name.as_str()),
+                        Value::Array(names) => names.iter().all(|n| n.as_str().map_or(false, |n| TYPES.contains(&n))),
+                        _ => false,
+                    };
+                    if !valid {
+                        fail("Type must be one of (or a list of): null, boolean, object, array, number, integer, string!");
+                    }
+                },
+                "enum" => if !value.is_array() {
+                    fail("Enum must be an array!");
+                },
+                "required" => if !value.as_array().map_or(false, |keys| keys.iter().all(|k| k.is_string())) {
+                    fail("Required must be an array of strings!");
+                },
+                "minItems" | "maxItems" | "minLength" | "maxLength" => if !value.is_u64() {
+                    fail("Value must be a non-negative integer!");
+                },
+                "minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum" => if !value.is_number() {
+                    fail("Value must be a number!");
+                },
+                "uniqueItems" => if !value.is_boolean() {
+                    fail("Value must be a boolean!");
+                },
+                "properties" => match value.as_object() {
+                    Some(properties) => for (key, subschema) in properties {
+                        Self::check(subschema, format!("{}/{}", at, escape(key)), errors);
+                    },
+                    None => fail("Properties must be an object of schemas!"),
+                },
+                "allOf" | "anyOf" | "oneOf" => match value.as_array() {
+                    Some(subschemas) if !subschemas.is_empty() => for (i, subschema) in subschemas.iter().enumerate() {
+                        Self::check(subschema, format!("{}/{}", at, i), errors);
+                    },
+                    _ => fail("Value must be a non-empty array of schemas!"),
+                },
+                // Only subschemas are left: additionalProperties, items, not. Const may be anything.
+                "const" => (),
+                _ => Self::check(value, at, errors),
+            }
+        }
+    }
+
+    /// Validates document and returns all violations, which is empty if document is valid.
+    pub fn validate(&self, document: &Value) -> Vec<Violation> {
+        let mut violations = vec![];
+        Self::validate_at(&self.0, document, String::new(), &mut violations);
+        violations
+    }
+
+    fn validate_at(schema: &Value, value: &Value, pointer: String, violations: &mut Vec<Violation>) {
+        let fields = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return violations.push(Violation { pointer, message: "No value is allowed here!".to_string() }),
+            Value::Object(fields) => fields,
+            // Schema was checked when attached.
+            _ => return,
+        };
+        let mut fail = |message: String| violations.push(Violation { pointer: pointer.clone(), message });
+
+        if let Some(expected) = fields.get("type") {
+            let names = match expected {
+                Value::Array(names) => names.iter().filter_map(|n| n.as_str()).collect::<Vec<&str>>(),
+                _ => expected.as_str().into_iter().collect(),
+            };
+            if !names.iter().any(|name| type_matches(name, value)) {
+                fail(format!("Expected type '{}'!", names.join("' or '")));
+                // Other checks would only repeat the same problem.
+                return;
+            }
+        }
+
+        if let Some(options) = fields.get("enum").and_then(|e| e.as_array()) {
+            if !options.contains(value) {
+                fail(format!("Value must be one of: {}!", Value::Array(options.clone())));
+            }
+        }
+        if let Some(expected) = fields.get("const") {
+            if expected != value {
+                fail(format!("Value must be equal to {}!", expected));
+            }
+        }
+
+        match value {
+            Value::String(s) => {
+                let length = s.chars().count() as u64;
+                if let Some(min) = fields.get("minLength").and_then(|v| v.as_u64()) {
+                    if length < min { fail(format!("String must be at least {} characters long!", min)); }
+                }
+                if let Some(max) = fields.get("maxLength").and_then(|v| v.as_u64()) {
+                    if length > max { fail(format!("String must be at most {} characters long!", max)); }
+                }
+            },
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or(f64::NAN);
+                let bound = |keyword: &str| fields.get(keyword).and_then(|v| v.as_f64());
+                if let Some(min) = bound("minimum") {
+                    if n < min { fail(format!("Value must be greater than or equal to {}!", min)); }
+                }
+                if let Some(max) = bound("maximum") {
+                    if n > max { fail(format!("Value must be less than or equal to {}!", max)); }
+                }
+                if let Some(min) = bound("exclusiveMinimum") {
+                    if n <= min { fail(format!("Value must be greater than {}!", min)); }
+                }
+                if let Some(max) = bound("exclusiveMaximum") {
+                    if n >= max { fail(format!("Value must be less than {}!", max)); }
+                }
+            },
+            Value::Array(items) => {
+                if let Some(min) = fields.get("minItems").and_then(|v| v.as_u64()) {
+                    if (items.len() as u64) < min { fail(format!("Array must contain at least {} items!", min)); }
+                }
+                if let Some(max) = fields.get("maxItems").and_then(|v| v.as_u64()) {
+                    if (items.len() as u64) > max { fail(format!("Array must contain at most {} items!", max)); }
+                }
+                if fields.get("uniqueItems") == Some(&Value::Bool(true)) {
+                    if items.iter().enumerate().any(|(i, item)| items[..i].contains(item)) {
+                        fail("Array items must be unique!".to_string());
+                    }
+                }
+            },
+            _ => (),
+        }
+
+        for keyword in &["allOf", "anyOf", "oneOf"] {
+            let subschemas = match fields.get(*keyword).and_then(|v| v.as_array()) {
+                Some(subschemas) => subschemas,
+                None => continue,
+            };
+            let results = subschemas.iter().map(|subschema| {
+                let mut nested = vec![];
+                Self::validate_at(subschema, value, pointer.clone(), &mut nested);
+                nested
+            }).collect::<Vec<Vec<Violation>>>();
+            let matched = results.iter().filter(|nested| nested.is_empty()).count();
+
+            match *keyword {
+                "allOf" => results.into_iter().flatten().for_each(|v| violations.push(v)),
+                "anyOf" if matched == 0 => violations.push(Violation {
+                    pointer: pointer.clone(), message: "Value doesn't match any of the schemas in 'anyOf'!".to_string()
+                }),
+                "oneOf" if matched != 1 => violations.push(Violation {
+                    pointer: pointer.clone(), message: format!("Value must match exactly one schema in 'oneOf', but matches {}!", matched)
+                }),
+                _ => (),
+            }
+        }
+        if let Some(subschema) = fields.get("not") {
+            let mut nested = vec![];
+            Self::validate_at(subschema, value, pointer.clone(), &mut nested);
+            if nested.is_empty() {
+                violations.push(Violation { pointer: pointer.clone(), message: "Value must not match the schema in 'not'!".to_string() });
+            }
+        }
+
+        // Nested values are checked last, so violations of the value itself come first.
+        match value {
+            Value::Object(object) => {
+                if let Some(required) = fields.get("required").and_then(|v| v.as_array()) {
+                    for key in required.iter().filter_map(|k| k.as_str()) {
+                        if !object.contains_key(key) {
+                            violations.push(Violation {
+                                pointer: format!("{}/{}", pointer, escape(key)),
+                                message: "Required property is missing!".to_string(),
+                            });
+                        }
+                    }
+                }
+
+                let properties = fields.get("properties").and_then(|v| v.as_object());
+                for (key, nested) in object {
+                    let at = format!("{}/{}", pointer, escape(key));
+                    match properties.and_then(|p| p.get(key)) {
+                        Some(subschema) => Self::validate_at(subschema, nested, at, violations),
+                        None => if let Some(subschema) = fields.get("additionalProperties") {
+                            Self::validate_at(subschema, nested, at, violations);
+                        },
+                    }
+                }
+            },
+            Value::Array(items) => if let Some(subschema) = fields.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    Self::validate_at(subschema, item, format!("{}/{}", pointer, i), violations);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// Returns schema attached to the namespace, if there is one.
+    pub fn load(c: &mut impl postgres::GenericClient, namespace: &str) -> Result<Option<Schema>, ApiError> {
+        Ok(c.query_opt("SELECT schema FROM namespace_schemas WHERE namespace = $1", &[&namespace])?
+            .map(|row| Schema(row.get::<_, Value>("schema"))))
+    }
+
+    /// Attaches schema to the namespace, replacing the previous one. Entries which are already
+    /// stored are not validated.
+    pub fn save(&self, c: &mut postgres::Client, namespace: &str) -> Result<(), ApiError> {
+        c.execute(
+            "INSERT INTO namespace_schemas (namespace, schema) VALUES ($1, $2) ON CONFLICT (namespace) \
+            DO UPDATE SET schema = EXCLUDED.schema, updated_at = now()",
+            &[&namespace, &self.0]
+        )?;
+        Ok(())
+    }
+
+    /// Detaches schema from the namespace. Returns false if there was no schema.
+    pub fn delete(c: &mut postgres::Client, namespace: &str) -> Result<bool, ApiError> {
+        Ok(c.execute("DELETE FROM namespace_schemas WHERE namespace = $1", &[&namespace])? > 0)
+    }
+}
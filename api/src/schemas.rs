@@ -0,0 +1,118 @@
+use crate::auth::{Scoped, Read, Admin};
+use crate::responders::CustomResponder;
+use crate::model::{ApiDatabase, Entry};
+use crate::namespace::Namespace;
+use crate::schema::Schema;
+use serde_json::Value;
+
+
+// Response for namespaces without a schema.
+fn schema_not_found(namespace: &str) -> CustomResponder {
+    CustomResponder::NotFound(json!({
+        "code": "err_schema_not_found",
+        "message": format!("Namespace '{}' has no JSON Schema!", namespace),
+        "namespace": namespace,
+    }))
+}
+
+
+/// This endpoint is used to receive JSON Schema of the namespace. For this endpoint you must provide
+/// namespace (url argument <namespace> or header "X-Namespace", of type <String>).
+#[get("/")]
+pub async fn get_schema(namespace: Namespace, _key: Scoped<Read>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Schema::load(c, &namespace.0)).await {
+        Ok(Some(schema)) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "schema": schema.0,
+        })),
+        Ok(None) => schema_not_found(&namespace_copy),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to attach JSON Schema to the namespace (or replace existing one). Body of
+/// the request is the schema itself. After this, entries written to the namespace must conform to
+/// the schema, otherwise they are rejected with 422 'err_schema_violation' error, which lists JSON
+/// pointers of failing values. Entries which are already stored are not checked. Supported keywords
+/// are: type, enum, const, properties, required, additionalProperties, items, minItems, maxItems,
+/// uniqueItems, minLength, maxLength, minimum, maximum, exclusiveMinimum, exclusiveMaximum, allOf,
+/// anyOf, oneOf and not. For this endpoint you must provide namespace (url argument <namespace> or
+/// header "X-Namespace", of type <String>), and a key with admin scope for it.
+#[put("/", format = "application/json", data = "<schema>")]
+pub async fn put_schema(namespace: Namespace, _key: Scoped<Admin>, schema: Entry, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+    let schema = match Schema::parse(schema.0) {
+        Ok(schema) => schema,
+        Err(message) => return CustomResponder::BadRequest(message),
+    };
+
+    match conn.run(move |c| schema.save(c, &namespace.0)).await {
+        Ok(()) => CustomResponder::Ok(json!({
+            "code": "info_schema_saved",
+            "message": format!("Successfully attached JSON Schema to namespace '{}'!", &namespace_copy),
+            "namespace": &namespace_copy,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to detach JSON Schema from the namespace. For this endpoint you must provide
+/// namespace (url argument <namespace> or header "X-Namespace", of type <String>), and a key with
+/// admin scope for it.
+#[delete("/")]
+pub async fn delete_schema(namespace: Namespace, _key: Scoped<Admin>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Schema::delete(c, &namespace.0)).await {
+        Ok(true) => CustomResponder::Ok(json!({
+            "code": "info_schema_deleted",
+            "message": format!("Successfully detached JSON Schema from namespace '{}'!", &namespace_copy),
+            "namespace": &namespace_copy,
+        })),
+        Ok(false) => schema_not_found(&namespace_copy),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to check documents against JSON Schema of the namespace without storing
+/// them (dry run). Body of the request is a document, or an array of documents if url argument
+/// <many> is "true" (then pointers start with the index of the document, same as for bulk creation).
+/// Response contains 'valid' flag and the list of violations. For this endpoint you must provide
+/// namespace (url argument <namespace> or header "X-Namespace", of type <String>).
+#[post("/validate?<many>", format = "application/json", data = "<document>")]
+pub async fn validate_documents(namespace: Namespace, _key: Scoped<Read>, many: Option<bool>, document: Entry, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    let schema = match conn.run(move |c| Schema::load(c, &namespace.0)).await {
+        Ok(Some(schema)) => schema,
+        Ok(None) => return schema_not_found(&namespace_copy),
+        Err(e) => return e.in_namespace(&namespace_copy),
+    };
+
+    let errors = match (many.unwrap_or(false), &document.0) {
+        (true, Value::Array(documents)) => documents.iter().enumerate()
+            .flat_map(|(i, document)| schema.validate(document).into_iter().map(move |mut v| {
+                v.pointer = format!("/{}{}", i, v.pointer);
+                v
+            }))
+            .collect(),
+        (true, _) => return CustomResponder::BadRequest(json!({
+            "code": "err_request_body_parse",
+            "message": "Request body must be a JSON array when 'many' is set!",
+        })),
+        (false, document) => schema.validate(document),
+    };
+
+    CustomResponder::Ok(json!({
+        "code": "no_message",
+        "namespace": &namespace_copy,
+        "valid": errors.is_empty(),
+        "errors": errors,
+    }))
+}
@@ -0,0 +1,243 @@
+use rocket::http::{ContentType, Status, Header};
+use rocket::local::asynchronous::Client;
+use serde_json::{from_str, Value};
+use super::rocket_custom;
+
+
+// Bootstrap key of the test configuration.
+const ADMIN_KEY: &str = "test_admin_key";
+
+
+async fn client() -> Client {
+    let figment = rocket::Config::figment()
+        .merge(("require_api_keys", true))
+        .merge(("admin_key", ADMIN_KEY));
+    Client::tracked(rocket_custom(figment)).await.unwrap()
+}
+
+
+fn bearer(key: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", key))
+}
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = client().await;
+
+    {
+        // Test missing and invalid keys.
+        let (r1, r2) = rocket::tokio::join!(
+            client.get("/api/v1/entries?namespace=test_name_alpha&page=0").dispatch(),
+            client.get("/api/v1/entries?namespace=test_name_alpha&page=0")
+                .header(Header::new("X-Api-Key", "vy_invalid")).dispatch()
+        );
+
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+
+        assert_eq!(r1.status(), Status::Unauthorized);
+        assert_eq!(r2.status(), Status::Unauthorized);
+
+        let (s1, s2) = rocket::tokio::join!(r1.into_string(), r2.into_string());
+
+        assert_eq!(s1, Some(json!({
+            "code": "err_api_key_missing",
+            "message": "You must provide API key with 'Authorization: Bearer <key>' or 'X-Api-Key' header!"
+        }).to_string()));
+
+        assert_eq!(s2, Some(json!({
+            "code": "err_api_key_invalid",
+            "message": "Provided API key is invalid or was revoked!"
+        }).to_string()));
+    }
+
+    {
+        // Test bad grants.
+        let (r1, r2, r3) = rocket::tokio::join!(
+            client.post("/api/v1/keys").header(bearer(ADMIN_KEY)).header(ContentType::JSON)
+                .body("{\"grants\": []}").dispatch(),
+            client.post("/api/v1/keys").header(bearer(ADMIN_KEY)).header(ContentType::JSON)
+                .body("{\"name\": \"a\", \"grants\": [{\"namespace\": \"a*b\", \"scopes\": []}]}").dispatch(),
+            client.post("/api/v1/keys").header(bearer(ADMIN_KEY)).header(ContentType::JSON)
+                .body("{\"name\": \"a\", \"grants\": [{\"namespace\": \"a\", \"scopes\": [\"all\"]}]}").dispatch()
+        );
+
+        assert_eq!(r1.status(), Status::BadRequest);
+        assert_eq!(r2.status(), Status::BadRequest);
+        assert_eq!(r3.status(), Status::BadRequest);
+
+        let (s1, s2, s3) = rocket::tokio::join!(r1.into_string(), r2.into_string(), r3.into_string());
+
+        assert_eq!(s1, Some(json!({
+            "code": "err_api_key_name",
+            "message": "API key must have a 'name' of 1 to 64 characters!"
+        }).to_string()));
+
+        assert_eq!(s2, Some(json!({
+            "code": "err_api_key_grants",
+            "message": "Grant namespace 'a*b' is malformed, it must be a namespace, a prefix ending with '*' or '*'!",
+            "namespace": "a*b"
+        }).to_string()));
+
+        assert_eq!(s3, Some(json!({
+            "code": "err_api_key_grants",
+            "message": "Unknown scope 'all', expected one of: read, write, delete, admin!",
+            "scope": "all"
+        }).to_string()));
+    }
+}
+
+
+/// Following test verifies the story below:
+///     - Create key which can read namespaces with a prefix
+///     - Read and try to write with it
+///     - Try to manage keys with it
+///     - Revoke the key and try to use it again
+#[rocket::async_test]
+async fn test_scopes() {
+    let client = client().await;
+
+    let r = client.post("/api/v1/keys").header(bearer(ADMIN_KEY)).header(ContentType::JSON)
+        .body("{\"name\": \"readers\", \"grants\": [{\"namespace\": \"test_name_*\", \"scopes\": [\"read\"]}]}")
+        .dispatch().await;
+
+    // We expect 200 JSON response.
+    assert_eq!(r.status(), Status::Ok);
+    assert_eq!(r.content_type(), Some(ContentType::JSON));
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "info_api_key_created");
+    let key = body["key"].as_str().unwrap().to_string();
+    let id = body["data"]["id"].as_u64().unwrap();
+    assert!(key.starts_with(body["data"]["prefix"].as_str().unwrap()));
+
+    // Reading any namespace with the prefix is allowed.
+    let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0").header(bearer(&key)).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let r = client.get("/api/v1/entries?namespace=test_name_beta&page=0")
+        .header(Header::new("X-Api-Key", key.clone())).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+
+    // Only readable namespaces are listed.
+    let r = client.get("/api/v1/namespaces").header(bearer(&key)).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert!(body["data"].as_array().unwrap().iter().all(|n| n["name"].as_str().unwrap().starts_with("test_name_")));
+
+    // Reading other namespaces and writing is not.
+    let r = client.get("/api/v1/entries?namespace=other&page=0").header(bearer(&key)).dispatch().await;
+    assert_eq!(r.status(), Status::Forbidden);
+    assert_eq!(r.into_string().await, Some(json!({
+        "code": "err_api_key_scope",
+        "message": "API key doesn't grant 'read' scope for namespace 'other'!"
+    }).to_string()));
+
+    let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(bearer(&key))
+        .header(ContentType::JSON).body("{}").dispatch().await;
+    assert_eq!(r.status(), Status::Forbidden);
+
+    let r = client.get("/api/v1/keys").header(bearer(&key)).dispatch().await;
+    assert_eq!(r.status(), Status::Forbidden);
+    assert_eq!(r.into_string().await, Some(json!({
+        "code": "err_api_key_scope",
+        "message": "API key doesn't grant 'admin' scope for all namespaces!"
+    }).to_string()));
+
+    // Key is listed, but hash and key itself are not.
+    let r = client.get("/api/v1/keys").header(bearer(ADMIN_KEY)).dispatch().await;
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    let listed = body["data"].as_array().unwrap().iter().find(|k| k["id"] == id).unwrap().clone();
+    assert_eq!(listed["name"], "readers");
+    assert!(listed["revoked_at"].is_null());
+    assert!(!listed.to_string().contains(&key));
+
+    let r = client.delete(format!("/api/v1/keys/{}", id)).header(bearer(ADMIN_KEY)).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "info_api_key_revoked");
+    assert!(body["data"]["revoked_at"].is_string());
+
+    let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0").header(bearer(&key)).dispatch().await;
+    assert_eq!(r.status(), Status::Unauthorized);
+}
+
+
+/// Following test verifies the story below:
+///     - Create entry in a namespace which the key can't read
+///     - Get, patch, delete and read revisions of it through a namespace which the key can use
+///     - Verify that responses don't tell the entry exists
+#[rocket::async_test]
+async fn test_hidden_entries() {
+    let client = client().await;
+
+    let r = client.post("/api/v1/entries?namespace=test_keys_hidden").header(bearer(ADMIN_KEY))
+        .header(ContentType::JSON).body("{\"secret\": true}").dispatch().await;
+    let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+
+    let r = client.post("/api/v1/keys").header(bearer(ADMIN_KEY)).header(ContentType::JSON)
+        .body("{\"name\": \"writers\", \"grants\": [{\"namespace\": \"test_keys_own\", \"scopes\": [\"read\", \"write\", \"delete\"]}]}")
+        .dispatch().await;
+    let key = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["key"].as_str().unwrap().to_string();
+
+    let not_found = json!({
+        "code": "err_entry_not_found",
+        "message": format!("Entry with ID '{}' does not exist!", id),
+        "namespace": "test_keys_own",
+        "id": id
+    }).to_string();
+
+    let r = client.get(format!("/api/v1/entries/{}?namespace=test_keys_own", id)).header(bearer(&key)).dispatch().await;
+    assert_eq!(r.status(), Status::NotFound);
+    assert_eq!(r.into_string().await, Some(not_found.clone()));
+
+    let r = client.get(format!("/api/v1/entries/{}/revisions/1?namespace=test_keys_own", id)).header(bearer(&key)).dispatch().await;
+    assert_eq!(r.status(), Status::NotFound);
+    assert_eq!(r.into_string().await, Some(not_found.clone()));
+
+    let r = client.patch(format!("/api/v1/entries/{}?namespace=test_keys_own", id)).header(bearer(&key))
+        .header(ContentType::new("application", "merge-patch+json")).body("{}").dispatch().await;
+    assert_eq!(r.status(), Status::NotFound);
+    assert_eq!(r.into_string().await, Some(not_found.clone()));
+
+    let r = client.delete(format!("/api/v1/entries/{}?namespace=test_keys_own", id)).header(bearer(&key)).dispatch().await;
+    assert_eq!(r.status(), Status::NotFound);
+    assert_eq!(r.into_string().await, Some(not_found));
+
+    // Put creates missing entries, so it answers the same conflict for every key (and never
+    // replaces the hidden entry).
+    let r = client.put(format!("/api/v1/entries/{}?namespace=test_keys_own", id)).header(bearer(&key))
+        .header(ContentType::JSON).body("{}").dispatch().await;
+    assert_eq!(r.status(), Status::Conflict);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "err_entry_namespace_conflict");
+    let r = client.get(format!("/api/v1/entries/{}?namespace=test_keys_hidden", id)).header(bearer(ADMIN_KEY)).dispatch().await;
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["data"]["content"], json!({ "secret": true }).0);
+
+    client.delete("/api/v1/entries?namespace=test_keys_hidden").header(bearer(ADMIN_KEY)).dispatch().await;
+}
+
+
+/// Following test verifies the story below:
+///     - Start without a bootstrap key (empty variable)
+///     - Try to manage keys with an empty key
+#[rocket::async_test]
+async fn test_empty_admin_key() {
+    let figment = rocket::Config::figment()
+        .merge(("require_api_keys", true))
+        .merge(("admin_key", ""));
+    let client = Client::tracked(rocket_custom(figment)).await.unwrap();
+
+    let (r1, r2) = rocket::tokio::join!(
+        client.get("/api/v1/keys").header(Header::new("X-Api-Key", "")).dispatch(),
+        client.get("/api/v1/keys").header(Header::new("X-Api-Key", " ")).dispatch()
+    );
+
+    for r in vec![r1, r2] {
+        assert_eq!(r.status(), Status::Unauthorized);
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "err_api_key_invalid",
+            "message": "Provided API key is invalid or was revoked!"
+        }).to_string()));
+    }
+}
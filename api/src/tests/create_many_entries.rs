@@ -1,6 +1,7 @@
 use rocket::local::asynchronous::Client;
 use rocket::http::{ContentType, Status};
 use rocket::tokio;
+use serde_json::{from_str, Value};
 use super::rocket;
 
 
@@ -28,9 +29,9 @@ async fn test_bad() {
                 .header(ContentType::JSON).body(format!("[{}]", "1,".repeat(1024 * 1024))).dispatch(),
         );
 
-        assert_eq!(r1.content_type(), Some(ContentType::HTML));
-        assert_eq!(r2.content_type(), Some(ContentType::HTML));
-        assert_eq!(r3.content_type(), Some(ContentType::HTML));
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
         // Parsing errors handled by us.
         assert_eq!(r4.content_type(), Some(ContentType::JSON));
         assert_eq!(r5.content_type(), Some(ContentType::JSON));
@@ -74,3 +75,19 @@ async fn test_bad() {
     }
 }
 
+
+
+#[rocket::async_test]
+async fn test_leading_whitespace() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    // Array preceded by whitespace is still a list of entries, not a single entry.
+    let r = client.post("/api/v1/entries?namespace=test_many_whitespace")
+        .header(ContentType::JSON).body(" \n\t[{\"a\": 1}, {\"a\": 2}]").dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "info_many_items_ok");
+    assert_eq!(body["item_ids"].as_array().unwrap().len(), 2);
+
+    client.delete("/api/v1/entries?namespace=test_many_whitespace").dispatch().await;
+}
@@ -28,9 +28,9 @@ async fn test_bad() {
                 .header(ContentType::JSON).body(format!("\"{}\"", "aa".repeat(1024 * 1024))).dispatch(),
         );
 
-        assert_eq!(r1.content_type(), Some(ContentType::HTML));
-        assert_eq!(r2.content_type(), Some(ContentType::HTML));
-        assert_eq!(r3.content_type(), Some(ContentType::HTML));
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
         // Parsing errors handled by us.
         assert_eq!(r4.content_type(), Some(ContentType::JSON));
         assert_eq!(r5.content_type(), Some(ContentType::JSON));
@@ -145,3 +145,30 @@ async fn test_bad() {
     }
 }
 
+
+
+#[rocket::async_test]
+async fn test_bad_idempotency_key() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    let (r1, r2, r3) = tokio::join!(
+        client.post("/api/v1/entries?namespace=a").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "")).body("{}").dispatch(),
+        client.post("/api/v1/entries?namespace=a").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "a".repeat(256))).body("{}").dispatch(),
+        client.post("/api/v1/entries?namespace=a").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "job 1")).body("[{}]").dispatch(),
+    );
+
+    assert_eq!(r1.status(), Status::BadRequest);
+    assert_eq!(r2.status(), Status::BadRequest);
+    assert_eq!(r3.status(), Status::BadRequest);
+
+    let message = json!({
+        "code": "err_idempotency_key",
+        "message": "Idempotency-Key must be a string of 1 to 255 printable ASCII characters!"
+    }).to_string();
+    assert_eq!(r1.into_string().await, Some(message.clone()));
+    assert_eq!(r2.into_string().await, Some(message.clone()));
+    assert_eq!(r3.into_string().await, Some(message));
+}
@@ -29,11 +29,11 @@ async fn test_bad() {
                 .header(ContentType::JSON).dispatch()
         );
 
-        assert_eq!(r1.content_type(), Some(ContentType::HTML));
-        assert_eq!(r2.content_type(), Some(ContentType::HTML));
-        assert_eq!(r3.content_type(), Some(ContentType::HTML));
-        assert_eq!(r4.content_type(), Some(ContentType::HTML));
-        assert_eq!(r5.content_type(), Some(ContentType::HTML));
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
+        assert_eq!(r4.content_type(), Some(ContentType::JSON));
+        assert_eq!(r5.content_type(), Some(ContentType::JSON));
 
         assert_eq!(r1.status(), Status::NotFound);
         assert_eq!(r2.status(), Status::NotFound);
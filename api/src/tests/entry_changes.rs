@@ -0,0 +1,95 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use crate::model::ApiDatabase;
+use serde_json::{from_str, Value};
+use super::rocket;
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    // Changes which were removed by retention can't be replayed.
+    let conn = ApiDatabase::get_one(client.rocket()).await.unwrap();
+    conn.run(|c| c.execute(
+        "INSERT INTO entry_change_log_pruned (namespace, seq) VALUES ('test_changes_beta', 100) \
+        ON CONFLICT (namespace) DO UPDATE SET seq = 100", &[]
+    )).await.unwrap();
+
+    let r = client.get("/api/v1/entries/changes?namespace=test_changes_beta&since=99").dispatch().await;
+    assert_eq!(r.status(), Status::Gone);
+    assert_eq!(r.content_type(), Some(ContentType::JSON));
+    assert_eq!(r.into_string().await, Some(json!({
+        "code": "err_changes_expired",
+        "message": "Changes up to '100' were removed from the change log, data must be reloaded!",
+        "namespace": "test_changes_beta",
+        "pruned_seq": 100
+    }).to_string()));
+
+    let r = client.get("/api/v1/entries/changes?namespace=test_changes_beta&since=100").dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+
+    // Head is never behind the removed changes.
+    let r = client.get("/api/v1/entries/changes/head?namespace=test_changes_beta").dispatch().await;
+    assert_eq!(from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["head"], 100);
+
+    conn.run(|c| c.execute("DELETE FROM entry_change_log_pruned WHERE namespace = 'test_changes_beta'", &[])).await.unwrap();
+}
+
+
+#[rocket::async_test]
+async fn test_changes() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    let conn = ApiDatabase::get_one(client.rocket()).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_changes_alpha").dispatch().await;
+    conn.run(|c| c.execute("DELETE FROM entry_change_log WHERE namespace = 'test_changes_alpha'", &[])).await.unwrap();
+
+    let r = client.post("/api/v1/entries?namespace=test_changes_alpha").header(ContentType::JSON)
+        .body("[{\"n\": 1}, {\"n\": 2}]").dispatch().await;
+    let ids = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_ids"].clone();
+    let (id1, id2) = (ids[0].as_u64().unwrap(), ids[1].as_u64().unwrap());
+    client.put(format!("/api/v1/entries/{}?namespace=test_changes_alpha", id1)).header(ContentType::JSON)
+        .body("{\"n\": 10}").dispatch().await;
+    client.delete(format!("/api/v1/entries/{}?namespace=test_changes_alpha", id2)).dispatch().await;
+    client.delete("/api/v1/entries?namespace=test_changes_alpha").dispatch().await;
+
+    let r = client.get("/api/v1/entries/changes?namespace=test_changes_alpha").dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["has_more"], false);
+    let data = body["data"].as_array().unwrap();
+    let summary = data.iter().map(|c| (c["kind"].as_str().unwrap(), c["id"].as_u64().unwrap())).collect::<Vec<_>>();
+    assert_eq!(summary, vec![
+        ("created", id1), ("created", id2), ("updated", id1), ("deleted", id2), ("deleted", id1),
+    ]);
+    assert_eq!(data[2]["content"], json!({ "n": 10 }));
+    assert_eq!(data[3].get("content"), None);
+    assert!(data.windows(2).all(|pair| pair[0]["seq"].as_u64() < pair[1]["seq"].as_u64()));
+    assert_eq!(body["next_since"], data[4]["seq"]);
+
+    // Pages continue from the last change.
+    let r = client.get("/api/v1/entries/changes?namespace=test_changes_alpha&page_size=2").dispatch().await;
+    let link = r.headers().get_one("Link").unwrap().to_string();
+    let page = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(page["has_more"], true);
+    assert_eq!(page["data"].as_array().unwrap().len(), 2);
+    assert_eq!(page["next_since"], data[1]["seq"]);
+    assert!(link.contains(&format!("since={}", data[1]["seq"])));
+
+    let r = client.get(format!("/api/v1/entries/changes?namespace=test_changes_alpha&since={}", page["next_since"]))
+        .dispatch().await;
+    let rest = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(rest["data"].as_array().unwrap()[..], data[2..]);
+
+    // Nothing new after the last change.
+    let r = client.get(format!("/api/v1/entries/changes?namespace=test_changes_alpha&since={}", body["next_since"]))
+        .dispatch().await;
+    let empty = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(empty["data"], json!([]));
+    assert_eq!(empty["next_since"], body["next_since"]);
+
+    // Head is the last change, so replaying after it gives nothing.
+    let r = client.get("/api/v1/entries/changes/head?namespace=test_changes_alpha").dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    assert_eq!(from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["head"], body["next_since"]);
+}
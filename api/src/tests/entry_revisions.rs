@@ -0,0 +1,139 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{from_str, Value};
+use super::rocket;
+
+
+async fn get_json(client: &Client, uri: String) -> (Status, Value) {
+    let r = client.get(uri).dispatch().await;
+    (r.status(), from_str::<Value>(&r.into_string().await.unwrap()).unwrap())
+}
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_revisions_beta").dispatch().await;
+
+    let r = client.post("/api/v1/entries?namespace=test_revisions_beta").header(ContentType::JSON)
+        .body("{\"n\": 1}").dispatch().await;
+    let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+
+    let (status, body) = get_json(&client, format!("/api/v1/entries/{}/revisions/2?namespace=test_revisions_beta", id)).await;
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(body, json!({
+        "code": "err_revision_not_found",
+        "message": format!("Entry with ID '{}' has no revision '2'!", id),
+        "namespace": "test_revisions_beta",
+        "id": id,
+        "revision": 2
+    }).0);
+
+    // Revisions of entries in other namespaces can't be seen or restored.
+    let (status, body) = get_json(&client, format!("/api/v1/entries/{}/revisions?namespace=test_revisions_gamma", id)).await;
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(body["code"], "err_entry_namespace_mismatch");
+    let r = client.post(format!("/api/v1/entries/{}/revisions/1/restore?namespace=test_revisions_gamma", id)).dispatch().await;
+    assert_eq!(r.status(), Status::NotFound);
+
+    let r = client.get("/api/v1/entries?namespace=test_revisions_beta&page=0&as_of=yesterday").dispatch().await;
+    assert_eq!(r.status(), Status::BadRequest);
+    assert_eq!(from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["code"], "err_timestamp_parse");
+
+    client.delete("/api/v1/entries?namespace=test_revisions_beta").dispatch().await;
+}
+
+
+#[rocket::async_test]
+async fn test_revisions() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_revisions_alpha").dispatch().await;
+
+    let r = client.post("/api/v1/entries?namespace=test_revisions_alpha").header(ContentType::JSON)
+        .body("{\"n\": 1, \"tags\": [\"a\"], \"old\": true}").dispatch().await;
+    let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+    client.put(format!("/api/v1/entries/{}?namespace=test_revisions_alpha", id)).header(ContentType::JSON)
+        .body("{\"n\": 2, \"tags\": [\"a\", \"b\"]}").dispatch().await;
+    client.patch(format!("/api/v1/entries/{}?namespace=test_revisions_alpha", id))
+        .header(ContentType::new("application", "merge-patch+json")).body("{\"n\": 3}").dispatch().await;
+
+    let (status, body) = get_json(&client, format!("/api/v1/entries/{}/revisions?namespace=test_revisions_alpha", id)).await;
+    assert_eq!(status, Status::Ok);
+    let revisions = body["data"].as_array().unwrap().clone();
+    assert_eq!(revisions.iter().map(|r| r["revision"].as_u64().unwrap()).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(revisions[0].get("content"), None);
+    assert_eq!(revisions[0]["valid_to"], revisions[1]["valid_from"]);
+    assert_eq!(revisions[2]["valid_to"], Value::Null);
+
+    let (_, body) = get_json(&client, format!("/api/v1/entries/{}/revisions/1?namespace=test_revisions_alpha", id)).await;
+    assert_eq!(body["data"]["content"], json!({ "n": 1, "tags": ["a"], "old": true }).0);
+
+    let (_, body) = get_json(&client, format!("/api/v1/entries/{}/revisions/diff?namespace=test_revisions_alpha&from=1&to=3", id)).await;
+    assert_eq!(body["patch"], json!([
+        { "op": "remove", "path": "/old" },
+        { "op": "replace", "path": "/n", "value": 3 },
+        { "op": "add", "path": "/tags/1", "value": "b" },
+    ]).0);
+
+    // Namespace as it was before the last update.
+    let (_, body) = get_json(&client, format!(
+        "/api/v1/entries?namespace=test_revisions_alpha&page=0&as_of={}", revisions[1]["valid_from"].as_str().unwrap()
+    )).await;
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    assert_eq!(body["data"][0]["content"], json!({ "n": 2, "tags": ["a", "b"] }).0);
+
+    // Restored content becomes the newest revision.
+    let r = client.post(format!("/api/v1/entries/{}/revisions/1/restore?namespace=test_revisions_alpha", id)).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    assert!(r.headers().get_one("ETag").is_some());
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "info_restore_item_ok");
+    assert_eq!(body["data"]["content"], json!({ "n": 1, "tags": ["a"], "old": true }).0);
+
+    let (_, body) = get_json(&client, format!("/api/v1/entries/{}/revisions?namespace=test_revisions_alpha", id)).await;
+    assert_eq!(body["data"].as_array().unwrap().len(), 4);
+
+    // Deleted entries are still there in the past.
+    client.delete(format!("/api/v1/entries/{}?namespace=test_revisions_alpha", id)).dispatch().await;
+    let (_, current) = get_json(&client, "/api/v1/entries?namespace=test_revisions_alpha&page=0".to_string()).await;
+    assert_eq!(current["data"], json!([]).0);
+    let (_, past) = get_json(&client, format!(
+        "/api/v1/entries?namespace=test_revisions_alpha&page=0&as_of={}", revisions[2]["valid_from"].as_str().unwrap()
+    )).await;
+    assert_eq!(past["data"][0]["id"], id);
+    assert_eq!(past["data"][0]["content"]["n"], 3);
+}
+
+
+#[rocket::async_test]
+async fn test_recreated() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_revisions_delta").dispatch().await;
+    client.delete("/api/v1/entries?namespace=test_revisions_epsilon").dispatch().await;
+
+    let r = client.post("/api/v1/entries?namespace=test_revisions_delta").header(ContentType::JSON)
+        .body("{\"secret\": 1}").dispatch().await;
+    let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+    client.put(format!("/api/v1/entries/{}?namespace=test_revisions_delta", id)).header(ContentType::JSON)
+        .body("{\"secret\": 2}").dispatch().await;
+    client.delete(format!("/api/v1/entries/{}?namespace=test_revisions_delta", id)).dispatch().await;
+
+    // Entry which is put again with the same ID, in another namespace, doesn't get the old history.
+    let r = client.put(format!("/api/v1/entries/{}?namespace=test_revisions_epsilon", id)).header(ContentType::JSON)
+        .body("{\"public\": true}").dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+
+    let (_, body) = get_json(&client, format!("/api/v1/entries/{}/revisions?namespace=test_revisions_epsilon", id)).await;
+    let revisions = body["data"].as_array().unwrap();
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(revisions[0]["revision"], 1);
+    assert_eq!(revisions[0]["namespace"], "test_revisions_epsilon");
+
+    let (status, body) = get_json(&client, format!("/api/v1/entries/{}/revisions/2?namespace=test_revisions_epsilon", id)).await;
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(body["code"], "err_revision_not_found");
+    let r = client.post(format!("/api/v1/entries/{}/revisions/2/restore?namespace=test_revisions_epsilon", id)).dispatch().await;
+    assert_eq!(r.status(), Status::NotFound);
+
+    client.delete("/api/v1/entries?namespace=test_revisions_epsilon").dispatch().await;
+}
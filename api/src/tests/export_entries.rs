@@ -0,0 +1,52 @@
+use rocket::http::{ContentType, Status, Header};
+use rocket::local::asynchronous::Client;
+use rocket::tokio;
+use super::rocket;
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    {
+        // Test unknown export format and malformed columns.
+        let (r1, r2, r3) = tokio::join!(
+            client.get("/api/v1/entries/export?namespace=test_name_alpha&format=xml").dispatch(),
+            client.get("/api/v1/entries/export?namespace=test_name_alpha&format=csv&columns=a,,b").dispatch(),
+            client.get("/api/v1/entries/export?namespace=test_name_alpha&format=csv")
+                .header(Header::new("X-Columns", "a..b")).dispatch()
+        );
+
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
+
+        assert_eq!(r1.status(), Status::BadRequest);
+        assert_eq!(r2.status(), Status::BadRequest);
+        assert_eq!(r3.status(), Status::BadRequest);
+
+        let (s1, s2, s3) = rocket::tokio::join!(
+            r1.into_string(),
+            r2.into_string(),
+            r3.into_string()
+        );
+
+        assert_eq!(s1, Some(json!({
+            "code": "err_export_format",
+            "message": "Unknown export format 'xml', expected one of: ndjson, json, csv!",
+            "format": "xml"
+        }).to_string()));
+
+        assert_eq!(s2, Some(json!({
+            "code": "err_export_columns",
+            "message": "Column '' is malformed, it must be a non-empty list of keys separated by dots!",
+            "column": ""
+        }).to_string()));
+
+        assert_eq!(s3, Some(json!({
+            "code": "err_export_columns",
+            "message": "Column 'a..b' is malformed, it must be a non-empty list of keys separated by dots!",
+            "column": "a..b"
+        }).to_string()));
+    }
+}
@@ -81,12 +81,12 @@ async fn test_bad() {
             client.get("/api/v1/entries/0.12345").header(Header::new("X-Namespace", "a")).dispatch(),
         );
 
-        assert_eq!(r1.content_type(), Some(ContentType::HTML));
-        //assert_eq!(r2.content_type(), Some(ContentType::HTML));
-        assert_eq!(r3.content_type(), Some(ContentType::HTML));
-        assert_eq!(r4.content_type(), Some(ContentType::HTML));
-        assert_eq!(r5.content_type(), Some(ContentType::HTML));
-        assert_eq!(r6.content_type(), Some(ContentType::HTML));
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        //assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
+        assert_eq!(r4.content_type(), Some(ContentType::JSON));
+        assert_eq!(r5.content_type(), Some(ContentType::JSON));
+        assert_eq!(r6.content_type(), Some(ContentType::JSON));
 
         assert_eq!(r1.status(), Status::NotFound);
         //assert_eq!(r2.status(), Status::NotFound);
@@ -1,5 +1,6 @@
 use rocket::http::{ContentType, Status, Header};
 use rocket::local::asynchronous::Client;
+use serde_json::{from_str, Value};
 use rocket::tokio;
 use super::rocket;
 
@@ -21,17 +22,24 @@ async fn test_bad() {
                 .header(Header::new("X-Namespace", "a")).dispatch()
         );
 
-        assert_eq!(r1.content_type(), Some(ContentType::HTML));
-        assert_eq!(r2.content_type(), Some(ContentType::HTML));
-        assert_eq!(r3.content_type(), Some(ContentType::HTML));
-        assert_eq!(r4.content_type(), Some(ContentType::HTML));
-        assert_eq!(r5.content_type(), Some(ContentType::HTML));
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
+        assert_eq!(r4.content_type(), Some(ContentType::JSON));
+        assert_eq!(r5.content_type(), Some(ContentType::JSON));
 
         assert_eq!(r1.status(), Status::NotFound);
         assert_eq!(r2.status(), Status::NotFound);
         assert_eq!(r3.status(), Status::NotFound);
         assert_eq!(r4.status(), Status::NotFound);
         assert_eq!(r5.status(), Status::NotFound);
+
+        // Unmatched requests get the same JSON error format.
+        let s1 = r1.into_string().await;
+        assert_eq!(s1, Some(json!({
+            "code": "err_not_found",
+            "message": "Nothing matches 'GET /api/v1/entries?namespace=a&page='! Make sure that url and its arguments are correct."
+        }).to_string()));
     }
 
     {
@@ -109,5 +117,133 @@ async fn test_bad() {
             "message": "Couldn't parse page size from header with error: 'invalid digit found in string'!"
         }).to_string()));
     }
-}
 
+    {
+        // Test bad filter value.
+        let (r1, r2, r3, r4, r5) = tokio::join!(
+            client.get("/api/v1/entries?namespace=a&page=0&filter=a").dispatch(),
+            client.get("/api/v1/entries?page=0")
+                .header(Header::new("X-Filter", "[1, 2]"))
+                .header(Header::new("X-Namespace", "a")).dispatch(),
+            client.get("/api/v1/entries?page=0")
+                .header(Header::new("X-Filter", "{\"a..b\": {\"eq\": 1}}"))
+                .header(Header::new("X-Namespace", "a")).dispatch(),
+            client.get("/api/v1/entries?page=0")
+                .header(Header::new("X-Filter", "{\"a\": {\"like\": 1}}"))
+                .header(Header::new("X-Namespace", "a")).dispatch(),
+            client.get("/api/v1/entries?page=0")
+                .header(Header::new("X-Filter", "{\"a\": {\"gt\": true}}"))
+                .header(Header::new("X-Namespace", "a")).dispatch()
+        );
+
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
+        assert_eq!(r4.content_type(), Some(ContentType::JSON));
+        assert_eq!(r5.content_type(), Some(ContentType::JSON));
+
+        assert_eq!(r1.status(), Status::BadRequest);
+        assert_eq!(r2.status(), Status::BadRequest);
+        assert_eq!(r3.status(), Status::BadRequest);
+        assert_eq!(r4.status(), Status::BadRequest);
+        assert_eq!(r5.status(), Status::BadRequest);
+
+        let (s1, s2, s3, s4, s5) = rocket::tokio::join!(
+            r1.into_string(), r2.into_string(), r3.into_string(),
+            r4.into_string(), r5.into_string()
+        );
+
+        assert_eq!(s1, Some(json!({
+            "code": "err_filter_parse",
+            "message": "Couldn't parse filter as JSON with error: 'expected value at line 1 column 1'!"
+        }).to_string()));
+
+        assert_eq!(s2, Some(json!({
+            "code": "err_filter_parse",
+            "message": "Filter must be a JSON object of paths mapped to operators, e.g. {\"status\": {\"eq\": \"failed\"}}!"
+        }).to_string()));
+
+        assert_eq!(s3, Some(json!({
+            "code": "err_filter_path",
+            "message": "Filter path 'a..b' is malformed, it must be a non-empty list of keys separated by dots!",
+            "path": "a..b"
+        }).to_string()));
+
+        assert_eq!(s4, Some(json!({
+            "code": "err_filter_operator",
+            "message": "Unknown filter operator 'like', expected one of: eq, ne, lt, lte, gt, gte, in, exists, contains!",
+            "path": "a",
+            "operator": "like"
+        }).to_string()));
+
+        assert_eq!(s5, Some(json!({
+            "code": "err_filter_value",
+            "message": "Value 'true' is not allowed for filter operator 'gt' (path 'a')!",
+            "path": "a",
+            "operator": "gt"
+        }).to_string()));
+    }
+
+    {
+        // Test bad cursor value.
+        let (r1, r2) = tokio::join!(
+            client.get("/api/v1/entries?namespace=a&cursor=abc").dispatch(),
+            // Valid base64, but not a cursor.
+            client.get("/api/v1/entries?namespace=a&cursor=aGVsbG8").dispatch()
+        );
+
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+
+        assert_eq!(r1.status(), Status::BadRequest);
+        assert_eq!(r2.status(), Status::BadRequest);
+
+        let (s1, s2) = rocket::tokio::join!(r1.into_string(), r2.into_string());
+
+        assert_eq!(s1, Some(json!({
+            "code": "err_cursor_invalid",
+            "message": "Provided cursor is invalid! Use empty cursor to start from the beginning, and 'next_cursor' value from the response to get next page.",
+            "cursor": "abc"
+        }).to_string()));
+
+        assert_eq!(s2, Some(json!({
+            "code": "err_cursor_invalid",
+            "message": "Provided cursor is invalid! Use empty cursor to start from the beginning, and 'next_cursor' value from the response to get next page.",
+            "cursor": "aGVsbG8"
+        }).to_string()));
+    }
+
+    {
+        // Test bad time range and sort values.
+        let (r1, r2, r3) = tokio::join!(
+            client.get("/api/v1/entries?namespace=a&page=0")
+                .header(Header::new("X-Since", "yesterday")).dispatch(),
+            client.get("/api/v1/entries?namespace=a&page=0&sort=content").dispatch(),
+            client.get("/api/v1/entries?namespace=a&cursor=&sort=-created_at").dispatch()
+        );
+
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
+
+        assert_eq!(r1.status(), Status::BadRequest);
+        assert_eq!(r2.status(), Status::BadRequest);
+        assert_eq!(r3.status(), Status::BadRequest);
+
+        let (s1, s2, s3) = rocket::tokio::join!(r1.into_string(), r2.into_string(), r3.into_string());
+
+        let s1 = from_str::<Value>(&s1.unwrap()).expect("Failed to read request body as JSON..");
+        assert_eq!(s1.get("code"), Some(&Value::from("err_timestamp_parse")));
+        assert_eq!(s1.get("value"), Some(&Value::from("yesterday")));
+
+        assert_eq!(s2, Some(json!({
+            "code": "err_sort_invalid",
+            "message": "Couldn't sort by 'content', expected one of: id, created_at, updated_at (prefixed with '-' for descending order)!"
+        }).to_string()));
+
+        assert_eq!(s3, Some(json!({
+            "code": "err_sort_cursor_unsupported",
+            "message": "Cursor pagination always follows ascending ID order, use page numbers to sort by other columns!"
+        }).to_string()));
+    }
+}
@@ -15,3 +15,38 @@ async fn test_health_endpoint() {
     assert_eq!(rbody, value);
 }
 
+
+
+#[rocket::async_test]
+async fn test_liveness_endpoint() {
+    let client = Client::tracked(super::rocket()).await.unwrap();
+
+    let resp = client.get("/api/v1/health/live").dispatch().await;
+    assert_eq!(resp.content_type(), Some(ContentType::JSON));
+    assert_eq!(resp.status(), Status::Ok);
+
+    let rbody = resp.into_string().await.unwrap();
+    let value = json!({"code": "info_status_check_ok"}).to_string();
+    assert_eq!(rbody, value);
+}
+
+
+#[rocket::async_test]
+async fn test_readiness_endpoint() {
+    let client = Client::tracked(super::rocket()).await.unwrap();
+
+    let resp = client.get("/api/v1/health/ready").dispatch().await;
+    assert_eq!(resp.content_type(), Some(ContentType::JSON));
+    assert_eq!(resp.status(), Status::Ok);
+
+    let rbody = serde_json::from_str::<serde_json::Value>(&resp.into_string().await.unwrap()).unwrap();
+    assert_eq!(rbody["code"], "info_ready_ok");
+    assert_eq!(rbody["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(rbody["checks"]["database"]["status"], "ok");
+    assert_eq!(rbody["checks"]["migrations"]["status"], "ok");
+    assert_eq!(rbody["checks"]["migrations"]["pending"], serde_json::json!([]));
+    assert!(rbody["pool"]["size"].as_u64().unwrap() > 0);
+    // At least the connection which ran the check was in use.
+    assert!(rbody["pool"]["in_use"].as_u64().unwrap() > 0);
+    assert!(rbody["pool"]["idle"].is_u64());
+}
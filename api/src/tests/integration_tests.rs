@@ -1,6 +1,10 @@
 use rocket::http::{ContentType, Status, Header};
 use rocket::local::asynchronous::Client;
+use crate::responders::CustomResponder;
 use crate::model::{ApiDatabase, Entry};
+use crate::errors::ApiError;
+use crate::schema::Schema;
+use crate::namespace::NamespaceInfo;
 use serde_json::{from_str, Value};
 use super::rocket;
 
@@ -10,6 +14,9 @@ use super::rocket;
 // transaction so we can regain concurrency.
 static DB_LOCK: parking_lot::Mutex<()> = parking_lot::const_mutex(());
 
+// Namespaces which are cleaned up before every test.
+static TEST_NAMESPACES: &[&str] = &["test_name_alpha", "test_name_alpha_copy", "test_name_alpha_renamed"];
+
 
 /// Macro for running async block in a blocking way. We need to do this, because
 /// our integration tests assume sequential processing of block after block.
@@ -21,9 +28,18 @@ macro_rules! run_test {
             let $client = Client::tracked(rocket()).await.expect("Rocket client");
             let db = ApiDatabase::get_one($client.rocket()).await;
             let $conn = db.expect("failed to get database connection for testing");
-            // Note: this deletes all entries on 'test_name_alpha' namespace to make
-            //       tests more consistent and easier to write.
-            $conn.run(|c| Entry::delete_all(c, "test_name_alpha".to_string())).await;
+            // Note: this deletes all entries (with the schema, metadata, idempotency keys and webhooks) on test namespaces
+            //       to make tests more consistent and easier to write.
+            $conn.run(|c| {
+                for namespace in TEST_NAMESPACES {
+                    Entry::delete_all(c, namespace.to_string())?;
+                    Schema::delete(c, namespace)?;
+                    NamespaceInfo::delete_metadata(c, namespace)?;
+                    c.execute("DELETE FROM idempotency_keys WHERE namespace = $1", &[namespace])?;
+                    c.execute("DELETE FROM webhooks WHERE namespace = $1", &[namespace])?;
+                }
+                Ok::<(), ApiError>(())
+            }).await.expect("failed to clean up test namespace");
 
             $block
         })
@@ -612,3 +628,1014 @@ fn test_suit_4() {
     })
 }
 
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Create 4 entries with nested fields
+///     - Query entries with equality filter (on nested path)
+///     - Query entries with numeric range filter
+///     - Query entries with 'in', 'exists' and 'contains' filters
+///     - Verify that key names are not matched as values
+#[test]
+fn test_suit_5() {
+    run_test!(|client, _conn| {
+        let ids: Vec<u64>;
+
+        {
+            // Creating 4 entries with structured contents ...
+            let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+                .body(
+                    "[{\"status\": \"failed\", \"metrics\": {\"latency_ms\": 700}, \"tags\": [\"a\", \"b\"]},
+                     {\"status\": \"ok\", \"metrics\": {\"latency_ms\": 120}, \"tags\": [\"a\"]},
+                     {\"status\": \"failed\", \"metrics\": {\"latency_ms\": 90}},
+                     {\"failed\": \"status\", \"note\": \"Operation has failed\"}]"
+                ).dispatch().await;
+
+            // We expect 200 JSON response.
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.status(), Status::Ok);
+
+            let body = from_str::<Value>(&r.into_string().await.unwrap())
+                .expect("Failed to read request body as JSON..");
+            ids = body.get("item_ids")
+                .expect("Expected response to contain 'item_ids' field..")
+                .as_array().expect("Failed to parse 'item_ids' as an array..")
+                .iter().map(|id| id.as_u64().expect("Failed to parse item id as u64..")).collect();
+
+            // Verify that 4 items were created.
+            assert_eq!(ids.len(), 4);
+        }
+
+        // Pairs of filter and ids of entries which we expect to receive.
+        let cases = vec![
+            ("{\"status\": {\"eq\": \"failed\"}}", vec![ids[0], ids[2]]),
+            ("{\"status\": {\"ne\": \"failed\"}}", vec![ids[1]]),
+            ("{\"metrics.latency_ms\": {\"gt\": 100}}", vec![ids[0], ids[1]]),
+            ("{\"metrics.latency_ms\": {\"gte\": 90, \"lt\": 700}}", vec![ids[1], ids[2]]),
+            ("{\"status\": {\"eq\": \"failed\"}, \"metrics.latency_ms\": {\"gt\": 500}}", vec![ids[0]]),
+            ("{\"status\": {\"in\": [\"ok\", \"unknown\"]}}", vec![ids[1]]),
+            ("{\"tags\": {\"exists\": false}}", vec![ids[2], ids[3]]),
+            ("{\"tags.1\": {\"exists\": true}}", vec![ids[0]]),
+            ("{\"tags\": {\"contains\": [\"b\"]}}", vec![ids[0]]),
+            ("{\"note\": {\"contains\": \"has failed\"}}", vec![ids[3]]),
+            // Numbers are never compared with strings.
+            ("{\"status\": {\"gt\": 0}}", vec![]),
+        ];
+
+        for (filter, expected) in cases {
+            let r = client.get("/api/v1/entries?page=0&namespace=test_name_alpha")
+                .header(Header::new("X-Filter", filter)).dispatch().await;
+
+            // We expect 200 JSON response.
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.status(), Status::Ok);
+
+            let body = from_str::<Value>(&r.into_string().await.unwrap())
+                .expect("Failed to read request body as JSON..");
+            let received = body.get("data")
+                .expect("Expected response to contain 'data' field..")
+                .as_array().expect("Failed to parse 'data' as an array..")
+                .iter().map(|entry| entry.get("id")
+                    .expect("Expected entry to contain 'id' field..")
+                    .as_u64().expect("Failed to parse 'id' value as u64.."))
+                .collect::<Vec<u64>>();
+
+            // Verify that filter matched exactly expected entries.
+            assert_eq!(received, expected, "Unexpected result for filter '{}'", filter);
+        }
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Create 5 entries
+///     - Walk all entries with cursor pagination (page size 2)
+///     - Add new entry in the middle of the walk
+///     - Verify that no entry was skipped or duplicated
+#[test]
+fn test_suit_6() {
+    run_test!(|client, _conn| {
+        let mut expected: Vec<u64>;
+
+        {
+            // Creating 5 entries ...
+            let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+                .body("[{\"n\": 1}, {\"n\": 2}, {\"n\": 3}, {\"n\": 4}, {\"n\": 5}]").dispatch().await;
+
+            // We expect 200 JSON response.
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.status(), Status::Ok);
+
+            let body = from_str::<Value>(&r.into_string().await.unwrap())
+                .expect("Failed to read request body as JSON..");
+            expected = body.get("item_ids")
+                .expect("Expected response to contain 'item_ids' field..")
+                .as_array().expect("Failed to parse 'item_ids' as an array..")
+                .iter().map(|id| id.as_u64().expect("Failed to parse item id as u64..")).collect();
+        }
+
+        let mut received: Vec<u64> = vec![];
+        let mut cursor = String::new();
+        let mut pages = 0;
+
+        loop {
+            let r = client.get(format!("/api/v1/entries?namespace=test_name_alpha&page_size=2&cursor={}", cursor))
+                .dispatch().await;
+
+            // We expect 200 JSON response.
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.status(), Status::Ok);
+
+            let body = from_str::<Value>(&r.into_string().await.unwrap())
+                .expect("Failed to read request body as JSON..");
+            let data = body.get("data")
+                .expect("Expected response to contain 'data' field..")
+                .as_array().expect("Failed to parse 'data' as an array..");
+
+            // Pages are never bigger than requested.
+            assert!(data.len() <= 2);
+            received.extend(data.iter().map(|entry| entry.get("id")
+                .expect("Expected entry to contain 'id' field..")
+                .as_u64().expect("Failed to parse 'id' value as u64..")));
+            pages += 1;
+
+            if pages == 1 {
+                // Add an entry after the first page, it must show up at the end of the walk.
+                let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+                    .body("{\"n\": 6}").dispatch().await;
+                let body = from_str::<Value>(&r.into_string().await.unwrap())
+                    .expect("Failed to read request body as JSON..");
+                expected.push(body.get("item_id")
+                    .expect("Expected response to contain 'item_id' field..")
+                    .as_u64().expect("Failed to parse 'item_id' value as u64.."));
+            }
+
+            match body.get("next_cursor").expect("Expected response to contain 'next_cursor' field..") {
+                Value::String(next) => cursor = next.clone(),
+                Value::Null => break,
+                other => panic!("Unexpected 'next_cursor' value: {}", other),
+            }
+        }
+
+        // Verify that every entry was received exactly once, in order.
+        assert_eq!(received, expected);
+        // 6 entries with page size 2 take exactly 3 pages.
+        assert_eq!(pages, 3);
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Create 5 entries
+///     - Query middle page with total count
+///     - Query last page without total count
+///     - Verify pagination metadata and links
+#[test]
+fn test_suit_7() {
+    run_test!(|client, _conn| {
+        {
+            // Creating 5 entries ...
+            let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+                .body("[{\"n\": 1}, {\"n\": 2}, {\"n\": 3}, {\"n\": 4}, {\"n\": 5}]").dispatch().await;
+
+            // We expect 200 JSON response.
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.status(), Status::Ok);
+        }
+
+        {
+            // Query middle page with total count ...
+            let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=1&page_size=2&count=true")
+                .dispatch().await;
+
+            // We expect 200 JSON response.
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.status(), Status::Ok);
+
+            // Verify links to neighbouring (and first/last) pages.
+            let link = r.headers().get_one("Link").expect("Expected response to contain 'Link' header..").to_string();
+            assert_eq!(link, "</api/v1/entries?namespace=test_name_alpha&page_size=2&count=true&page=0>; rel=\"prev\", \
+                </api/v1/entries?namespace=test_name_alpha&page_size=2&count=true&page=2>; rel=\"next\", \
+                </api/v1/entries?namespace=test_name_alpha&page_size=2&count=true&page=0>; rel=\"first\", \
+                </api/v1/entries?namespace=test_name_alpha&page_size=2&count=true&page=2>; rel=\"last\"");
+
+            let body = from_str::<Value>(&r.into_string().await.unwrap())
+                .expect("Failed to read request body as JSON..");
+
+            // Verify pagination metadata.
+            assert_eq!(body.get("total"), Some(&Value::from(5)));
+            assert_eq!(body.get("total_pages"), Some(&Value::from(3)));
+            assert_eq!(body.get("has_next"), Some(&Value::from(true)));
+            assert_eq!(body.get("has_prev"), Some(&Value::from(true)));
+        }
+
+        {
+            // Query last page without total count ...
+            let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=2&page_size=2")
+                .dispatch().await;
+
+            // We expect 200 JSON response.
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.status(), Status::Ok);
+
+            // Verify that there is no link to the next page.
+            let link = r.headers().get_one("Link").expect("Expected response to contain 'Link' header..").to_string();
+            assert_eq!(link, "</api/v1/entries?namespace=test_name_alpha&page_size=2&page=1>; rel=\"prev\"");
+
+            let body = from_str::<Value>(&r.into_string().await.unwrap())
+                .expect("Failed to read request body as JSON..");
+
+            // Verify that metadata is not computed unless asked for.
+            assert_eq!(body.get("total"), None);
+            assert_eq!(body.get("data").and_then(|data| data.as_array()).map(|data| data.len()), Some(1));
+        }
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Create 2 entries one after another
+///     - Query entries created since/until the time of the second one
+///     - Update first entry
+///     - Query entries updated since the update, and sorted by update time
+#[test]
+fn test_suit_8() {
+    run_test!(|client, _conn| {
+        // Helper to read list of (id, created_at, updated_at) from the listing response.
+        async fn list(r: rocket::local::asynchronous::LocalResponse<'_>) -> Vec<(u64, String, String)> {
+            // We expect 200 JSON response.
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.status(), Status::Ok);
+
+            let body = from_str::<Value>(&r.into_string().await.unwrap())
+                .expect("Failed to read request body as JSON..");
+            body.get("data")
+                .expect("Expected response to contain 'data' field..")
+                .as_array().expect("Failed to parse 'data' as an array..")
+                .iter().map(|entry| (
+                    entry.get("id").and_then(|v| v.as_u64()).expect("Expected entry to contain 'id'.."),
+                    entry.get("created_at").and_then(|v| v.as_str()).expect("Expected entry to contain 'created_at'..").to_string(),
+                    entry.get("updated_at").and_then(|v| v.as_str()).expect("Expected entry to contain 'updated_at'..").to_string(),
+                ))
+                .collect()
+        }
+
+        // Creating 2 entries in separate requests, so they get different timestamps ...
+        for n in 0..2 {
+            let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+                .body(format!("{{\"n\": {}}}", n)).dispatch().await;
+            assert_eq!(r.status(), Status::Ok);
+        }
+
+        let entries = list(client.get("/api/v1/entries?namespace=test_name_alpha&page=0").dispatch().await).await;
+        assert_eq!(entries.len(), 2);
+        let (first, second) = (entries[0].clone(), entries[1].clone());
+
+        // Lower bound is inclusive.
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0")
+            .header(Header::new("X-Since", second.1.clone())).dispatch().await;
+        assert_eq!(list(r).await.iter().map(|e| e.0).collect::<Vec<u64>>(), vec![second.0]);
+
+        // Upper bound is exclusive.
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0")
+            .header(Header::new("X-Until", second.1.clone())).dispatch().await;
+        assert_eq!(list(r).await.iter().map(|e| e.0).collect::<Vec<u64>>(), vec![first.0]);
+
+        // Updating first entry ...
+        let r = client.put(format!("/api/v1/entries/{}?namespace=test_name_alpha", first.0))
+            .header(ContentType::JSON).body("{\"n\": 2}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0&sort=-updated_at").dispatch().await;
+        let entries = list(r).await;
+
+        // Verify that update changed only update time, and that it's now the most recent one.
+        assert_eq!(entries.iter().map(|e| e.0).collect::<Vec<u64>>(), vec![first.0, second.0]);
+        assert_eq!(entries[0].1, first.1);
+        assert!(entries[0].2 > first.2);
+
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0")
+            .header(Header::new("X-Updated-Since", entries[0].2.clone())).dispatch().await;
+        assert_eq!(list(r).await.iter().map(|e| e.0).collect::<Vec<u64>>(), vec![first.0]);
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Add multiple entries, one of which can't be stored (all-or-nothing mode)
+///     - Verify that nothing was created
+///     - Add same entries in partial mode
+///     - Verify that all entries but the bad one were created
+#[test]
+fn test_suit_9() {
+    run_test!(|client, _conn| {
+        // Postgres doesn't allow null character in JSONB strings, so second entry is rejected.
+        let body = "[{\"n\": 0}, {\"n\": \"\\u0000\"}, {\"n\": 2}]";
+
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha")
+            .header(ContentType::JSON).body(body).dispatch().await;
+
+        // We expect 400 JSON response.
+        assert_eq!(r.status(), Status::BadRequest);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        let response = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(response["code"], "err_bulk_insert_rejected");
+
+        // Verify that no entry was created.
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0").dispatch().await;
+        let response = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(response["data"].as_array().unwrap().len(), 0);
+
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha&partial=true")
+            .header(ContentType::JSON).body(body).dispatch().await;
+
+        // We expect 200 JSON response with result for each entry.
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        let response = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(response["code"], "info_many_items_partial");
+        assert_eq!(response["inserted"], 2);
+        assert_eq!(response["failed"], 1);
+
+        let results = response["results"].as_array().unwrap();
+        assert_eq!(results.iter().map(|r| r["index"].as_u64().unwrap()).collect::<Vec<u64>>(), vec![0, 1, 2]);
+        assert!(results[0]["id"].is_u64());
+        assert!(results[1]["error"].is_string());
+        assert!(results[2]["id"].as_u64().unwrap() > results[0]["id"].as_u64().unwrap());
+
+        // Verify that good entries were created.
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0").dispatch().await;
+        let response = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        let contents = response["data"].as_array().unwrap().iter()
+            .map(|e| e["content"].clone()).collect::<Vec<Value>>();
+        assert_eq!(Value::Array(contents).to_string(), json!([{"n": 0}, {"n": 2}]).to_string());
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Add entries as NDJSON, with one malformed line and one empty line
+///     - Verify summary of the ingestion
+///     - Query entries and verify that good lines were created
+#[test]
+fn test_suit_10() {
+    run_test!(|client, _conn| {
+        let body = "{\"n\": 0}\n{\"n\": 1\n\n{\"n\": 2}\r\n[3]\n";
+
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha")
+            .header(ContentType::new("application", "x-ndjson")).body(body).dispatch().await;
+
+        // We expect 200 JSON response.
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        let response = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(response["code"], "info_ndjson_ingest_ok");
+        assert_eq!(response["inserted"], 3);
+        assert_eq!(response["rejected"], 1);
+
+        // Second line is malformed, and line numbers count empty lines too.
+        let rejected = response["rejected_lines"].as_array().unwrap();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0]["line"], 2);
+
+        // Entries of a single batch get consecutive IDs.
+        let ranges = response["id_ranges"].as_array().unwrap();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0][1].as_u64().unwrap() - ranges[0][0].as_u64().unwrap(), 2);
+
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0").dispatch().await;
+        let response = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        let contents = response["data"].as_array().unwrap().iter()
+            .map(|e| e["content"].clone()).collect::<Vec<Value>>();
+        assert_eq!(Value::Array(contents).to_string(), json!([{"n": 0}, {"n": 2}, [3]]).to_string());
+
+        // Body of exactly the limit is accepted, even without the final newline.
+        let body = "{\"n\": 4}\n{\"n\": 12345}";
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha")
+            .header(ContentType::new("application", "x-ndjson"))
+            .header(Header::new("X-Content-Length", body.len().to_string()))
+            .body(body).dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["inserted"], 2);
+
+        // Longer body is rejected, and the line cut by the limit isn't created as {"n": 123}.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha")
+            .header(ContentType::new("application", "x-ndjson"))
+            .header(Header::new("X-Content-Length", (body.len() - 2).to_string()))
+            .body(body).dispatch().await;
+        assert_eq!(r.status(), Status::PayloadTooLarge);
+        let response = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(response["code"], "err_buffer_too_large");
+        assert_eq!(response["inserted"], 0);
+
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0").dispatch().await;
+        let response = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(response["data"].as_array().unwrap().len(), 5);
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Add multiple entries
+///     - Export entries as NDJSON, JSON array and CSV
+///     - Export entries as CSV with selected columns and a filter
+#[test]
+fn test_suit_11() {
+    run_test!(|client, _conn| {
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("[{\"name\": \"a\", \"meta\": {\"size\": 1}}, {\"name\": \"b, c\", \"tags\": [1, 2]}]")
+            .dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let ids = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_ids"].clone();
+
+        // NDJSON is the default format.
+        let r = client.get("/api/v1/entries/export?namespace=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.content_type(), Some(ContentType::new("application", "x-ndjson")));
+        let lines = r.into_string().await.unwrap().lines()
+            .map(|line| from_str::<Value>(line).unwrap())
+            .collect::<Vec<Value>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["id"], ids[0]);
+        assert_eq!(lines[1]["content"]["name"], "b, c");
+
+        // Format is chosen by "Accept" header too.
+        let r = client.get("/api/v1/entries/export?namespace=test_name_alpha")
+            .header(Header::new("Accept", "application/json")).dispatch().await;
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        let array = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(array.as_array().unwrap().len(), 2);
+        assert_eq!(array[1]["id"], ids[1]);
+
+        // Columns are taken from exported entries (keys are sorted), nested objects are flattened.
+        let r = client.get("/api/v1/entries/export?namespace=test_name_alpha&format=csv").dispatch().await;
+        assert_eq!(r.content_type(), Some(ContentType::CSV));
+        let csv = r.into_string().await.unwrap();
+        let lines = csv.split("\r\n").collect::<Vec<&str>>();
+        assert_eq!(lines[0], "id,created_at,updated_at,meta.size,name,tags");
+        assert!(lines[1].starts_with(&format!("{},", ids[0])));
+        assert!(lines[1].ends_with(",1,a,"));
+        assert!(lines[2].ends_with(",,\"b, c\",\"[1,2]\""));
+
+        // Selecting columns and filtering entries.
+        let r = client.get("/api/v1/entries/export?namespace=test_name_alpha&format=csv&columns=meta.size,name")
+            .header(Header::new("X-Filter", "{\"name\": {\"eq\": \"a\"}}")).dispatch().await;
+        let csv = r.into_string().await.unwrap();
+        let lines = csv.split("\r\n").collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "id,created_at,updated_at,meta.size,name");
+        assert!(lines[1].ends_with(",1,a"));
+
+        // Empty JSON export is still a valid array.
+        let r = client.get("/api/v1/entries/export?namespace=test_name_alpha&format=json&query=nothing").dispatch().await;
+        assert_eq!(r.into_string().await.unwrap(), "[]");
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Query and delete entry which doesn't exist
+///     - Query and delete entry from another namespace
+///     - Query entry when database query fails
+///     - Query entry when database connection is lost
+#[test]
+fn test_suit_12() {
+    run_test!(|client, conn| {
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"n\": 0}").dispatch().await;
+        let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+
+        // Entry doesn't exist at all.
+        for r in vec![
+            client.get(format!("/api/v1/entries/{}?namespace=test_name_alpha", id + 1000)).dispatch().await,
+            client.delete(format!("/api/v1/entries/{}?namespace=test_name_alpha", id + 1000)).dispatch().await,
+        ] {
+            // We expect 404 JSON response.
+            assert_eq!(r.status(), Status::NotFound);
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.into_string().await, Some(json!({
+                "code": "err_entry_not_found",
+                "message": format!("Entry with ID '{}' does not exist!", id + 1000),
+                "namespace": "test_name_alpha",
+                "id": id + 1000
+            }).to_string()));
+        }
+
+        // Entry exists, but in another namespace.
+        for r in vec![
+            client.get(format!("/api/v1/entries/{}?namespace=test_name_beta", id)).dispatch().await,
+            client.delete(format!("/api/v1/entries/{}?namespace=test_name_beta", id)).dispatch().await,
+        ] {
+            // We expect 404 JSON response.
+            assert_eq!(r.status(), Status::NotFound);
+            assert_eq!(r.content_type(), Some(ContentType::JSON));
+            assert_eq!(r.into_string().await, Some(json!({
+                "code": "err_entry_namespace_mismatch",
+                "message": format!("Entry with ID '{}' belongs to another namespace!", id),
+                "namespace": "test_name_beta",
+                "id": id
+            }).to_string()));
+        }
+
+        // Verify that entry wasn't deleted from the wrong namespace.
+        let r = client.get(format!("/api/v1/entries/{}?namespace=test_name_alpha", id)).dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+
+        // Query fails, because the table can't be found from this session.
+        let error = conn.run(move |c| {
+            c.batch_execute("SET search_path TO voyeur_missing_schema").unwrap();
+            let result = Entry::get_one(c, id, "test_name_alpha".to_string());
+            c.batch_execute("RESET search_path").unwrap();
+            result.unwrap_err()
+        }).await;
+        assert!(matches!(error, ApiError::Database(_)));
+        assert!(matches!(CustomResponder::from(error), CustomResponder::UnknownError(_)));
+
+        // Connection is terminated by the server (pool will throw it away afterwards).
+        let error = conn.run(move |c| {
+            let _ = c.batch_execute("SELECT pg_terminate_backend(pg_backend_pid())");
+            Entry::get_one(c, id, "test_name_alpha".to_string()).unwrap_err()
+        }).await;
+        assert!(matches!(error, ApiError::Unavailable(_)));
+        assert!(matches!(CustomResponder::from(error), CustomResponder::Unavailable(_)));
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Attach JSON Schema to the namespace
+///     - Add conforming and non-conforming entries, one by one and in bulk
+///     - Update entry with non-conforming content
+///     - Validate documents without storing them
+///     - Detach schema and add previously rejected entry
+#[test]
+fn test_suit_13() {
+    run_test!(|client, _conn| {
+        let schema = json!({
+            "type": "object",
+            "required": ["status"],
+            "properties": {
+                "status": {"enum": ["ok", "failed"]},
+                "metrics": {"type": "object", "additionalProperties": {"type": "number", "minimum": 0}}
+            }
+        }).to_string();
+
+        let r = client.put("/api/v1/schemas?namespace=test_name_alpha").header(ContentType::JSON)
+            .body(&schema).dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+
+        let r = client.get("/api/v1/schemas?namespace=test_name_alpha").dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["schema"].to_string(), from_str::<Value>(&schema).unwrap().to_string());
+
+        // Conforming entry is created.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"status\": \"ok\", \"metrics\": {\"latency_ms\": 5}}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+
+        // Non-conforming entry is rejected with all failing pointers.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"metrics\": {\"latency_ms\": -1, \"count\": \"a\"}}").dispatch().await;
+
+        // We expect 422 JSON response.
+        assert_eq!(r.status(), Status::UnprocessableEntity);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "err_schema_violation",
+            "message": "Document doesn't conform to the JSON Schema of the namespace!",
+            "errors": [
+                {"pointer": "/status", "message": "Required property is missing!"},
+                {"pointer": "/metrics/count", "message": "Expected type 'number'!"},
+                {"pointer": "/metrics/latency_ms", "message": "Value must be greater than or equal to 0!"}
+            ]
+        }).to_string()));
+
+        // Bulk creation is rejected as a whole, pointers start with the index.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("[{\"status\": \"ok\"}, {\"status\": \"unknown\"}]").dispatch().await;
+        assert_eq!(r.status(), Status::UnprocessableEntity);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["code"], "err_schema_violation");
+        assert_eq!(body["errors"][0]["pointer"], "/1/status");
+
+        // Unless partial mode is used.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha&partial=true").header(ContentType::JSON)
+            .body("[{\"status\": \"ok\"}, {\"status\": \"unknown\"}]").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["inserted"], 1);
+        assert!(body["results"][0]["id"].is_u64());
+        assert!(body["results"][1]["error"].as_str().unwrap().contains("/status"));
+
+        let r = client.put(format!("/api/v1/entries/{}?namespace=test_name_alpha", id)).header(ContentType::JSON)
+            .body("{\"status\": 1}").dispatch().await;
+        assert_eq!(r.status(), Status::UnprocessableEntity);
+
+        // Dry run doesn't store anything.
+        let r = client.post("/api/v1/schemas/validate?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"status\": \"failed\"}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["valid"], true);
+
+        let r = client.post("/api/v1/schemas/validate?namespace=test_name_alpha&many=true").header(ContentType::JSON)
+            .body("[{\"status\": \"failed\"}, {}]").dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["valid"], false);
+        assert_eq!(body["errors"][0]["pointer"], "/1/status");
+
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0&count=true").dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["total"], 2);
+
+        // Without schema anything goes again.
+        let r = client.delete("/api/v1/schemas?namespace=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"status\": 1}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+
+        let r = client.get("/api/v1/schemas?namespace=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::NotFound);
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Describe empty namespace
+///     - Add entries and check statistics
+///     - Delete metadata and check that namespace is still listed
+#[test]
+fn test_suit_14() {
+    run_test!(|client, conn| {
+        let r = client.get("/api/v1/namespaces/info?namespace=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::NotFound);
+
+        let r = client.put("/api/v1/namespaces?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"description\": \"Integration tests\", \"owner\": \"team-a\", \"tags\": [\"ci\", \"tests\", \"ci\"]}")
+            .dispatch().await;
+
+        // We expect 200 JSON response.
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "info_namespace_saved",
+            "message": "Successfully saved metadata of namespace 'test_name_alpha'!",
+            "data": {
+                "name": "test_name_alpha",
+                "description": "Integration tests",
+                "owner": "team-a",
+                "tags": ["ci", "tests"],
+                "entries": 0,
+                "content_bytes": 0,
+                "first_id": null,
+                "last_id": null,
+                "last_write_at": null
+            }
+        }).to_string()));
+
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("[{\"a\": 1}, {\"b\": \"text\"}]").dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        let ids = body["item_ids"].as_array().unwrap().clone();
+
+        let r = client.get("/api/v1/namespaces").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        let listed = body["data"].as_array().unwrap().iter().find(|n| n["name"] == "test_name_alpha").unwrap().clone();
+        assert_eq!(listed["owner"], "team-a");
+        assert_eq!(listed["entries"], 2);
+        // Sizes of '{"a": 1}' and '{"b": "text"}' as stored by the database (binary JSONB).
+        let stored = conn.run(|c| c.query_one(
+            "SELECT sum(pg_column_size(content))::BIGINT AS size FROM entries WHERE namespace = 'test_name_alpha'", &[]
+        ).unwrap().get::<_, i64>("size")).await;
+        assert!(stored > 0);
+        assert_eq!(listed["content_bytes"], stored);
+        assert_eq!(listed["first_id"], ids[0]);
+        assert_eq!(listed["last_id"], ids[1]);
+        assert!(listed["last_write_at"].is_string());
+
+        let r = client.delete("/api/v1/namespaces?namespace=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let r = client.delete("/api/v1/namespaces?namespace=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::NotFound);
+
+        let r = client.get("/api/v1/namespaces/info?namespace=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["data"]["entries"], 2);
+        assert_eq!(body["data"]["owner"], Value::Null);
+        assert_eq!(body["data"]["tags"], Value::Array(vec![]));
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Add entries and copy some of them into another namespace
+///     - Try to rename namespace into the existing one
+///     - Rename the copy
+///     - Try to merge it back into namespace with JSON Schema
+///     - Merge it back without JSON Schema
+#[test]
+fn test_suit_15() {
+    run_test!(|client, _conn| {
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("[{\"n\": 1, \"s\": \"ok\"}, {\"n\": 2, \"s\": \"failed\"}, {\"n\": 3, \"s\": \"failed\"}]")
+            .dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        let ids = body["item_ids"].as_array().unwrap().clone();
+
+        let r = client.post("/api/v1/namespaces/copy?namespace=test_name_alpha&target=test_name_alpha_copy")
+            .header(Header::new("X-Filter", "{\"s\": {\"eq\": \"failed\"}}")).dispatch().await;
+
+        // We expect 200 JSON response.
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "info_namespace_copied",
+            "message": "Successfully copied 2 entries from namespace 'test_name_alpha' to 'test_name_alpha_copy'!",
+            "namespace": "test_name_alpha",
+            "target": "test_name_alpha_copy",
+            "copied": 2
+        }).to_string()));
+
+        // Copies are new entries in the same order.
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha_copy&page=0").dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        let copies = body["data"].as_array().unwrap().clone();
+        assert_eq!(copies.len(), 2);
+        assert_eq!(copies[0]["content"]["n"], 2);
+        assert_eq!(copies[1]["content"]["n"], 3);
+        assert!(copies[0]["id"].as_u64().unwrap() > ids[2].as_u64().unwrap());
+
+        let r = client.post("/api/v1/namespaces/rename?namespace=test_name_alpha&target=test_name_alpha_copy").dispatch().await;
+        assert_eq!(r.status(), Status::Conflict);
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "err_namespace_conflict",
+            "message": "Namespace 'test_name_alpha_copy' already exists!",
+            "namespace": "test_name_alpha",
+            "target": "test_name_alpha_copy"
+        }).to_string()));
+
+        let r = client.post("/api/v1/namespaces/rename?namespace=test_name_alpha_copy&target=test_name_alpha_renamed").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["moved"], 2);
+
+        // Entries keep their IDs.
+        let r = client.get(format!("/api/v1/entries/{}?namespace=test_name_alpha_renamed", copies[1]["id"])).dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let r = client.get("/api/v1/namespaces/info?namespace=test_name_alpha_copy").dispatch().await;
+        assert_eq!(r.status(), Status::NotFound);
+
+        // Nothing is moved, if any entry doesn't conform to the schema of the target.
+        let r = client.put("/api/v1/schemas?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"properties\": {\"n\": {\"maximum\": 2}}}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+
+        let r = client.post("/api/v1/namespaces/merge?namespace=test_name_alpha_renamed&target=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::UnprocessableEntity);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["errors"][0]["pointer"], format!("/{}/n", copies[1]["id"]));
+
+        let r = client.delete("/api/v1/schemas?namespace=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+
+        let r = client.post("/api/v1/namespaces/merge?namespace=test_name_alpha_renamed&target=test_name_alpha").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["code"], "info_namespace_merged");
+        assert_eq!(body["moved"], 2);
+
+        let r = client.get("/api/v1/namespaces/info?namespace=test_name_alpha").dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["data"]["entries"], 5);
+        let r = client.get("/api/v1/namespaces/info?namespace=test_name_alpha_renamed").dispatch().await;
+        assert_eq!(r.status(), Status::NotFound);
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Add entry
+///     - Patch it with JSON Merge Patch
+///     - Patch it with JSON Patch
+///     - Try patches with failing test and missing path
+#[test]
+fn test_suit_16() {
+    run_test!(|client, _conn| {
+        let merge_patch = ContentType::new("application", "merge-patch+json");
+        let json_patch = ContentType::new("application", "json-patch+json");
+
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"status\": \"running\", \"tags\": [\"a\"], \"metrics\": {\"latency_ms\": 5, \"count\": 1}}")
+            .dispatch().await;
+        let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+
+        let r = client.patch(format!("/api/v1/entries/{}?namespace=test_name_alpha", id)).header(merge_patch.clone())
+            .body("{\"status\": \"done\", \"metrics\": {\"count\": null, \"errors\": 0}}").dispatch().await;
+
+        // We expect 200 JSON response.
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["code"], "info_patch_item_ok");
+        assert_eq!(body["data"]["content"].to_string(), json!({
+            "status": "done",
+            "tags": ["a"],
+            "metrics": {"latency_ms": 5, "errors": 0}
+        }).to_string());
+
+        let r = client.patch(format!("/api/v1/entries/{}?namespace=test_name_alpha", id)).header(json_patch.clone())
+            .body("[
+                {\"op\": \"test\", \"path\": \"/status\", \"value\": \"done\"},
+                {\"op\": \"add\", \"path\": \"/tags/-\", \"value\": \"b\"},
+                {\"op\": \"add\", \"path\": \"/tags/0\", \"value\": \"z\"},
+                {\"op\": \"replace\", \"path\": \"/metrics/errors\", \"value\": 2},
+                {\"op\": \"move\", \"from\": \"/metrics/latency_ms\", \"path\": \"/latency_ms\"},
+                {\"op\": \"copy\", \"from\": \"/status\", \"path\": \"/previous_status\"},
+                {\"op\": \"remove\", \"path\": \"/status\"}
+            ]").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["data"]["content"].to_string(), json!({
+            "tags": ["z", "a", "b"],
+            "metrics": {"errors": 2},
+            "latency_ms": 5,
+            "previous_status": "done"
+        }).to_string());
+
+        // Failed test doesn't change anything, even if previous operations succeeded.
+        let r = client.patch(format!("/api/v1/entries/{}?namespace=test_name_alpha", id)).header(json_patch.clone())
+            .body("[{\"op\": \"remove\", \"path\": \"/tags\"}, {\"op\": \"test\", \"path\": \"/latency_ms\", \"value\": 6}]")
+            .dispatch().await;
+        assert_eq!(r.status(), Status::Conflict);
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "err_patch_test",
+            "message": "Operation 1 of the patch failed at '/latency_ms': expected 6, but found 5!",
+            "namespace": "test_name_alpha",
+            "index": 1,
+            "pointer": "/latency_ms"
+        }).to_string()));
+
+        let r = client.patch(format!("/api/v1/entries/{}?namespace=test_name_alpha", id)).header(json_patch.clone())
+            .body("[{\"op\": \"replace\", \"path\": \"/metrics/missing/value\", \"value\": 1}]").dispatch().await;
+        assert_eq!(r.status(), Status::UnprocessableEntity);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["code"], "err_patch_path");
+        assert_eq!(body["pointer"], "/metrics/missing/value");
+
+        let r = client.get(format!("/api/v1/entries/{}?namespace=test_name_alpha", id)).dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert!(body["data"]["content"]["tags"].is_array());
+
+        // Entries of other namespaces can't be patched.
+        let r = client.patch(format!("/api/v1/entries/{}?namespace=test_name_beta", id)).header(merge_patch.clone())
+            .body("{}").dispatch().await;
+        assert_eq!(r.status(), Status::NotFound);
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["code"], "err_entry_namespace_mismatch");
+    })
+}
+
+
+/// Following test suit verifies API availability for the story below:
+///     - Add entry and read it with its ETag
+///     - Read it again with If-None-Match
+///     - Update it with current and stale If-Match
+///     - Patch and delete it with stale and current If-Match
+#[test]
+fn test_suit_17() {
+    run_test!(|client, _conn| {
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .body("{\"workers\": 1}").dispatch().await;
+        let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+        let url = format!("/api/v1/entries/{}?namespace=test_name_alpha", id);
+
+        let r = client.get(url.clone()).dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let etag = r.headers().get_one("ETag").expect("Expected response to contain ETag..").to_string();
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(etag, format!("\"{}\"", body["data"]["version"]));
+
+        let r = client.get(url.clone()).header(Header::new("If-None-Match", format!("\"0\", W/{}", etag))).dispatch().await;
+        assert_eq!(r.status(), Status::NotModified);
+        assert_eq!(r.headers().get_one("ETag"), Some(etag.as_str()));
+        assert!(r.into_string().await.unwrap_or_default().is_empty());
+
+        let r = client.put(url.clone()).header(ContentType::JSON).header(Header::new("If-Match", etag.clone()))
+            .body("{\"workers\": 2}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let new_etag = r.headers().get_one("ETag").unwrap().to_string();
+        assert_ne!(new_etag, etag);
+
+        // Another writer still has the old ETag.
+        let r = client.put(url.clone()).header(ContentType::JSON).header(Header::new("If-Match", etag.clone()))
+            .body("{\"workers\": 3}").dispatch().await;
+
+        // We expect 412 JSON response.
+        assert_eq!(r.status(), Status::PreconditionFailed);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "err_entry_precondition_failed",
+            "message": format!("Entry with ID '{}' was changed, its ETag doesn't match 'If-Match'!", id),
+            "id": id
+        }).to_string()));
+
+        let r = client.patch(url.clone()).header(ContentType::new("application", "merge-patch+json"))
+            .header(Header::new("If-Match", etag.clone())).body("{\"workers\": 3}").dispatch().await;
+        assert_eq!(r.status(), Status::PreconditionFailed);
+
+        let r = client.patch(url.clone()).header(ContentType::new("application", "merge-patch+json"))
+            .header(Header::new("If-Match", new_etag.clone())).body("{\"workers\": 3}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let patched_etag = r.headers().get_one("ETag").unwrap().to_string();
+
+        let r = client.delete(url.clone()).header(Header::new("If-Match", new_etag.clone())).dispatch().await;
+        assert_eq!(r.status(), Status::PreconditionFailed);
+
+        // Content is the one of the successful patch.
+        let r = client.get(url.clone()).dispatch().await;
+        assert_eq!(r.headers().get_one("ETag"), Some(patched_etag.as_str()));
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["data"]["content"]["workers"], 3);
+
+        let r = client.delete(url.clone()).header(Header::new("If-Match", "*")).dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+
+        // Entry which doesn't exist never matches.
+        let r = client.put(url.clone()).header(ContentType::JSON).header(Header::new("If-Match", "*"))
+            .body("{\"workers\": 4}").dispatch().await;
+        assert_eq!(r.status(), Status::PreconditionFailed);
+    })
+}
+
+
+/// Following test suit verifies idempotent entry creation:
+///     - Create an entry with Idempotency-Key
+///     - Retry the request, the same entry is returned
+///     - Reuse the key with another body
+///     - Create multiple entries with another key and retry
+#[test]
+fn test_suit_18() {
+    run_test!(|client, _conn| {
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "job-1")).body("{\"job\": 1}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.headers().get_one("Idempotency-Replayed"), None);
+        let first = r.into_string().await.unwrap();
+        let id = from_str::<Value>(&first).unwrap()["item_id"].as_u64().unwrap();
+
+        // Retry gets the same response and nothing is created.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "job-1")).body("{\"job\": 1}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.headers().get_one("Idempotency-Replayed"), Some("true"));
+        assert_eq!(from_str::<Value>(&r.into_string().await.unwrap()).unwrap(), from_str::<Value>(&first).unwrap());
+
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0").dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["id"], id);
+
+        // Same key with another body is a conflict.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "job-1")).body("{\"job\": 2}").dispatch().await;
+        assert_eq!(r.status(), Status::Conflict);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "err_idempotency_key_reused",
+            "message": "Idempotency key 'job-1' was already used with a different request!",
+            "key": "job-1"
+        }).to_string()));
+
+        // Keys are scoped by namespace.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha_copy").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "job-1")).body("{\"job\": 2}").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.headers().get_one("Idempotency-Replayed"), None);
+
+        // Multiple entries.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "job-2")).body("[{\"job\": 3}, {\"job\": 4}]").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        let ids = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_ids"].clone();
+        assert_eq!(ids.as_array().unwrap().len(), 2);
+
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "job-2")).body("[{\"job\": 3}, {\"job\": 4}]").dispatch().await;
+        assert_eq!(r.status(), Status::Ok);
+        assert_eq!(r.headers().get_one("Idempotency-Replayed"), Some("true"));
+        assert_eq!(from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_ids"], ids);
+
+        // Partial insert is a different request, even with the same body.
+        let r = client.post("/api/v1/entries?namespace=test_name_alpha&partial=true").header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "job-2")).body("[{\"job\": 3}, {\"job\": 4}]").dispatch().await;
+        assert_eq!(r.status(), Status::Conflict);
+
+        let r = client.get("/api/v1/entries?namespace=test_name_alpha&page=0").dispatch().await;
+        let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+        assert_eq!(body["data"].as_array().unwrap().len(), 3);
+    })
+}
@@ -0,0 +1,30 @@
+use crate::migrations::{self, MIGRATIONS};
+use rocket::local::asynchronous::Client;
+use crate::model::ApiDatabase;
+use super::rocket;
+
+
+#[test]
+fn test_versions_are_ordered() {
+    // Versions must be strictly increasing, otherwise bookkeeping breaks.
+    for pair in MIGRATIONS.windows(2) {
+        assert!(pair[0].version < pair[1].version, "Migration {} is out of order!", pair[1].name);
+    }
+}
+
+
+#[rocket::async_test]
+async fn test_migrations_applied_on_launch() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    let conn = ApiDatabase::get_one(client.rocket()).await
+        .expect("failed to get database connection for testing");
+
+    let (pending, applied) = conn.run(|c| (
+        migrations::pending(c).expect("Failed to check pending migrations..").len(),
+        // Running migrations again must be a no-op.
+        migrations::run(c).expect("Failed to run migrations..").len(),
+    )).await;
+
+    assert_eq!(pending, 0);
+    assert_eq!(applied, 0);
+}
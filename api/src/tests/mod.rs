@@ -1,12 +1,22 @@
-use super::rocket;
+use super::{rocket, rocket_custom};
 
 mod health;
+mod migrations;
+mod api_keys;
+mod schemas;
+mod namespaces;
+mod webhooks;
 
 mod get_entry_by_id;
 mod get_paginated_entries;
+mod export_entries;
+mod stream_entries;
+mod entry_changes;
+mod entry_revisions;
 mod create_one_entry;
 mod create_many_entries;
 mod update_entry_by_id;
+mod patch_entry_by_id;
 mod delete_entry_by_id;
 
 mod integration_tests;
@@ -0,0 +1,131 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::tokio;
+use serde_json::{from_str, Value};
+use crate::model::ApiDatabase;
+use std::time::Duration;
+use super::rocket;
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    // Test invalid metadata.
+    let (r1, r2, r3) = tokio::join!(
+        client.put("/api/v1/namespaces?namespace=test_name_beta").header(ContentType::JSON)
+            .body("{\"name\": \"other\"}").dispatch(),
+        client.put("/api/v1/namespaces?namespace=test_name_beta").header(ContentType::JSON)
+            .body("{\"owner\": \"\"}").dispatch(),
+        client.put("/api/v1/namespaces?namespace=test_name_beta").header(ContentType::JSON)
+            .body("{\"tags\": [\"ci\", 1]}").dispatch()
+    );
+
+    assert_eq!(r1.content_type(), Some(ContentType::JSON));
+    assert_eq!(r2.content_type(), Some(ContentType::JSON));
+    assert_eq!(r3.content_type(), Some(ContentType::JSON));
+
+    assert_eq!(r1.status(), Status::BadRequest);
+    assert_eq!(r2.status(), Status::BadRequest);
+    assert_eq!(r3.status(), Status::BadRequest);
+
+    let (s1, s2, s3) = tokio::join!(r1.into_string(), r2.into_string(), r3.into_string());
+
+    assert_eq!(s1, Some(json!({
+        "code": "err_namespace_metadata",
+        "message": "Unknown field 'name', expected any of: description, owner, tags!"
+    }).to_string()));
+
+    assert_eq!(s2, Some(json!({
+        "code": "err_namespace_metadata",
+        "message": "Owner must be a string of 1 to 64 characters!"
+    }).to_string()));
+
+    assert_eq!(s3, Some(json!({
+        "code": "err_namespace_metadata",
+        "message": "Tags must be strings of 1 to 64 characters!"
+    }).to_string()));
+}
+
+
+#[rocket::async_test]
+async fn test_bad_target() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    // Test missing, long and same target namespaces.
+    let (r1, r2, r3) = tokio::join!(
+        client.post("/api/v1/namespaces/rename?namespace=test_name_beta").dispatch(),
+        client.post(format!("/api/v1/namespaces/copy?namespace=test_name_beta&target={}", "a".repeat(65))).dispatch(),
+        client.post("/api/v1/namespaces/merge?namespace=test_name_beta&target=test_name_beta").dispatch()
+    );
+
+    assert_eq!(r1.content_type(), Some(ContentType::JSON));
+    assert_eq!(r2.content_type(), Some(ContentType::JSON));
+    assert_eq!(r3.content_type(), Some(ContentType::JSON));
+
+    assert_eq!(r1.status(), Status::BadRequest);
+    assert_eq!(r2.status(), Status::BadRequest);
+    assert_eq!(r3.status(), Status::BadRequest);
+
+    let (s1, s2, s3) = tokio::join!(r1.into_string(), r2.into_string(), r3.into_string());
+
+    assert_eq!(s1, Some(json!({
+        "code": "err_namespace_empty",
+        "message": "You must provide 'X-Target-Namespace' header or 'target' URL argument with request!"
+    }).to_string()));
+
+    assert_eq!(s2, Some(json!({
+        "code": "err_namespace_long",
+        "message": "Provided namespace value is too big (max is 64 characters, received 65)!",
+        "namespace": "a".repeat(65)
+    }).to_string()));
+
+    assert_eq!(s3, Some(json!({
+        "code": "err_namespace_same",
+        "message": "Source and target namespaces must be different!",
+        "namespace": "test_name_beta"
+    }).to_string()));
+}
+
+
+/// Following test verifies the story below:
+///     - Create entry and start writing it (row is locked first, then the namespace, as any
+///       write through the API does), slowly
+///     - Rename its namespace while the write is in progress
+///     - Verify that rename waits for the write instead of deadlocking with it
+#[rocket::async_test]
+async fn test_rename_during_write() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_name_writing").dispatch().await;
+    client.delete("/api/v1/entries?namespace=test_name_written").dispatch().await;
+
+    let r = client.post("/api/v1/entries?namespace=test_name_writing").header(ContentType::JSON)
+        .body("{\"n\": 1}").dispatch().await;
+    let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_i64().unwrap();
+
+    let conn = ApiDatabase::get_one(client.rocket()).await.expect("failed to get database connection for testing");
+    let write = conn.run(move |c| {
+        let mut tx = c.transaction()?;
+        tx.execute("SELECT id FROM entries WHERE id = $1 FOR UPDATE", &[&id])?;
+        // Give the rename time to start and wait for the row.
+        std::thread::sleep(Duration::from_millis(500));
+        tx.execute("UPDATE entries SET content = '{\"n\": 2}' WHERE id = $1", &[&id])?;
+        tx.commit()
+    });
+    let rename = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.post("/api/v1/namespaces/rename?namespace=test_name_writing&target=test_name_written").dispatch().await
+    };
+    let (write, r) = tokio::join!(write, rename);
+
+    write.expect("write must not be aborted by a deadlock");
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["moved"], 1);
+
+    let r = client.get(format!("/api/v1/entries/{}?namespace=test_name_written", id)).dispatch().await;
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["data"]["content"]["n"], 2);
+
+    client.delete("/api/v1/entries?namespace=test_name_written").dispatch().await;
+}
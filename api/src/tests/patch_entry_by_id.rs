@@ -0,0 +1,60 @@
+use rocket::http::{ContentType, Status, Header};
+use rocket::local::asynchronous::Client;
+use rocket::tokio;
+use super::rocket;
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    let json_patch = ContentType::new("application", "json-patch+json");
+
+    {
+        // Test unsupported content type.
+        let r = client.patch("/api/v1/entries/1")
+            .header(Header::new("X-Namespace", "a"))
+            .header(ContentType::JSON).body("{}").dispatch().await;
+
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        assert_eq!(r.status(), Status::UnsupportedMediaType);
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "err_patch_content_type",
+            "message": "Patch must be sent with 'application/merge-patch+json' or 'application/json-patch+json' content type!"
+        }).to_string()));
+    }
+
+    {
+        // Test malformed JSON Patch documents.
+        let (r1, r2, r3) = tokio::join!(
+            client.patch("/api/v1/entries/1").header(Header::new("X-Namespace", "a"))
+                .header(json_patch.clone()).body("{\"op\": \"add\"}").dispatch(),
+            client.patch("/api/v1/entries/1").header(Header::new("X-Namespace", "a"))
+                .header(json_patch.clone()).body("[{\"op\": \"append\", \"path\": \"/a\"}]").dispatch(),
+            client.patch("/api/v1/entries/1").header(Header::new("X-Namespace", "a"))
+                .header(json_patch.clone()).body("[{\"op\": \"remove\", \"path\": \"/a\"}, {\"op\": \"remove\", \"path\": \"a/~2\"}]").dispatch()
+        );
+
+        assert_eq!(r1.status(), Status::BadRequest);
+        assert_eq!(r2.status(), Status::BadRequest);
+        assert_eq!(r3.status(), Status::BadRequest);
+
+        let (s1, s2, s3) = tokio::join!(r1.into_string(), r2.into_string(), r3.into_string());
+
+        assert_eq!(s1, Some(json!({
+            "code": "err_patch_parse",
+            "message": "JSON Patch must be an array of operations!"
+        }).to_string()));
+
+        assert_eq!(s2, Some(json!({
+            "code": "err_patch_parse",
+            "message": "Operation 0 of the patch is malformed: unknown operation 'append', expected one of: add, remove, replace, move, copy, test!",
+            "index": 0
+        }).to_string()));
+
+        assert_eq!(s3, Some(json!({
+            "code": "err_patch_parse",
+            "message": "Operation 1 of the patch is malformed: 'a/~2' is not a valid JSON pointer!",
+            "index": 1
+        }).to_string()));
+    }
+}
@@ -0,0 +1,65 @@
+use rocket::http::{ContentType, Status, Header};
+use rocket::local::asynchronous::Client;
+use rocket::tokio;
+use super::rocket;
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    {
+        // Test invalid and unsupported schemas.
+        let (r1, r2) = tokio::join!(
+            client.put("/api/v1/schemas").header(Header::new("X-Namespace", "test_name_beta"))
+                .header(ContentType::JSON).body("{\"type\": \"text\", \"properties\": {\"a\": 1}}").dispatch(),
+            client.put("/api/v1/schemas").header(Header::new("X-Namespace", "test_name_beta"))
+                .header(ContentType::JSON).body("{\"$ref\": \"#/definitions/a\"}").dispatch()
+        );
+
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+
+        assert_eq!(r1.status(), Status::BadRequest);
+        assert_eq!(r2.status(), Status::BadRequest);
+
+        let (s1, s2) = rocket::tokio::join!(r1.into_string(), r2.into_string());
+
+        assert_eq!(s1, Some(json!({
+            "code": "err_schema_invalid",
+            "message": "Provided JSON Schema is invalid or uses unsupported keywords! Only a subset of JSON Schema \
+                is supported, keywords: type, enum, const, properties, required, additionalProperties, items, minItems, \
+                maxItems, uniqueItems, minLength, maxLength, minimum, maximum, exclusiveMinimum, exclusiveMaximum, allOf, \
+                anyOf, oneOf, not (and annotations: $schema, $id, $comment, title, description, default, examples).",
+            "errors": [
+                {"pointer": "/properties/a", "message": "Schema must be an object or a boolean!"},
+                {"pointer": "/type", "message": "Type must be one of (or a list of): null, boolean, object, array, number, integer, string!"}
+            ]
+        }).to_string()));
+
+        assert_eq!(s2, Some(json!({
+            "code": "err_schema_invalid",
+            "message": "Provided JSON Schema is invalid or uses unsupported keywords! Only a subset of JSON Schema \
+                is supported, keywords: type, enum, const, properties, required, additionalProperties, items, minItems, \
+                maxItems, uniqueItems, minLength, maxLength, minimum, maximum, exclusiveMinimum, exclusiveMaximum, allOf, \
+                anyOf, oneOf, not (and annotations: $schema, $id, $comment, title, description, default, examples).",
+            "errors": [
+                {"pointer": "/$ref", "message": "Keyword '$ref' is not supported!"}
+            ]
+        }).to_string()));
+    }
+
+    {
+        // Test namespace without schema.
+        let r = client.post("/api/v1/schemas/validate").header(Header::new("X-Namespace", "test_name_beta"))
+            .header(ContentType::JSON).body("{}").dispatch().await;
+
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+        assert_eq!(r.status(), Status::NotFound);
+        assert_eq!(r.into_string().await, Some(json!({
+            "code": "err_schema_not_found",
+            "message": "Namespace 'test_name_beta' has no JSON Schema!",
+            "namespace": "test_name_beta"
+        }).to_string()));
+    }
+}
@@ -0,0 +1,147 @@
+use rocket::http::{ContentType, Status, Header};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::tokio::io::AsyncReadExt;
+use rocket::tokio::time::{timeout, Duration};
+use crate::model::ApiDatabase;
+use serde_json::{from_str, Value};
+use rocket::tokio;
+use super::rocket;
+
+
+// Reads events from the stream until there are `count` of them, and returns their names, IDs and data.
+async fn read_events(r: &mut LocalResponse<'_>, count: usize) -> Vec<(String, String, Value)> {
+    let mut raw = String::new();
+    let mut buffer = [0u8; 4096];
+    let mut events = vec![];
+
+    while events.len() < count {
+        let n = timeout(Duration::from_secs(5), r.read(&mut buffer)).await
+            .expect("event wasn't received in time").unwrap();
+        assert!(n > 0, "stream ended");
+        raw.push_str(std::str::from_utf8(&buffer[..n]).unwrap());
+
+        // Events are separated by an empty line, the last one may be incomplete.
+        while let Some(end) = raw.find("\n\n") {
+            let block = raw[..end].to_string();
+            raw.drain(..end + 2);
+
+            let field = |name: &str| block.lines()
+                .find_map(|line| line.strip_prefix(name).map(|value| value.trim_start().to_string()));
+            // Heartbeats are comments without data.
+            if let Some(data) = field("data:") {
+                events.push((field("event:").unwrap_or_default(), field("id:").unwrap_or_default(), from_str(&data).unwrap()));
+            }
+        }
+    }
+    events
+}
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    let (r1, r2) = tokio::join!(
+        client.get("/api/v1/entries/stream?namespace=a").header(Header::new("Last-Event-ID", "first")).dispatch(),
+        client.get("/api/v1/entries/stream?namespace=a&filter=[]").dispatch(),
+    );
+
+    assert_eq!(r1.status(), Status::BadRequest);
+    assert_eq!(r2.status(), Status::BadRequest);
+    assert_eq!(r1.content_type(), Some(ContentType::JSON));
+    assert_eq!(r2.content_type(), Some(ContentType::JSON));
+
+    assert_eq!(r1.into_string().await, Some(json!({
+        "code": "err_last_event_id",
+        "message": "Couldn't parse Last-Event-ID with error: 'invalid digit found in string'!"
+    }).to_string()));
+}
+
+
+#[rocket::async_test]
+async fn test_stream() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_stream_alpha").dispatch().await;
+
+    let mut all = client.get("/api/v1/entries/stream?namespace=test_stream_alpha").dispatch().await;
+    let mut failed = client.get("/api/v1/entries/stream?namespace=test_stream_alpha")
+        .header(Header::new("X-Filter", "{\"status\": {\"eq\": \"failed\"}}")).dispatch().await;
+    assert_eq!(all.status(), Status::Ok);
+    assert_eq!(all.content_type(), Some(ContentType::EventStream));
+
+    // Writes to other namespaces are not sent.
+    client.post("/api/v1/entries?namespace=test_stream_beta").header(ContentType::JSON)
+        .body("{\"status\": \"failed\"}").dispatch().await;
+
+    let r = client.post("/api/v1/entries?namespace=test_stream_alpha").header(ContentType::JSON)
+        .body("[{\"status\": \"ok\"}, {\"status\": \"failed\"}]").dispatch().await;
+    let ids = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_ids"].clone();
+    let (ok_id, failed_id) = (ids[0].as_u64().unwrap(), ids[1].as_u64().unwrap());
+
+    client.patch(format!("/api/v1/entries/{}?namespace=test_stream_alpha", ok_id))
+        .header(ContentType::new("application", "merge-patch+json")).body("{\"status\": \"failed\"}").dispatch().await;
+    // Writes made directly in SQL are streamed as well.
+    let conn = ApiDatabase::get_one(client.rocket()).await.unwrap();
+    let sql_id = conn.run(|c| c.query_one(
+        "INSERT INTO entries (namespace, content) VALUES ('test_stream_alpha', '{\"status\": \"sql\"}') RETURNING id", &[]
+    )).await.unwrap().get::<_, i64>("id") as u64;
+    client.delete("/api/v1/entries?namespace=test_stream_alpha").dispatch().await;
+
+    let events = read_events(&mut all, 7).await;
+    let summary = events.iter().map(|(name, _, data)| (name.as_str(), data["id"].as_u64().unwrap())).collect::<Vec<_>>();
+    assert_eq!(summary[..4], [("created", ok_id), ("created", failed_id), ("updated", ok_id), ("created", sql_id)]);
+    // Entries are deleted by a single statement, in no particular order.
+    let mut deleted = summary[4..].to_vec();
+    deleted.sort_unstable();
+    assert_eq!(deleted, vec![("deleted", ok_id), ("deleted", failed_id), ("deleted", sql_id)]);
+    assert_eq!(events[0].2, json!({ "namespace": "test_stream_alpha", "kind": "created", "id": ok_id, "content": { "status": "ok" } }));
+    assert_eq!(events[3].2["content"], json!({ "status": "sql" }));
+    assert_eq!(events[4].2.get("content"), None);
+
+    // Created entry which is "ok" doesn't match the filter, but it does after update.
+    let filtered = read_events(&mut failed, 5).await;
+    let summary = filtered.iter().map(|(name, _, data)| (name.as_str(), data["id"].as_u64().unwrap())).collect::<Vec<_>>();
+    assert_eq!(summary[..2], [("created", failed_id), ("updated", ok_id)]);
+    assert!(summary[2..].iter().all(|(name, _)| *name == "deleted"));
+
+    // Reconnecting client gets events after the last one it has seen.
+    let mut resumed = client.get("/api/v1/entries/stream?namespace=test_stream_alpha")
+        .header(Header::new("Last-Event-ID", events[2].1.clone())).dispatch().await;
+    let missed = read_events(&mut resumed, 4).await;
+    assert_eq!(missed.iter().map(|(_, id, _)| id).collect::<Vec<_>>(), events[3..].iter().map(|(_, id, _)| id).collect::<Vec<_>>());
+
+    // Unknown ID means that client should reload everything.
+    let mut reset = client.get("/api/v1/entries/stream?namespace=test_stream_alpha&last_event_id=1000000").dispatch().await;
+    let events = read_events(&mut reset, 1).await;
+    assert_eq!(events[0].0, "reset");
+    assert_eq!(events[0].2["code"], "info_stream_reset");
+
+    client.delete("/api/v1/entries?namespace=test_stream_beta").dispatch().await;
+}
+
+
+#[rocket::async_test]
+async fn test_bulk() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_stream_gamma").dispatch().await;
+
+    let mut stream = client.get("/api/v1/entries/stream?namespace=test_stream_gamma").dispatch().await;
+
+    // Many created entries are still announced one by one, while namespace-wide operations which
+    // change many entries are announced as a single reset of the namespace.
+    let body = Value::Array((0..150).map(|n| json!({ "n": n }).0).collect()).to_string();
+    client.post("/api/v1/entries?namespace=test_stream_gamma").header(ContentType::JSON).body(body).dispatch().await;
+    client.delete("/api/v1/entries?namespace=test_stream_gamma").dispatch().await;
+
+    let events = read_events(&mut stream, 151).await;
+    let mut expected = vec!["created"; 150];
+    expected.push("reset");
+    assert_eq!(events.iter().map(|(name, _, _)| name.as_str()).collect::<Vec<_>>(), expected);
+    assert_eq!(events[0].2["content"], json!({ "n": 0 }).0);
+    assert_eq!(events[149].2["content"], json!({ "n": 149 }).0);
+    assert_eq!(events[150].2, json!({
+        "code": "info_stream_reset",
+        "message": "Many entries of the namespace were changed at once, data should be reloaded!",
+        "namespace": "test_stream_gamma"
+    }).0);
+}
@@ -1,5 +1,6 @@
 use rocket::http::{ContentType, Status, Header};
 use rocket::local::asynchronous::Client;
+use serde_json::{from_str, Value};
 use rocket::tokio;
 use super::rocket;
 
@@ -29,11 +30,11 @@ async fn test_bad() {
                 .header(ContentType::JSON).dispatch()
         );
 
-        assert_eq!(r1.content_type(), Some(ContentType::HTML));
-        assert_eq!(r2.content_type(), Some(ContentType::HTML));
-        assert_eq!(r3.content_type(), Some(ContentType::HTML));
-        assert_eq!(r4.content_type(), Some(ContentType::HTML));
-        assert_eq!(r5.content_type(), Some(ContentType::HTML));
+        assert_eq!(r1.content_type(), Some(ContentType::JSON));
+        assert_eq!(r2.content_type(), Some(ContentType::JSON));
+        assert_eq!(r3.content_type(), Some(ContentType::JSON));
+        assert_eq!(r4.content_type(), Some(ContentType::JSON));
+        assert_eq!(r5.content_type(), Some(ContentType::JSON));
 
         assert_eq!(r1.status(), Status::NotFound);
         assert_eq!(r2.status(), Status::NotFound);
@@ -43,3 +44,60 @@ async fn test_bad() {
     }
 }
 
+
+/// Regression test: PUT with another namespace used to silently move the entry into it.
+#[rocket::async_test]
+async fn test_namespace_conflict() {
+    let client = Client::tracked(rocket()).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_put_owner").dispatch().await;
+    client.delete("/api/v1/entries?namespace=test_put_intruder").dispatch().await;
+
+    let r = client.post("/api/v1/entries?namespace=test_put_owner").header(ContentType::JSON)
+        .body("{\"owner\": true}").dispatch().await;
+    let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+
+    let r = client.put(format!("/api/v1/entries/{}?namespace=test_put_intruder", id)).header(ContentType::JSON)
+        .body("{\"owner\": false}").dispatch().await;
+
+    // We expect 409 JSON response.
+    assert_eq!(r.status(), Status::Conflict);
+    assert_eq!(r.content_type(), Some(ContentType::JSON));
+    assert_eq!(r.into_string().await, Some(json!({
+        "code": "err_entry_namespace_conflict",
+        "message": format!("Entry with ID '{}' belongs to another namespace, it must be moved explicitly!", id),
+        "namespace": "test_put_intruder",
+        "id": id
+    }).to_string()));
+
+    // Entry is neither changed nor moved.
+    let r = client.get(format!("/api/v1/entries/{}?namespace=test_put_owner", id)).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["data"]["content"]["owner"], true);
+
+    // Replacing it within its own namespace still works.
+    let r = client.put(format!("/api/v1/entries/{}?namespace=test_put_owner", id)).header(ContentType::JSON)
+        .body("{\"owner\": 1}").dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+
+    // Explicit move is allowed and recorded.
+    let r = client.post(format!("/api/v1/entries/{}/move?namespace=test_put_owner&target=test_put_intruder", id)).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "info_move_item_ok");
+    assert_eq!(body["data"]["content"]["owner"], 1);
+
+    let r = client.get(format!("/api/v1/entries/{}?namespace=test_put_owner", id)).dispatch().await;
+    assert_eq!(r.status(), Status::NotFound);
+
+    let r = client.get(format!("/api/v1/entries/{}/moves?namespace=test_put_intruder", id)).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    let moves = body["data"].as_array().unwrap();
+    assert_eq!(moves.len(), 1);
+    assert_eq!(moves[0]["from"], "test_put_owner");
+    assert_eq!(moves[0]["to"], "test_put_intruder");
+    assert_eq!(moves[0]["moved_by"], Value::Null);
+
+    client.delete("/api/v1/entries?namespace=test_put_intruder").dispatch().await;
+}
@@ -0,0 +1,298 @@
+use rocket::http::{ContentType, Status, Header};
+use rocket::local::asynchronous::Client;
+use rocket::tokio::time::{sleep, Duration};
+use crate::model::ApiDatabase;
+use crate::webhook::{sign, PublicResolver};
+use hyper::client::connect::dns::Name;
+use hyper::service::Service;
+use std::str::FromStr;
+use serde_json::{from_str, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use rocket::tokio;
+use super::{rocket, rocket_custom};
+
+
+// Request received by the stub receiver: lowercase header names mapped to values, and the body.
+type Received = (HashMap<String, String>, String);
+
+
+// Starts HTTP server which responds with the statuses in order (the last one is repeated), and
+// sends received requests to the channel. Returns url of the server.
+fn receiver(statuses: Vec<u16>) -> (String, mpsc::Receiver<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for (i, stream) in listener.incoming().enumerate() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut headers = HashMap::new();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(':') {
+                    Some((name, value)) => headers.insert(name.to_lowercase(), value.trim().to_string()),
+                    None => break,
+                };
+            }
+            let length = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let status = statuses[i.min(statuses.len() - 1)];
+            write!(stream, "HTTP/1.1 {} Stub\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope", status).unwrap();
+            if sender.send((headers, String::from_utf8(body).unwrap())).is_err() {
+                return;
+            }
+        }
+    });
+
+    (url, receiver)
+}
+
+
+// Stub receivers listen on loopback, so private addresses must be allowed.
+async fn client() -> Client {
+    let figment = rocket::Config::figment().merge(("webhook_allow_private", true));
+    Client::tracked(rocket_custom(figment)).await.unwrap()
+}
+
+
+// Waits for the next request to the receiver, while dispatcher polls the queue.
+async fn next_request(receiver: &mpsc::Receiver<Received>) -> Received {
+    for _ in 0..100 {
+        if let Ok(request) = receiver.try_recv() {
+            return request;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("webhook wasn't delivered in time");
+}
+
+
+#[rocket::async_test]
+async fn test_bad() {
+    let client = Client::tracked(rocket()).await.unwrap();
+
+    let (r1, r2, r3, r4) = tokio::join!(
+        client.post("/api/v1/webhooks?namespace=test_webhook_beta").header(ContentType::JSON)
+            .body("{\"url\": \"ftp://example.com\"}").dispatch(),
+        client.post("/api/v1/webhooks?namespace=test_webhook_beta").header(ContentType::JSON)
+            .body("{\"url\": \"https://example.com\", \"events\": [\"moved\"]}").dispatch(),
+        client.post("/api/v1/webhooks?namespace=test_webhook_beta").header(ContentType::JSON)
+            .body("{\"url\": \"https://example.com\", \"secret\": \"short\"}").dispatch(),
+        client.post("/api/v1/webhooks?namespace=test_webhook_beta").header(ContentType::JSON)
+            .body("{\"url\": \"https://example.com\", \"filter\": []}").dispatch(),
+    );
+
+    for r in [&r1, &r2, &r3, &r4] {
+        assert_eq!(r.status(), Status::BadRequest);
+        assert_eq!(r.content_type(), Some(ContentType::JSON));
+    }
+
+    let (s1, s2, s3, s4) = tokio::join!(r1.into_string(), r2.into_string(), r3.into_string(), r4.into_string());
+    assert_eq!(s1, Some(json!({
+        "code": "err_webhook_url",
+        "message": "Webhook must have a 'url' which is an absolute http or https URL!"
+    }).to_string()));
+    assert_eq!(s2, Some(json!({
+        "code": "err_webhook_events",
+        "message": "Webhook 'events' must be a non-empty list of: created, updated, deleted!"
+    }).to_string()));
+    assert_eq!(s3, Some(json!({
+        "code": "err_webhook_secret",
+        "message": "Webhook 'secret' must be a string of at least 16 characters!"
+    }).to_string()));
+    assert_eq!(from_str::<Value>(&s4.unwrap()).unwrap()["code"], "err_filter_parse");
+
+    // Internal addresses are refused by default.
+    let (r1, r2) = tokio::join!(
+        client.post("/api/v1/webhooks?namespace=test_webhook_beta").header(ContentType::JSON)
+            .body("{\"url\": \"http://169.254.169.254/latest/meta-data\"}").dispatch(),
+        client.post("/api/v1/webhooks?namespace=test_webhook_beta").header(ContentType::JSON)
+            .body("{\"url\": \"http://[::1]:8080/hook\"}").dispatch(),
+    );
+    assert_eq!(r1.status(), Status::BadRequest);
+    assert_eq!(r2.status(), Status::BadRequest);
+    assert_eq!(r1.into_string().await, Some(json!({
+        "code": "err_webhook_address",
+        "message": "Webhook url must point to a public address! Host '169.254.169.254' resolves to non-public address '169.254.169.254'!"
+    }).to_string()));
+    assert_eq!(from_str::<Value>(&r2.into_string().await.unwrap()).unwrap()["code"], "err_webhook_address");
+
+    let (r1, r2) = tokio::join!(
+        client.delete("/api/v1/webhooks/1000000?namespace=test_webhook_beta").dispatch(),
+        client.get("/api/v1/webhooks/1000000/deliveries?namespace=test_webhook_beta&status=lost").dispatch(),
+    );
+
+    assert_eq!(r1.status(), Status::NotFound);
+    assert_eq!(r2.status(), Status::BadRequest);
+    assert_eq!(r1.into_string().await, Some(json!({
+        "code": "err_webhook_not_found",
+        "message": "Webhook with ID '1000000' does not exist!",
+        "namespace": "test_webhook_beta",
+        "id": 1000000
+    }).to_string()));
+    assert_eq!(r2.into_string().await, Some(json!({
+        "code": "err_webhook_delivery_status",
+        "message": "Unknown delivery status 'lost', expected one of: pending, delivered, dead!",
+        "namespace": "test_webhook_beta"
+    }).to_string()));
+}
+
+
+#[rocket::async_test]
+async fn test_deliveries() {
+    let client = client().await;
+    let conn = ApiDatabase::get_one(client.rocket()).await.unwrap();
+    conn.run(|c| c.execute("DELETE FROM webhooks WHERE namespace = 'test_webhook_alpha'", &[])).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_webhook_alpha").dispatch().await;
+
+    // Receiver fails once, then accepts everything.
+    let (url, requests) = receiver(vec![500, 204]);
+    let secret = "test_webhook_secret_0123456789";
+    let r = client.post("/api/v1/webhooks?namespace=test_webhook_alpha").header(ContentType::JSON)
+        .body(json!({ "url": url, "secret": secret, "events": ["created"], "filter": {"status": {"eq": "failed"}} }).to_string())
+        .dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "info_webhook_created");
+    assert_eq!(body["secret"], secret);
+    assert_eq!(body["data"]["events"], json!(["created"]));
+    let webhook_id = body["data"]["id"].as_u64().unwrap();
+
+    let r = client.get("/api/v1/webhooks?namespace=test_webhook_alpha").dispatch().await;
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    assert_eq!(body["data"][0].get("secret"), None);
+
+    // Entry which doesn't match the filter and the update are not sent.
+    client.post("/api/v1/entries?namespace=test_webhook_alpha").header(ContentType::JSON)
+        .body("{\"status\": \"ok\"}").dispatch().await;
+    let r = client.post("/api/v1/entries?namespace=test_webhook_alpha").header(ContentType::JSON)
+        .body("{\"status\": \"failed\"}").dispatch().await;
+    let id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["item_id"].as_u64().unwrap();
+    client.put(format!("/api/v1/entries/{}?namespace=test_webhook_alpha", id)).header(ContentType::JSON)
+        .body("{\"status\": \"failed\", \"retry\": true}").dispatch().await;
+
+    let (headers, body) = next_request(&requests).await;
+    let payload = from_str::<Value>(&body).unwrap();
+    assert_eq!(payload["webhook_id"], webhook_id);
+    assert_eq!(payload["attempt"], 1);
+    assert_eq!(payload["event"], json!({
+        "kind": "created", "namespace": "test_webhook_alpha", "id": id, "content": {"status": "failed"}
+    }));
+    assert_eq!(headers["x-voyeur-event"], "created");
+    assert_eq!(headers["x-voyeur-delivery"], payload["delivery_id"].to_string());
+    let timestamp = headers["x-voyeur-timestamp"].parse::<i64>().unwrap();
+    assert_eq!(headers["x-voyeur-signature"], format!("sha256={}", sign(secret, timestamp, &body)));
+
+    // Failed attempt is scheduled for later, here we make it due right away.
+    sleep(Duration::from_millis(500)).await;
+    let r = client.get(format!("/api/v1/webhooks/{}/deliveries?namespace=test_webhook_alpha", webhook_id)).dispatch().await;
+    let log = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(log["data"].as_array().unwrap().len(), 1);
+    assert_eq!(log["data"][0]["status"], "pending");
+    assert_eq!(log["data"][0]["attempts"], 1);
+    assert_eq!(log["data"][0]["last_status"], 500);
+    assert_eq!(log["data"][0]["last_error"], "Receiver responded with status 500 (Internal Server Error)!");
+    conn.run(|c| c.execute("UPDATE webhook_deliveries SET next_attempt_at = now() WHERE status = 'pending'", &[])).await.unwrap();
+
+    let (_, body) = next_request(&requests).await;
+    let retry = from_str::<Value>(&body).unwrap();
+    assert_eq!(retry["delivery_id"], payload["delivery_id"]);
+    assert_eq!(retry["attempt"], 2);
+
+    sleep(Duration::from_millis(500)).await;
+    let r = client.get(format!("/api/v1/webhooks/{}/deliveries?namespace=test_webhook_alpha&status=delivered", webhook_id))
+        .dispatch().await;
+    let log = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(log["data"].as_array().unwrap().len(), 1);
+    assert_eq!(log["data"][0]["attempts"], 2);
+    assert_eq!(log["data"][0]["last_status"], 204);
+    assert!(log["data"][0]["delivered_at"].is_string());
+
+    // Webhook of another namespace can't be seen or deleted.
+    let r = client.delete(format!("/api/v1/webhooks/{}?namespace=test_webhook_beta", webhook_id)).dispatch().await;
+    assert_eq!(r.status(), Status::NotFound);
+
+    let r = client.delete(format!("/api/v1/webhooks/{}?namespace=test_webhook_alpha", webhook_id)).dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let left = conn.run(move |c| c.query_one(
+        "SELECT count(*) FROM webhook_deliveries WHERE webhook_id = $1", &[&(webhook_id as i64)]
+    )).await.unwrap().get::<_, i64>(0);
+    assert_eq!(left, 0);
+
+    client.delete("/api/v1/entries?namespace=test_webhook_alpha").dispatch().await;
+}
+
+
+/// Following test verifies the story below:
+///     - Create webhook and an entry in the namespace
+///     - Rename the namespace and verify that webhook is moved without deliveries of "deletions"
+///     - Merge the namespace and verify that webhook is deleted
+#[rocket::async_test]
+async fn test_rename() {
+    let client = client().await;
+    let conn = ApiDatabase::get_one(client.rocket()).await.unwrap();
+    conn.run(|c| c.execute("DELETE FROM webhooks WHERE namespace IN ('test_webhook_gamma', 'test_webhook_delta', 'test_webhook_zeta')", &[])).await.unwrap();
+    for namespace in &["test_webhook_gamma", "test_webhook_delta", "test_webhook_epsilon"] {
+        client.delete(format!("/api/v1/entries?namespace={}", namespace)).dispatch().await;
+    }
+
+    let (url, _requests) = receiver(vec![204]);
+    let r = client.post("/api/v1/webhooks?namespace=test_webhook_gamma").header(ContentType::JSON)
+        .body(json!({ "url": url, "events": ["created", "deleted"] }).to_string()).dispatch().await;
+    let webhook_id = from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["data"]["id"].as_u64().unwrap();
+    client.post("/api/v1/entries?namespace=test_webhook_gamma").header(ContentType::JSON).body("{}").dispatch().await;
+
+    let r = client.post("/api/v1/namespaces/rename?namespace=test_webhook_gamma&target=test_webhook_delta").dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let r = client.get("/api/v1/webhooks?namespace=test_webhook_delta").dispatch().await;
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["data"][0]["id"], webhook_id);
+    let r = client.get(format!("/api/v1/webhooks/{}/deliveries?namespace=test_webhook_delta", webhook_id)).dispatch().await;
+    let log = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(log["data"].as_array().unwrap().len(), 1);
+    assert_eq!(log["data"][0]["event"]["kind"], "created");
+
+    let r = client.post("/api/v1/namespaces/merge?namespace=test_webhook_delta&target=test_webhook_epsilon").dispatch().await;
+    assert_eq!(r.status(), Status::Ok);
+    let r = client.get("/api/v1/webhooks?namespace=test_webhook_delta").dispatch().await;
+    assert_eq!(from_str::<Value>(&r.into_string().await.unwrap()).unwrap()["data"], json!([]));
+
+    // Namespace which only has webhooks exists too, so it can't be the target of a rename.
+    client.post("/api/v1/webhooks?namespace=test_webhook_zeta").header(ContentType::JSON)
+        .body(json!({ "url": url }).to_string()).dispatch().await;
+    let r = client.post("/api/v1/namespaces/rename?namespace=test_webhook_epsilon&target=test_webhook_zeta").dispatch().await;
+    assert_eq!(r.status(), Status::Conflict);
+    let body = from_str::<Value>(&r.into_string().await.unwrap()).unwrap();
+    assert_eq!(body["code"], "err_namespace_conflict");
+
+    conn.run(|c| c.execute("DELETE FROM webhooks WHERE namespace = 'test_webhook_zeta'", &[])).await.unwrap();
+    client.delete("/api/v1/entries?namespace=test_webhook_epsilon").dispatch().await;
+}
+
+
+#[rocket::async_test]
+async fn test_resolver() {
+    // Addresses are checked when the connection is made, not only when webhook is created.
+    let mut resolver = PublicResolver { allow_private: false };
+    let e = resolver.call(Name::from_str("localhost").unwrap()).await.unwrap_err();
+    assert!(e.to_string().starts_with("Host 'localhost' resolves to non-public address"), "{}", e);
+
+    let mut resolver = PublicResolver { allow_private: true };
+    let addresses = resolver.call(Name::from_str("localhost").unwrap()).await.unwrap().collect::<Vec<_>>();
+    assert!(addresses.iter().all(|address| address.ip().is_loopback()));
+}
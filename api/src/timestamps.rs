@@ -0,0 +1,86 @@
+use rocket::request::{Outcome, Request, FromRequest};
+use time::{OffsetDateTime, UtcOffset, Format};
+use crate::errors::ErrorMessage;
+use rocket::http::Status;
+
+
+/// Formats timestamp as RFC 3339 string in UTC. We keep microseconds (same precision as
+/// Postgres uses), so timestamps from responses can be used as exact bounds of time range.
+pub fn format_timestamp(timestamp: OffsetDateTime) -> String {
+    let timestamp = timestamp.to_offset(UtcOffset::UTC);
+    format!("{}.{:06}Z", timestamp.format("%Y-%m-%dT%H:%M:%S"), timestamp.microsecond())
+}
+
+
+/// Value which allows to access time range of the listing request. Values are provided as
+/// RFC 3339 timestamps, lower bounds are inclusive and upper bounds are exclusive. Values
+/// 'since' and 'until' limit creation time of the entry, while 'updated_since' and
+/// 'updated_until' limit time of its last update. Value 'as_of' selects entries as they were at
+/// that time (from their revisions), instead of the current ones.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeRange {
+    pub since:         Option<OffsetDateTime>,
+    pub until:         Option<OffsetDateTime>,
+    pub updated_since: Option<OffsetDateTime>,
+    pub updated_until: Option<OffsetDateTime>,
+    pub as_of:         Option<OffsetDateTime>,
+}
+
+
+impl TimeRange {
+    // Reads single bound from header (e.g. "X-Since") or url argument (e.g. "since").
+    fn bound(req: &Request<'_>, header: &str, arg: &str) -> Result<Option<OffsetDateTime>, ()> {
+        let raw = match req.headers().get_one(header) {
+            Some(value) => value.to_string(),
+            None => match req.query_value::<String>(arg) {
+                Some(Ok(value)) => value,
+                Some(Err(e)) => {
+                    // Store error message.
+                    req.local_cache(|| ErrorMessage(Some(json!({
+                        "code":    "err_timestamp_parse",
+                        "message": format!("Couldn't parse '{}' from url argument with error: '{}'!", arg, e)
+                    }))));
+                    return Err(());
+                },
+                None => return Ok(None),
+            }
+        };
+
+        match OffsetDateTime::parse(&raw, Format::Rfc3339) {
+            Ok(timestamp) => Ok(Some(timestamp)),
+            Err(e) => {
+                // Store error message.
+                req.local_cache(|| ErrorMessage(Some(json!({
+                    "code":    "err_timestamp_parse",
+                    "message": format!("Couldn't parse '{}' as RFC 3339 timestamp with error: '{}'!", arg, e),
+                    "value":   raw,
+                }))));
+                Err(())
+            }
+        }
+    }
+}
+
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TimeRange {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        let range = (|| -> Result<TimeRange, ()> {
+            Ok(TimeRange {
+                since:         TimeRange::bound(req, "X-Since", "since")?,
+                until:         TimeRange::bound(req, "X-Until", "until")?,
+                updated_since: TimeRange::bound(req, "X-Updated-Since", "updated_since")?,
+                updated_until: TimeRange::bound(req, "X-Updated-Until", "updated_until")?,
+                as_of:         TimeRange::bound(req, "X-As-Of", "as_of")?,
+            })
+        })();
+
+        match range {
+            Ok(range) => Outcome::Success(range),
+            // Error message was stored already, forward to error catcher.
+            Err(()) => Outcome::Failure((Status::BadRequest, ())),
+        }
+    }
+}
@@ -0,0 +1,583 @@
+use rocket_contrib::databases::postgres;
+use rocket_contrib::json::JsonValue;
+use rocket::request::{Outcome, Request, FromRequest};
+use rocket::tokio::net::lookup_host;
+use rocket::tokio::runtime;
+use rocket::tokio::time::timeout;
+use rocket::futures::future::join_all;
+use hyper::client::HttpConnector;
+use hyper::client::connect::dns::Name;
+use hyper_rustls::HttpsConnector;
+use crate::timestamps::format_timestamp;
+use crate::events::ChangeKind;
+use crate::errors::ApiError;
+use crate::filter::Filter;
+use rocket::fairing::AdHoc;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use sha2::Sha256;
+use hmac::{Hmac, Mac, NewMac};
+use time::OffsetDateTime;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::task::{Context, Poll};
+use std::sync::{Arc, Weak};
+use std::future::Future;
+use std::time::Duration;
+use std::pin::Pin;
+use std::thread;
+use std::io;
+
+
+// Amount of deliveries which are claimed and sent at once.
+const DISPATCH_BATCH_SIZE: i64 = 16;
+// How often pending deliveries are checked, when there was nothing to send.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Delay before dispatcher reconnects to the database.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Longest delay between attempts, no matter how many of them failed.
+const MAX_RETRY_DELAY: u64 = 60 * 60;
+// How long resolving host of the webhook url may take.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+
+fn default_max_attempts() -> u32 { 8 }
+fn default_retry_delay() -> u64 { 10 }
+fn default_timeout() -> u64 { 10 }
+
+
+/// Webhook settings, read from Rocket config. Failed delivery is retried after `webhook_retry_delay`
+/// seconds, and the delay doubles with every attempt; after `webhook_max_attempts` attempts delivery
+/// is dead. Receiver must respond within `webhook_timeout` seconds. Webhooks can't be sent to
+/// loopback, private or link-local addresses (e.g. cloud metadata service), unless
+/// `webhook_allow_private` is enabled.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    #[serde(default = "default_max_attempts")]
+    pub webhook_max_attempts: u32,
+    #[serde(default = "default_retry_delay")]
+    pub webhook_retry_delay: u64,
+    #[serde(default = "default_timeout")]
+    pub webhook_timeout: u64,
+    #[serde(default)]
+    pub webhook_allow_private: bool,
+}
+
+
+impl WebhookConfig {
+    // Delay after the failed attempt with the number (starting from 1).
+    fn retry_delay(&self, attempt: u32) -> u64 {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        self.webhook_retry_delay.saturating_mul(factor).min(MAX_RETRY_DELAY)
+    }
+}
+
+
+/// Webhook of a namespace, which receives changes of its entries. Secret is never returned
+/// after the webhook is created.
+#[derive(Debug, Clone, Serialize)]
+pub struct Webhook {
+    pub id:         u64,
+    pub namespace:  String,
+    pub url:        String,
+    pub events:     Vec<ChangeKind>,
+    pub filter:     Value,
+    pub created_at: String,
+}
+
+
+/// Settings of a new webhook, as they are sent by the client.
+#[derive(Debug, Clone)]
+pub struct NewWebhook {
+    pub url:    String,
+    pub secret: Option<String>,
+    pub events: Vec<ChangeKind>,
+    pub filter: Value,
+}
+
+
+/// Record of the delivery log. Event is the change which is delivered.
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    pub id:              u64,
+    pub webhook_id:      u64,
+    pub event:           Value,
+    pub status:          String,
+    pub attempts:        u32,
+    /// Time of the next attempt, if delivery is still pending.
+    pub next_attempt_at: Option<String>,
+    /// HTTP status of the last response, if there was one.
+    pub last_status:     Option<u16>,
+    pub last_error:      Option<String>,
+    pub created_at:      String,
+    pub delivered_at:    Option<String>,
+}
+
+
+// Statuses of deliveries.
+static STATUSES: &[&str] = &["pending", "delivered", "dead"];
+
+
+impl NewWebhook {
+    /// Parses webhook from the request body, e.g. {"url": "https://example.com/hook", "secret": "...",
+    /// "events": ["created"], "filter": {"status": {"eq": "failed"}}}. Only url is required. On
+    /// failure returns an error message which is ready to be sent to the client.
+    pub fn parse(value: &Value) -> Result<NewWebhook, JsonValue> {
+        let url = match value.get("url").and_then(|url| url.as_str()) {
+            Some(url) if url.parse::<hyper::Uri>().is_ok() && (url.starts_with("http://") || url.starts_with("https://")) => url.to_string(),
+            _ => return Err(json!({
+                "code":    "err_webhook_url",
+                "message": "Webhook must have a 'url' which is an absolute http or https URL!",
+            })),
+        };
+
+        let secret = match value.get("secret") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(secret)) if secret.len() >= 16 => Some(secret.clone()),
+            Some(_) => return Err(json!({
+                "code":    "err_webhook_secret",
+                "message": "Webhook 'secret' must be a string of at least 16 characters!",
+            })),
+        };
+
+        let events = match value.get("events") {
+            None | Some(Value::Null) => vec![ChangeKind::Created, ChangeKind::Updated, ChangeKind::Deleted],
+            Some(events) => match serde_json::from_value::<Vec<ChangeKind>>(events.clone()) {
+                Ok(events) if !events.is_empty() => events,
+                _ => return Err(json!({
+                    "code":    "err_webhook_events",
+                    "message": "Webhook 'events' must be a non-empty list of: created, updated, deleted!",
+                })),
+            },
+        };
+
+        let filter = match value.get("filter") {
+            None | Some(Value::Null) => json!({}).0,
+            Some(filter) => {
+                Filter::from_value(filter)?;
+                filter.clone()
+            },
+        };
+
+        Ok(NewWebhook { url, secret, events, filter })
+    }
+}
+
+
+impl Webhook {
+    fn from_row(row: &postgres::Row) -> Result<Webhook, ApiError> {
+        let malformed = |e: postgres::Error| ApiError::Malformed(e.to_string());
+        Ok(Webhook {
+            id: row.try_get::<_, i64>("id").map_err(malformed)? as u64,
+            namespace: row.try_get("namespace").map_err(malformed)?,
+            url: row.try_get("url").map_err(malformed)?,
+            events: serde_json::from_value(row.try_get::<_, Value>("events").map_err(malformed)?)
+                .map_err(|e| ApiError::Malformed(e.to_string()))?,
+            filter: row.try_get("filter").map_err(malformed)?,
+            created_at: format_timestamp(row.try_get::<_, OffsetDateTime>("created_at").map_err(malformed)?),
+        })
+    }
+
+    /// Creates webhook of the namespace. Returns stored webhook together with its secret, which
+    /// is generated if it wasn't provided.
+    pub fn create(c: &mut postgres::Client, namespace: &str, new: NewWebhook) -> Result<(Webhook, String), ApiError> {
+        let secret = new.secret.unwrap_or_else(|| base64::encode_config(rand::random::<[u8; 32]>(), base64::URL_SAFE_NO_PAD));
+        let events = serde_json::to_value(&new.events).map_err(|e| ApiError::Malformed(e.to_string()))?;
+
+        let row = c.query_one(
+            "INSERT INTO webhooks (namespace, url, secret, events, filter) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+            &[&namespace, &new.url, &secret, &events, &new.filter]
+        )?;
+        Ok((Self::from_row(&row)?, secret))
+    }
+
+    pub fn list(c: &mut postgres::Client, namespace: &str) -> Result<Vec<Webhook>, ApiError> {
+        c.query("SELECT * FROM webhooks WHERE namespace = $1 ORDER BY id ASC", &[&namespace])?
+            .iter()
+            .map(Self::from_row)
+            .collect()
+    }
+
+    /// Deletes the webhook together with its deliveries, including pending ones.
+    pub fn delete(c: &mut postgres::Client, id: u64, namespace: &str) -> Result<Webhook, ApiError> {
+        match c.query_opt(
+            "DELETE FROM webhooks WHERE id = $1 AND namespace = $2 RETURNING *",
+            &[&(id as i64), &namespace]
+        )? {
+            Some(row) => Self::from_row(&row),
+            None => Err(ApiError::WebhookNotFound(id)),
+        }
+    }
+
+    /// Returns page of the delivery log of the webhook, from the newest delivery, optionally only
+    /// with the status. Second value is true if there are more pages.
+    pub fn deliveries(c: &mut postgres::Client, id: u64, namespace: &str, status: Option<&str>, page: u32, page_size: u16) -> Result<(Vec<Delivery>, bool), ApiError> {
+        if c.query_opt("SELECT 1 FROM webhooks WHERE id = $1 AND namespace = $2", &[&(id as i64), &namespace])?.is_none() {
+            return Err(ApiError::WebhookNotFound(id));
+        }
+
+        // One more row is read, to know whether there is a next page.
+        let mut rows = c.query(
+            "SELECT * FROM webhook_deliveries WHERE webhook_id = $1 AND ($2::text IS NULL OR status = $2) \
+            ORDER BY id DESC LIMIT $3 OFFSET $4",
+            &[&(id as i64), &status, &(page_size as i64 + 1), &(page as i64 * page_size as i64)]
+        )?;
+        let has_next = rows.len() > page_size as usize;
+        rows.truncate(page_size as usize);
+
+        let malformed = |e: postgres::Error| ApiError::Malformed(e.to_string());
+        let deliveries = rows.iter().map(|row| {
+            let status = row.try_get::<_, String>("status").map_err(malformed)?;
+            Ok(Delivery {
+                id: row.try_get::<_, i64>("id").map_err(malformed)? as u64,
+                webhook_id: row.try_get::<_, i64>("webhook_id").map_err(malformed)? as u64,
+                event: row.try_get("event").map_err(malformed)?,
+                attempts: row.try_get::<_, i32>("attempts").map_err(malformed)? as u32,
+                next_attempt_at: match status.as_str() {
+                    "pending" => Some(format_timestamp(row.try_get::<_, OffsetDateTime>("next_attempt_at").map_err(malformed)?)),
+                    _ => None,
+                },
+                last_status: row.try_get::<_, Option<i32>>("last_status").map_err(malformed)?.map(|s| s as u16),
+                last_error: row.try_get("last_error").map_err(malformed)?,
+                created_at: format_timestamp(row.try_get::<_, OffsetDateTime>("created_at").map_err(malformed)?),
+                delivered_at: row.try_get::<_, Option<OffsetDateTime>>("delivered_at").map_err(malformed)?.map(format_timestamp),
+                status,
+            })
+        }).collect::<Result<Vec<Delivery>, ApiError>>()?;
+
+        Ok((deliveries, has_next))
+    }
+
+    /// Checks that status of deliveries is known.
+    pub fn is_status(status: &str) -> bool {
+        STATUSES.contains(&status)
+    }
+}
+
+
+// Returns true if the address is reachable from the internet, so it's safe to send requests to it
+// from inside of the network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+                || ip.is_broadcast() || ip.is_multicast() || ip.is_documentation()
+                // "This network" and shared address space (carrier-grade NAT).
+                || a == 0 || (a == 100 && (64..128).contains(&b)))
+        },
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            match segments {
+                // IPv4-mapped address.
+                [0, 0, 0, 0, 0, 0xffff, high, low] => is_public(IpAddr::V4(Ipv4Addr::from(((high as u32) << 16) | low as u32))),
+                _ => !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast()
+                    // Unique local (fc00::/7) and link-local (fe80::/10) addresses.
+                    || (segments[0] & 0xfe00) == 0xfc00 || (segments[0] & 0xffc0) == 0xfe80),
+            }
+        },
+    }
+}
+
+
+// Resolves the host and checks that all of its addresses are public (unless private ones are
+// allowed). On failure returns the reason.
+async fn resolve(host: &str, port: u16, allow_private: bool) -> Result<Vec<SocketAddr>, String> {
+    let addresses = match timeout(RESOLVE_TIMEOUT, lookup_host((host, port))).await {
+        Ok(Ok(addresses)) => addresses.collect::<Vec<_>>(),
+        Ok(Err(e)) => return Err(format!("Couldn't resolve host '{}' with error: '{}'!", host, e)),
+        Err(_) => return Err(format!("Host '{}' wasn't resolved in {} seconds!", host, RESOLVE_TIMEOUT.as_secs())),
+    };
+    match addresses.iter().find(|address| !allow_private && !is_public(address.ip())) {
+        _ if addresses.is_empty() => Err(format!("Host '{}' has no addresses!", host)),
+        Some(address) => Err(format!("Host '{}' resolves to non-public address '{}'!", host, address.ip())),
+        None => Ok(addresses),
+    }
+}
+
+
+/// Resolves host of the webhook url and checks that all of its addresses are public (unless private
+/// ones are allowed). It's checked when webhook is created and before every delivery (IP addresses
+/// in urls are only checked here), while the HTTP client checks addresses it connects to on its own
+/// (see `PublicResolver`). On failure returns the reason.
+pub async fn check_address(url: &str, allow_private: bool) -> Result<(), String> {
+    let uri = url.parse::<hyper::Uri>().map_err(|e| format!("Couldn't parse url with error: '{}'!", e))?;
+    let host = uri.host().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+    if allow_private {
+        return Ok(());
+    }
+    resolve(host, port, false).await.map(|_| ())
+}
+
+
+// DNS resolver of the HTTP client, which fails for hosts with non-public addresses (unless they
+// are allowed). Addresses are checked when the connection is made, so DNS record which changes
+// after `check_address` (e.g. to an internal address) can't redirect the request.
+#[derive(Clone)]
+pub(crate) struct PublicResolver {
+    pub(crate) allow_private: bool,
+}
+
+
+impl hyper::service::Service<Name> for PublicResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, io::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let allow_private = self.allow_private;
+        Box::pin(async move {
+            // Port is set by the connector.
+            resolve(name.as_str(), 0, allow_private).await
+                .map(|addresses| addresses.into_iter())
+                .map_err(|reason| io::Error::new(io::ErrorKind::Other, reason))
+        })
+    }
+}
+
+
+/// Signs payload of the delivery: HMAC-SHA256 of "<timestamp>.<body>" with the secret of the
+/// webhook, as a hex string. It's sent as "X-Voyeur-Signature: sha256=<signature>", together with
+/// "X-Voyeur-Timestamp", so receivers can also reject replayed requests.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+
+// Claimed delivery, with everything that is needed to send it.
+struct Claimed {
+    id:         i64,
+    webhook_id: i64,
+    attempt:    i32,
+    event:      Value,
+    url:        String,
+    secret:     String,
+    filter:     Value,
+}
+
+
+// Outcome of a single attempt.
+enum Attempt {
+    Delivered(u16),
+    Failed(Option<u16>, String),
+    // Content doesn't match the filter of the webhook, so nothing is sent.
+    Skipped,
+}
+
+
+type HttpClient = hyper::Client<HttpsConnector<HttpConnector<PublicResolver>>>;
+
+
+// Builds HTTP client the same way as `HttpsConnector::with_native_roots`, but with our resolver.
+fn http_client(config: &WebhookConfig) -> Result<HttpClient, String> {
+    let mut http = HttpConnector::new_with_resolver(PublicResolver { allow_private: config.webhook_allow_private });
+    http.enforce_http(false);
+
+    let mut tls = rustls::ClientConfig::new();
+    tls.root_store = match rustls_native_certs::load_native_certs() {
+        Ok(store) => store,
+        // Some of the certificates couldn't be loaded, the rest are still fine.
+        Err((Some(store), _)) => store,
+        Err((None, e)) => return Err(format!("Couldn't load root certificates with error: '{}'!", e)),
+    };
+    Ok(hyper::Client::builder().build(HttpsConnector::from((http, tls))))
+}
+
+
+// Sends the delivery and returns the outcome.
+async fn send(http: &HttpClient, config: &WebhookConfig, delivery: &Claimed) -> Attempt {
+    let filter = Filter::from_value(&delivery.filter).unwrap_or_default();
+    if let Some(content) = delivery.event.get("content") {
+        if !filter.matches(content) {
+            return Attempt::Skipped;
+        }
+    }
+
+    let body = json!({
+        "delivery_id": delivery.id,
+        "webhook_id":  delivery.webhook_id,
+        "attempt":     delivery.attempt,
+        "event":       delivery.event,
+    }).to_string();
+    let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+    let kind = delivery.event.get("kind").and_then(|kind| kind.as_str()).unwrap_or_default().to_string();
+
+    if let Err(reason) = check_address(&delivery.url, config.webhook_allow_private).await {
+        return Attempt::Failed(None, reason);
+    }
+
+    let request = hyper::Request::post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", concat!("voyeur/", env!("CARGO_PKG_VERSION")))
+        .header("X-Voyeur-Delivery", delivery.id.to_string())
+        .header("X-Voyeur-Event", kind)
+        .header("X-Voyeur-Timestamp", timestamp.to_string())
+        .header("X-Voyeur-Signature", format!("sha256={}", sign(&delivery.secret, timestamp, &body)))
+        .body(hyper::Body::from(body));
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return Attempt::Failed(None, format!("Couldn't build request with error: '{}'!", e)),
+    };
+
+    match timeout(Duration::from_secs(config.webhook_timeout), http.request(request)).await {
+        Ok(Ok(response)) if response.status().is_success() => Attempt::Delivered(response.status().as_u16()),
+        // Body of the response is not kept, so receivers can't be used to read other services.
+        Ok(Ok(response)) => {
+            let status = response.status();
+            let reason = status.canonical_reason().unwrap_or("Unknown");
+            Attempt::Failed(Some(status.as_u16()), format!("Receiver responded with status {} ({})!", status.as_u16(), reason))
+        },
+        Ok(Err(e)) => Attempt::Failed(None, format!("Request failed with error: '{}'!", e)),
+        Err(_) => Attempt::Failed(None, format!("Receiver didn't respond in {} seconds!", config.webhook_timeout)),
+    }
+}
+
+
+// Claims due deliveries, sends them and records the outcomes. Claimed deliveries are postponed
+// for longer than the request may take, so other dispatchers skip them meanwhile, and if this one
+// dies, they are retried. Returns amount of claimed deliveries.
+fn dispatch_due(c: &mut postgres::Client, rt: &runtime::Runtime, http: &HttpClient, config: &WebhookConfig) -> Result<usize, postgres::Error> {
+    let lease = (config.webhook_timeout * 2 + RESOLVE_TIMEOUT.as_secs() + 10) as f64;
+    let claimed = c.query(
+        "UPDATE webhook_deliveries d SET attempts = d.attempts + 1, next_attempt_at = now() + make_interval(secs => $1) \
+        FROM webhooks w \
+        WHERE w.id = d.webhook_id AND d.id IN ( \
+            SELECT id FROM webhook_deliveries WHERE status = 'pending' AND next_attempt_at <= now() \
+            ORDER BY id ASC LIMIT $2 FOR UPDATE SKIP LOCKED \
+        ) \
+        RETURNING d.id, d.webhook_id, d.attempts, d.event, w.url, w.secret, w.filter",
+        &[&lease, &DISPATCH_BATCH_SIZE]
+    )?
+    .iter()
+    .map(|row| Claimed {
+        id:         row.get("id"),
+        webhook_id: row.get("webhook_id"),
+        attempt:    row.get("attempts"),
+        event:      row.get("event"),
+        url:        row.get("url"),
+        secret:     row.get("secret"),
+        filter:     row.get("filter"),
+    })
+    .collect::<Vec<Claimed>>();
+
+    let attempts = rt.block_on(join_all(claimed.iter().map(|delivery| send(http, config, delivery))));
+
+    for (delivery, attempt) in claimed.iter().zip(attempts) {
+        match attempt {
+            Attempt::Delivered(status) => c.execute(
+                "UPDATE webhook_deliveries SET status = 'delivered', delivered_at = now(), last_status = $2, last_error = NULL \
+                WHERE id = $1",
+                &[&delivery.id, &(status as i32)]
+            )?,
+            Attempt::Failed(status, error) => {
+                let dead = delivery.attempt as u32 >= config.webhook_max_attempts;
+                c.execute(
+                    "UPDATE webhook_deliveries SET status = $2, last_status = $3, last_error = $4, \
+                    next_attempt_at = now() + make_interval(secs => $5) WHERE id = $1",
+                    &[
+                        &delivery.id, &if dead { "dead" } else { "pending" }, &status.map(|s| s as i32), &error,
+                        &(config.retry_delay(delivery.attempt as u32) as f64),
+                    ]
+                )?
+            },
+            Attempt::Skipped => c.execute("DELETE FROM webhook_deliveries WHERE id = $1", &[&delivery.id])?,
+        };
+    }
+
+    Ok(claimed.len())
+}
+
+
+// Runs in its own thread with its own connection and HTTP client, until Rocket instance is gone.
+fn dispatch(url: String, config: WebhookConfig, alive: Weak<()>) {
+    let rt = match runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("Failed to start webhook dispatcher with error: '{}'!", e);
+            return;
+        }
+    };
+    let http = match http_client(&config) {
+        Ok(http) => http,
+        Err(e) => {
+            eprintln!("Failed to start webhook dispatcher with error: {}", e);
+            return;
+        }
+    };
+
+    let mut client: Option<postgres::Client> = None;
+    while alive.upgrade().is_some() {
+        if client.is_none() {
+            match postgres::Client::connect(&url, postgres::NoTls) {
+                Ok(c) => client = Some(c),
+                Err(e) => {
+                    eprintln!("Failed to connect webhook dispatcher with error: '{}'!", e);
+                    thread::sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(c) = client.as_mut() {
+            match dispatch_due(c, &rt, &http, &config) {
+                // There may be more of them.
+                Ok(n) if n as i64 == DISPATCH_BATCH_SIZE => (),
+                Ok(_) => thread::sleep(POLL_INTERVAL),
+                Err(e) => {
+                    eprintln!("Webhook dispatcher failed with error: '{}'!", e);
+                    client = None;
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+}
+
+
+// Managed state which keeps dispatcher running, while Rocket instance exists.
+struct Dispatcher(#[allow(dead_code)] Arc<()>);
+
+
+/// Fairing which reads webhook settings and starts the dispatcher of deliveries. Every replica
+/// runs one, and they share the queue.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Webhooks", |rocket| async {
+        let config = match rocket.figment().extract::<WebhookConfig>() {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Failed to read webhooks config with error: '{}'!", e);
+                return Err(rocket);
+            }
+        };
+        let url = match rocket.figment().extract_inner::<String>("databases.storage.url") {
+            Ok(url) => url,
+            Err(e) => {
+                eprintln!("Failed to read database url for webhooks with error: '{}'!", e);
+                return Err(rocket);
+            }
+        };
+
+        let alive = Arc::new(());
+        let weak = Arc::downgrade(&alive);
+        let dispatcher_config = config.clone();
+        thread::spawn(move || dispatch(url, dispatcher_config, weak));
+        Ok(rocket.manage(Dispatcher(alive)).manage(config))
+    })
+}
+
+
+// Allows a route to read webhook settings.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebhookConfig {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        Outcome::Success(req.rocket().state::<WebhookConfig>().expect("Webhooks fairing is not attached!").clone())
+    }
+}
@@ -0,0 +1,120 @@
+use crate::auth::{Scoped, Admin};
+use crate::responders::CustomResponder;
+use crate::webhook::{self, Webhook, NewWebhook, WebhookConfig};
+use crate::model::{ApiDatabase, Entry};
+use crate::namespace::Namespace;
+use crate::pagination::PageSize;
+
+
+/// This endpoint is used to create a webhook, which receives changes of entries in the namespace.
+/// Body of the request must be a JSON object with the url of the receiver (http or https), and
+/// optionally its secret (<String> of at least 16 characters, generated if it's missing), kinds of
+/// changes (list of "created", "updated" and "deleted", all of them by default) and a structured
+/// filter (same as "X-Filter" of entries, deletions are sent regardless of it), example:
+/// {"url": "https://example.com/hook", "events": ["created"], "filter": {"status": {"eq": "failed"}}}.
+/// Every change is sent as a POST request with JSON body {"delivery_id", "webhook_id", "attempt",
+/// "event"}, signed with "X-Voyeur-Signature: sha256=<hex HMAC-SHA256 of '<timestamp>.<body>'>", where
+/// timestamp is sent in "X-Voyeur-Timestamp". Failed deliveries are retried with exponential backoff.
+/// Host of the url must resolve to public addresses only (loopback, private and link-local ones
+/// are refused, unless "webhook_allow_private" is enabled in the config), which is checked again
+/// before every delivery. Response contains the secret, which can't be received again. For this
+/// endpoint you must provide namespace (url argument <namespace> or header "X-Namespace", of type
+/// <String>), and a key with admin scope for it.
+#[post("/", format = "application/json", data = "<body>")]
+pub async fn create_webhook(namespace: Namespace, _key: Scoped<Admin>, config: WebhookConfig, body: Entry, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+    let new = match NewWebhook::parse(&body.0) {
+        Ok(new) => new,
+        Err(message) => return CustomResponder::BadRequest(message),
+    };
+    if let Err(reason) = webhook::check_address(&new.url, config.webhook_allow_private).await {
+        return CustomResponder::BadRequest(json!({
+            "code":    "err_webhook_address",
+            "message": format!("Webhook url must point to a public address! {}", reason),
+        }));
+    }
+
+    match conn.run(move |c| Webhook::create(c, &namespace.0, new)).await {
+        Ok((data, secret)) => CustomResponder::Ok(json!({
+            "code": "info_webhook_created",
+            "message": "Successfully created new webhook! Store its secret now, it can't be received again.",
+            "namespace": &namespace_copy,
+            "secret": secret,
+            "data": data,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to receive all webhooks of the namespace (secrets are never returned).
+/// For this endpoint you must provide namespace (url argument <namespace> or header "X-Namespace",
+/// of type <String>), and a key with admin scope for it.
+#[get("/")]
+pub async fn list_webhooks(namespace: Namespace, _key: Scoped<Admin>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Webhook::list(c, &namespace.0)).await {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "data": data,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to delete webhook by ID (url path /<id> of type unsigned 64-bit integer),
+/// together with its delivery log. Pending deliveries are not sent anymore. For this endpoint you
+/// must provide namespace (url argument <namespace> or header "X-Namespace", of type <String>), and
+/// a key with admin scope for it.
+#[delete("/<id>")]
+pub async fn delete_webhook(id: u64, namespace: Namespace, _key: Scoped<Admin>, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+
+    match conn.run(move |c| Webhook::delete(c, id, &namespace.0)).await {
+        Ok(data) => CustomResponder::Ok(json!({
+            "code": "info_webhook_deleted",
+            "message": format!("Successfully deleted webhook of ID '{}'!", id),
+            "namespace": &namespace_copy,
+            "data": data,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
+
+
+/// This endpoint is used to receive the delivery log of the webhook with certain ID (url path /<id>
+/// of type unsigned 64-bit integer), from the newest delivery. Delivery is "pending" until it's sent
+/// successfully ("delivered") or it has failed too many times ("dead"); the last error and status of
+/// the response are recorded. Optionally, you can specify status (url argument <status>), page (url
+/// argument <page>, of type unsigned 32-bit integer) and page size (url argument <page_size> or header
+/// "X-PAGE-SIZE"). For this endpoint you must provide namespace (url argument <namespace> or header
+/// "X-Namespace", of type <String>), and a key with admin scope for it.
+#[get("/<id>/deliveries?<status>&<page>")]
+pub async fn get_webhook_deliveries(id: u64, namespace: Namespace, _key: Scoped<Admin>, status: Option<String>, page: Option<u32>, page_size: PageSize, conn: ApiDatabase) -> CustomResponder {
+    let namespace_copy = namespace.0.clone();
+    let (page, size) = (page.unwrap_or(0), page_size.0);
+
+    if let Some(status) = status.as_deref().filter(|status| !Webhook::is_status(status)) {
+        return CustomResponder::BadRequest(json!({
+            "code": "err_webhook_delivery_status",
+            "message": format!("Unknown delivery status '{}', expected one of: pending, delivered, dead!", status),
+            "namespace": &namespace_copy,
+        }));
+    }
+
+    match conn.run(move |c| Webhook::deliveries(c, id, &namespace.0, status.as_deref(), page, size)).await {
+        Ok((data, has_next)) => CustomResponder::Ok(json!({
+            "code": "no_message",
+            "namespace": &namespace_copy,
+            "id": id,
+            "page_number": page,
+            "page_size": size,
+            "has_next": has_next,
+            "data": data,
+        })),
+        Err(e) => e.in_namespace(&namespace_copy),
+    }
+}
@@ -1,5 +0,0 @@
-CREATE TABLE entries (
-  id BIGSERIAL PRIMARY KEY,
-  namespace VARCHAR(64) NOT NULL,
-  content TEXT NOT NULL
-);